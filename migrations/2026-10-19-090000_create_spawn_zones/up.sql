@@ -0,0 +1,23 @@
+CREATE TABLE public.spawn_zones
+(
+    name character varying(64) COLLATE pg_catalog."default" NOT NULL,
+    affiliation integer,
+    q integer NOT NULL,
+    r integer NOT NULL,
+    radius integer NOT NULL DEFAULT 0,
+    CONSTRAINT spawn_zones_pkey PRIMARY KEY (name)
+)
+
+TABLESPACE pg_default;
+
+ALTER TABLE public.spawn_zones
+    OWNER to plexms;
+
+GRANT ALL ON TABLE public.spawn_zones TO ares;
+
+GRANT TRIGGER, SELECT, REFERENCES ON TABLE public.spawn_zones TO ares_api;
+
+GRANT ALL ON TABLE public.spawn_zones TO plexms;
+
+COMMENT ON TABLE public.spawn_zones
+    IS 'Areas where robots of an affiliation spawn';
@@ -0,0 +1,2 @@
+ALTER TABLE public.robot_modules
+    DROP COLUMN m_comms;
@@ -0,0 +1,2 @@
+ALTER TABLE public.robot_modules
+    ADD COLUMN m_comms character varying NOT NULL default 'none';
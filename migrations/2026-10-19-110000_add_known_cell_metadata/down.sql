@@ -0,0 +1,8 @@
+ALTER TABLE public.robot_known_cells
+    DROP COLUMN last_used;
+
+ALTER TABLE public.robot_known_cells
+    DROP COLUMN confidence;
+
+ALTER TABLE public.robot_known_cells
+    DROP COLUMN on_route;
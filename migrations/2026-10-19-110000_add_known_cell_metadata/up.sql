@@ -0,0 +1,8 @@
+ALTER TABLE public.robot_known_cells
+    ADD COLUMN last_used timestamp without time zone NOT NULL default now();
+
+ALTER TABLE public.robot_known_cells
+    ADD COLUMN confidence INTEGER NOT NULL default 100;
+
+ALTER TABLE public.robot_known_cells
+    ADD COLUMN on_route BOOLEAN NOT NULL default false;
@@ -0,0 +1,2 @@
+ALTER TABLE public.robots
+    DROP COLUMN behavior;
@@ -0,0 +1,2 @@
+ALTER TABLE public.robots
+    ADD COLUMN behavior character varying NOT NULL default 'standard';
@@ -0,0 +1,22 @@
+CREATE TABLE public.robot_scripts
+(
+    name character varying(64) COLLATE pg_catalog."default" NOT NULL,
+    api_version integer NOT NULL DEFAULT 1,
+    fuel bigint NOT NULL DEFAULT 10000,
+    source text COLLATE pg_catalog."default" NOT NULL,
+    CONSTRAINT robot_scripts_pkey PRIMARY KEY (name)
+)
+
+TABLESPACE pg_default;
+
+ALTER TABLE public.robot_scripts
+    OWNER to plexms;
+
+GRANT ALL ON TABLE public.robot_scripts TO ares;
+
+GRANT TRIGGER, SELECT, REFERENCES ON TABLE public.robot_scripts TO ares_api;
+
+GRANT ALL ON TABLE public.robot_scripts TO plexms;
+
+COMMENT ON TABLE public.robot_scripts
+    IS 'User written scripts that drive robots';
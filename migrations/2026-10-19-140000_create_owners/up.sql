@@ -0,0 +1,22 @@
+CREATE TABLE public.owners
+(
+    id SERIAL NOT NULL,
+    name character varying(64) COLLATE pg_catalog."default" NOT NULL,
+    token character varying(64) COLLATE pg_catalog."default" NOT NULL,
+    CONSTRAINT owners_pkey PRIMARY KEY (id),
+    CONSTRAINT owners_name_key UNIQUE (name)
+)
+
+TABLESPACE pg_default;
+
+ALTER TABLE public.owners
+    OWNER to plexms;
+
+GRANT ALL ON TABLE public.owners TO ares;
+
+GRANT ALL ON TABLE public.owners TO plexms;
+
+GRANT ALL ON SEQUENCE public.owners_id_seq TO ares;
+
+COMMENT ON TABLE public.owners
+    IS 'Players who can drive their robots with a remote controller';
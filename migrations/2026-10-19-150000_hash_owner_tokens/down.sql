@@ -0,0 +1,3 @@
+-- the tokens can't be recovered from their hashes; owners need new ones
+ALTER TABLE public.owners
+    RENAME COLUMN token_hash TO token;
@@ -0,0 +1,4 @@
+ALTER TABLE public.owners
+    RENAME COLUMN token TO token_hash;
+UPDATE public.owners
+    SET token_hash = encode(sha256(token_hash::bytea), 'hex');
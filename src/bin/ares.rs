@@ -1,11 +1,13 @@
-use ares::server;
+use ares::{logging, server};
+use tracing::info;
 
 fn main() {
     let config = server::get_config();
+    logging::init(&config.log);
     let mut server = server::Server::new(config);
 
     ctrlc::set_handler(move || {
-        println!("Signal for shutdown");
+        info!("Signal for shutdown");
         std::process::exit(0);
     })
     .expect("Error setting Ctrl-C handler");
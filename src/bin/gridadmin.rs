@@ -1,49 +1,1041 @@
-use clap::{Arg, App};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
-use ares::grid::Grid;
 use ares::db;
+use ares::grid::*;
+use ares::logging::{self, LogConfig};
+use ares::owner::Owner;
+use ares::robot::{
+    Robot, RobotData, RobotKnownCell, RobotModules, ScriptBehavior, ScriptSource, BEHAVIORS,
+    SCRIPT_PREFIX,
+};
+use ares::schema::*;
+use ares::server::protocol;
+use ares::valuable::Valuable;
+
+/// Largest radius we are willing to draw in the terminal
+const MAX_TEXT_RADIUS: i32 = 25;
 
 fn main() {
-    let matches = App::new("Ares Grid Admin")
+    logging::init(&LogConfig::default());
+    let matches = db::add_db_args(App::new("Ares Grid Admin"))
         .version("0.1.0")
         .about("Create/maintain grids")
-        .arg(Arg::with_name("dbuser")
-            .short("u")
-            .long("user")
-            .takes_value(true)
-            .help("Database username"))
-        .arg(Arg::with_name("dbpw")
-            .short("p")
-            .long("password")
-            .takes_value(true)
-            .help("Database password"))
-        .arg(Arg::with_name("dbhost")
-            .short("o")
-            .long("hostname")
-            .takes_value(true)
-            .help("Database hostname"))
-        .arg(Arg::with_name("db")
-            .short("n")
-            .long("dbname")
-            .takes_value(true)
-            .help("Database name"))
-        .arg(Arg::with_name("size")
-            .required(true)
-            .takes_value(true)
-            .help("Grid radius size"))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .global_setting(AppSettings::AllowNegativeNumbers)
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about("Destructively regenerate the grid")
+                .arg(
+                    Arg::with_name("size")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Grid radius size"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Draw the grid as SVG or text")
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["svg", "ascii", "unicode"])
+                        .default_value("svg")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .help("File to write to instead of stdout"),
+                )
+                .arg(
+                    Arg::with_name("robots")
+                        .long("robots")
+                        .help("Draw the robots"),
+                )
+                .arg(
+                    Arg::with_name("valuables")
+                        .long("valuables")
+                        .help("Draw the valuables"),
+                )
+                .arg(
+                    Arg::with_name("known")
+                        .long("known")
+                        .takes_value(true)
+                        .help("Highlight the cells known by this robot id"),
+                )
+                .arg(
+                    Arg::with_name("preview")
+                        .long("preview")
+                        .takes_value(true)
+                        .conflicts_with_all(&["robots", "valuables", "known"])
+                        .help("Render a newly generated grid of this radius without touching the database"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cell")
+                .about("Show a cell and what is on it")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r")),
+        )
+        .subcommand(SubCommand::with_name("robots").about("List all robots"))
+        .subcommand(
+            SubCommand::with_name("delete-robot")
+                .about("Delete a robot along with its modules and memory")
+                .arg(id_arg("robot_id")),
+        )
+        .subcommand(
+            SubCommand::with_name("spawn")
+                .about("Spawn a robot at the given coordinates")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(
+                    Arg::with_name("orientation")
+                        .long("orientation")
+                        .takes_value(true)
+                        .possible_values(&["0", "60", "120", "180", "240", "300"])
+                        .help("Direction the robot faces"),
+                )
+                .arg(
+                    Arg::with_name("affiliation")
+                        .long("affiliation")
+                        .takes_value(true)
+                        .help("Team the robot belongs to"),
+                )
+                .arg(
+                    Arg::with_name("behavior")
+                        .long("behavior")
+                        .takes_value(true)
+                        .validator(validate_behavior)
+                        .help("Personality that decides what the robot does, or script:<name>"),
+                )
+                .arg(
+                    Arg::with_name("owner")
+                        .long("owner")
+                        .takes_value(true)
+                        .help("Owner whose remote controller may drive the robot"),
+                )
+                .args(&module_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("place-valuable")
+                .about("Place a valuable or add to the one already there")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(
+                    Arg::with_name("amount")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Amount of valuables"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-valuable")
+                .about("Remove a valuable")
+                .arg(id_arg("valuable_id")),
+        )
+        .subcommand(
+            SubCommand::with_name("reset-memory")
+                .about("Forget all the cells known by a robot")
+                .arg(id_arg("robot_id")),
+        )
+        .subcommand(SubCommand::with_name("stats").about("Print world statistics"))
+        .subcommand(
+            SubCommand::with_name("grow")
+                .about("Add rings to the grid, keeping robots and valuables")
+                .arg(
+                    Arg::with_name("size")
+                        .required(true)
+                        .takes_value(true)
+                        .help("New grid radius size"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("carve")
+                .about("Open the wall on one side of a cell")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(dir_arg("dir")),
+        )
+        .subcommand(
+            SubCommand::with_name("seal")
+                .about("Put a wall on one side of a cell")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(dir_arg("dir")),
+        )
+        .subcommand(
+            SubCommand::with_name("stamp")
+                .about("Stamp a room template centered on a cell")
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(
+                    Arg::with_name("template")
+                        .required(true)
+                        .takes_value(true)
+                        .possible_values(&["hexagon", "corridor", "cross"]),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .takes_value(true)
+                        .default_value("2")
+                        .help("Radius of a hexagon or half length of a corridor"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .takes_value(true)
+                        .possible_values(&["0", "60", "120", "180", "240", "300"])
+                        .default_value("0")
+                        .help("Direction of a corridor"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("zones").about("List the spawn zones"))
+        .subcommand(
+            SubCommand::with_name("add-zone")
+                .about("Add or replace a spawn zone")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the zone"),
+                )
+                .arg(coord_arg("q"))
+                .arg(coord_arg("r"))
+                .arg(
+                    Arg::with_name("radius")
+                        .long("radius")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Zone radius around the center cell"),
+                )
+                .arg(
+                    Arg::with_name("affiliation")
+                        .long("affiliation")
+                        .takes_value(true)
+                        .help("Team that spawns here; shared by teams without a zone if not set"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-zone")
+                .about("Remove a spawn zone")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the zone"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("owners").about("List the robot owners"))
+        .subcommand(
+            SubCommand::with_name("add-owner")
+                .about("Add a robot owner and print their controller token")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the owner"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-owner")
+                .about("Remove a robot owner; their robots go back to driving themselves")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the owner"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("scripts").about("List the robot scripts"))
+        .subcommand(
+            SubCommand::with_name("add-script")
+                .about("Add or replace a robot script")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the script"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .takes_value(true)
+                        .help("File holding the script source"),
+                )
+                .arg(
+                    Arg::with_name("fuel")
+                        .long("fuel")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .help("Operations the script may run each tick"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-script")
+                .about("Remove a robot script")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of the script"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("protocol-schema")
+                .about("Print the JSON schema of the websocket protocol"),
+        )
         .get_matches();
 
-    let dbuser = matches.value_of("dbuser").unwrap_or("ares").to_string();
-    let dbpw = matches.value_of("dbpw").unwrap_or("ares").to_string();
-    let dbhost = matches.value_of("dbhost").unwrap_or("localhost").to_string();
-    let dbname = matches.value_of("db").unwrap_or("ares").to_string();
+    let dbconfig = db::DbConfig::from_matches(&matches);
+
+    match matches.subcommand() {
+        ("generate", Some(sub)) => generate(&dbconfig, sub),
+        ("render", Some(sub)) => render(&dbconfig, sub),
+        ("cell", Some(sub)) => inspect_cell(&dbconfig, sub),
+        ("robots", Some(_)) => list_robots(&dbconfig),
+        ("delete-robot", Some(sub)) => delete_robot(&dbconfig, sub),
+        ("spawn", Some(sub)) => spawn_robot(&dbconfig, sub),
+        ("place-valuable", Some(sub)) => place_valuable(&dbconfig, sub),
+        ("remove-valuable", Some(sub)) => remove_valuable(&dbconfig, sub),
+        ("reset-memory", Some(sub)) => reset_memory(&dbconfig, sub),
+        ("stats", Some(_)) => print_stats(&dbconfig),
+        ("grow", Some(sub)) => grow(&dbconfig, sub),
+        ("carve", Some(sub)) => set_edge(&dbconfig, sub, EdgeType::Open),
+        ("seal", Some(sub)) => set_edge(&dbconfig, sub, EdgeType::Wall),
+        ("stamp", Some(sub)) => stamp(&dbconfig, sub),
+        ("zones", Some(_)) => list_zones(&dbconfig),
+        ("add-zone", Some(sub)) => add_zone(&dbconfig, sub),
+        ("remove-zone", Some(sub)) => remove_zone(&dbconfig, sub),
+        ("owners", Some(_)) => list_owners(&dbconfig),
+        ("add-owner", Some(sub)) => add_owner(&dbconfig, sub),
+        ("remove-owner", Some(sub)) => remove_owner(&dbconfig, sub),
+        ("scripts", Some(_)) => list_scripts(&dbconfig),
+        ("add-script", Some(sub)) => add_script(&dbconfig, sub),
+        ("remove-script", Some(sub)) => remove_script(&dbconfig, sub),
+        ("protocol-schema", Some(_)) => print_protocol_schema(),
+        _ => unreachable!(),
+    }
+}
+
+/// Behaviors are either built in or a script:<name>
+fn validate_behavior(behavior: String) -> Result<(), String> {
+    if BEHAVIORS.contains(&behavior.as_str()) || behavior.starts_with(SCRIPT_PREFIX) {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected one of {} or {}<name>",
+            BEHAVIORS.join(", "),
+            SCRIPT_PREFIX
+        ))
+    }
+}
+
+/// A required coordinate argument
+fn coord_arg(name: &str) -> Arg<'_, '_> {
+    Arg::with_name(name)
+        .required(true)
+        .takes_value(true)
+        .help("Axial coordinate")
+}
 
-    let size = matches.value_of("size").unwrap_or("100");
-    let size = size.parse::<u32>().expect("Could not parse size");
+/// A required direction argument
+fn dir_arg(name: &str) -> Arg<'_, '_> {
+    Arg::with_name(name)
+        .required(true)
+        .takes_value(true)
+        .possible_values(&["0", "60", "120", "180", "240", "300"])
+        .help("Side of the cell")
+}
+
+/// A required id argument
+fn id_arg(name: &str) -> Arg<'_, '_> {
+    Arg::with_name(name).required(true).takes_value(true)
+}
+
+/// Module names keyed the way `RobotModules::new` expects them
+const MODULE_KEYS: [(&str, &str); 9] = [
+    ("collector", "m_collector"),
+    ("comms", "m_comms"),
+    ("drivesystem", "m_drivesystem"),
+    ("exfilbeacon", "m_exfilbeacon"),
+    ("hull", "m_hull"),
+    ("memory", "m_memory"),
+    ("power", "m_power"),
+    ("scanner", "m_scanner"),
+    ("weapon", "m_weapon"),
+];
+
+/// An optional argument for each of the robot modules
+fn module_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    MODULE_KEYS
+        .iter()
+        .map(|(name, _)| {
+            Arg::with_name(name)
+                .long(name)
+                .takes_value(true)
+                .help("Module to load")
+        })
+        .collect()
+}
+
+/// Parse a value we know is present since clap required it
+fn parse_arg<T: std::str::FromStr>(matches: &ArgMatches, name: &str) -> T {
+    matches
+        .value_of(name)
+        .unwrap()
+        .parse::<T>()
+        .unwrap_or_else(|_| {
+            eprintln!("Could not parse {}", name);
+            std::process::exit(1);
+        })
+}
+
+/// Parse q and r into coordinates
+fn parse_coords(matches: &ArgMatches) -> Coords {
+    Coords {
+        q: parse_arg(matches, "q"),
+        r: parse_arg(matches, "r"),
+    }
+}
+
+/// Regenerate the grid; this deletes all robots and valuables
+fn generate(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let size: u32 = parse_arg(matches, "size");
+
+    let connection = db::establish_connection(dbconfig);
 
-    let dbconfig = db::DbConfig{dbuser, dbpw, dbhost, dbname};
-    let connection = db::establish_connection(&dbconfig);
-    
     let grid = Grid::new(size, Some(&connection)).unwrap();
     println!("Cells: {}", grid.cells.len())
 }
+
+/// Load what we need from the database and draw the grid
+fn render(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let mut overlay = RenderOverlay::default();
+
+    let grid = if let Some(size) = matches.value_of("preview") {
+        let size = size.parse::<u32>().expect("Could not parse preview size");
+        Grid::new(size, None).expect("Could not generate grid")
+    } else {
+        let connection = db::establish_connection(dbconfig);
+        load_overlay(&connection, matches, &mut overlay);
+        Grid::load(Some(&connection)).expect("Could not load grid")
+    };
+
+    let output = match matches.value_of("format").unwrap_or("svg") {
+        "svg" => render_svg(&grid.cells, &overlay),
+        format => {
+            let radius = grid.radius();
+            if radius > MAX_TEXT_RADIUS {
+                eprintln!(
+                    "Grid radius {} is too large to draw as text (max {}); use svg",
+                    radius, MAX_TEXT_RADIUS
+                );
+                std::process::exit(1);
+            }
+
+            let charset = if format == "unicode" {
+                Charset::Unicode
+            } else {
+                Charset::Ascii
+            };
+            render_text(&grid.cells, &overlay, charset)
+        }
+    };
+
+    match matches.value_of("output") {
+        Some(path) => std::fs::write(path, output).expect("Could not write output file"),
+        None => print!("{}", output),
+    }
+}
+
+/// Fill in the requested robots, valuables and known cells
+fn load_overlay(conn: &PgConnection, matches: &ArgMatches, overlay: &mut RenderOverlay) {
+    if matches.is_present("robots") {
+        overlay.robots = robots::table
+            .select((robots::q, robots::r, robots::orientation))
+            .load::<(i32, i32, Dir)>(conn)
+            .expect("Could not load robots")
+            .into_iter()
+            .map(|(q, r, dir)| CoordsAndDir {
+                coords: Coords { q, r },
+                dir,
+            })
+            .collect();
+    }
+
+    if matches.is_present("valuables") {
+        overlay.valuables = Valuable::load_all(Some(conn))
+            .expect("Could not load valuables")
+            .values()
+            .map(|v| Coords { q: v.q, r: v.r })
+            .collect();
+    }
+
+    if let Some(robot_id) = matches.value_of("known") {
+        let robot_id = robot_id.parse::<i64>().expect("Could not parse robot id");
+        let known_cells: HashSet<Coords> = RobotKnownCell::load_all(Some(conn), robot_id)
+            .expect("Could not load known cells")
+            .iter()
+            .map(|c| Coords { q: c.q, r: c.r })
+            .collect();
+        overlay.known_cells = Some(known_cells);
+    }
+}
+
+/// Print the cell details along with any robot or valuable on it
+fn inspect_cell(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let coords = parse_coords(matches);
+    let conn = db::establish_connection(dbconfig);
+
+    let cell = gridcells::table
+        .filter(gridcells::q.eq(coords.q))
+        .filter(gridcells::r.eq(coords.r))
+        .get_result::<GridCell>(&conn)
+        .optional()
+        .expect("Could not load cell");
+
+    let cell = match cell {
+        Some(cell) => cell,
+        None => {
+            println!("No cell at {},{}", coords.q, coords.r);
+            return;
+        }
+    };
+
+    println!("Cell {} at {},{}", cell.id, cell.q, cell.r);
+    for dir in Dir::get_vec() {
+        let angle: i32 = dir.into();
+        println!("  edge{:<3} {:?}", angle, cell.get_side(dir));
+    }
+
+    let robots_here = robots::table
+        .filter(robots::q.eq(coords.q))
+        .filter(robots::r.eq(coords.r))
+        .load::<RobotData>(&conn)
+        .expect("Could not load robots");
+    for robot in robots_here {
+        println!("  robot {} ({})", robot.id, robot.name);
+    }
+
+    let valuables_here = valuables::table
+        .filter(valuables::q.eq(coords.q))
+        .filter(valuables::r.eq(coords.r))
+        .load::<Valuable>(&conn)
+        .expect("Could not load valuables");
+    for valuable in valuables_here {
+        println!(
+            "  valuable {} ({} {})",
+            valuable.id, valuable.amount, valuable.kind
+        );
+    }
+
+    let known_by = robot_known_cells::table
+        .filter(robot_known_cells::gridcell_id.eq(cell.id))
+        .count()
+        .get_result::<i64>(&conn)
+        .expect("Could not count known cells");
+    println!("  known by {} robots", known_by);
+}
+
+/// List all the robots with their location, vitals and modules
+fn list_robots(dbconfig: &db::DbConfig) {
+    let conn = db::establish_connection(dbconfig);
+
+    let all_robots = robots::table
+        .order(robots::id)
+        .load::<RobotData>(&conn)
+        .expect("Could not load robots");
+
+    for robot in &all_robots {
+        let modules = RobotModules::load(robot.id, Some(&conn)).expect("Could not load modules");
+        let orientation: i32 = robot.orientation.into();
+        println!(
+            "{} {:<8} {:<8} ({},{}) @ {:<3} power {}/{} hull {}/{} inventory {}/{}",
+            robot.id,
+            robot.name,
+            robot.behavior,
+            robot.q,
+            robot.r,
+            orientation,
+            robot.power,
+            robot.max_power,
+            robot.hull_strength,
+            robot.max_hull_strength,
+            robot.val_inventory,
+            robot.max_val_inventory,
+        );
+        println!(
+            "    collector {} comms {} drivesystem {} exfilbeacon {} hull {} memory {} power {} scanner {} weapons {}",
+            modules.m_collector,
+            modules.m_comms,
+            modules.m_drivesystem,
+            modules.m_exfilbeacon,
+            modules.m_hull,
+            modules.m_memory,
+            modules.m_power,
+            modules.m_scanner,
+            modules.m_weapons,
+        );
+        if let Some(status) = robot.status_text.lines().next() {
+            println!("    \"{}\"", status);
+        }
+    }
+    println!("{} robots", all_robots.len());
+}
+
+/// Delete a robot; modules and known cells cascade
+fn delete_robot(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let robot_id: i64 = parse_arg(matches, "robot_id");
+    let conn = db::establish_connection(dbconfig);
+
+    let deleted = diesel::delete(robots::table.filter(robots::id.eq(robot_id)))
+        .execute(&conn)
+        .expect("Could not delete robot");
+
+    if deleted == 0 {
+        println!("No robot {}", robot_id);
+    } else {
+        println!("Deleted robot {}", robot_id);
+    }
+}
+
+/// Spawn a robot with the requested modules on an open, unoccupied cell
+fn spawn_robot(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let coords = parse_coords(matches);
+    let conn = db::establish_connection(dbconfig);
+
+    if let Some(name) = matches
+        .value_of("behavior")
+        .and_then(|b| b.strip_prefix(SCRIPT_PREFIX))
+    {
+        if let Err(reason) = ScriptBehavior::load(Some(&conn), name) {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+
+    let owner = matches
+        .value_of("owner")
+        .map(|name| match Owner::load_by_name(&conn, name) {
+            Ok(owner) => owner,
+            Err(reason) => {
+                eprintln!("{}", reason);
+                std::process::exit(1);
+            }
+        });
+
+    let grid = Grid::load(Some(&conn)).expect("Could not load grid");
+    match grid.cells.get(&coords) {
+        Some(cell) if cell.is_open() => (),
+        Some(_) => {
+            eprintln!("Cell {},{} is walled in", coords.q, coords.r);
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("No cell at {},{}", coords.q, coords.r);
+            std::process::exit(1);
+        }
+    }
+
+    let occupants = robots::table
+        .filter(robots::q.eq(coords.q))
+        .filter(robots::r.eq(coords.r))
+        .count()
+        .get_result::<i64>(&conn)
+        .expect("Could not load robots");
+    if occupants > 0 {
+        eprintln!("Cell {},{} is already occupied", coords.q, coords.r);
+        std::process::exit(1);
+    }
+
+    let orientation: Dir = match matches.value_of("orientation") {
+        Some(_) => parse_arg::<i32>(matches, "orientation").into(),
+        None => rand::random(),
+    };
+
+    let mut modules: HashMap<String, String> = HashMap::new();
+    for (name, key) in MODULE_KEYS.iter() {
+        if let Some(module) = matches.value_of(name) {
+            modules.insert(key.to_string(), module.to_string());
+        }
+    }
+
+    let mut robot = Robot::new(
+        coords,
+        orientation,
+        Some(&conn),
+        Arc::new(Mutex::new(grid)),
+        Some(modules),
+    );
+    if matches.is_present("affiliation") {
+        robot.set_affiliation(Some(&conn), Some(parse_arg(matches, "affiliation")));
+    }
+    if let Some(behavior) = matches.value_of("behavior") {
+        robot.set_behavior(Some(&conn), behavior);
+    }
+    if let Some(owner) = owner {
+        robot.set_owner(Some(&conn), Some(owner.id));
+    }
+    println!(
+        "Spawned robot {} ({}) at {},{}",
+        robot.data.id, robot.data.name, coords.q, coords.r
+    );
+}
+
+/// Place a new valuable, or add to the valuable already on the cell
+fn place_valuable(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let coords = parse_coords(matches);
+    let amount: i32 = parse_arg(matches, "amount");
+    let conn = db::establish_connection(dbconfig);
+
+    let grid = Grid::load(Some(&conn)).expect("Could not load grid");
+    if !grid.cells.get(&coords).map_or(false, |c| c.is_open()) {
+        eprintln!("Cell {},{} is not an open cell", coords.q, coords.r);
+        std::process::exit(1);
+    }
+
+    let existing = valuables::table
+        .filter(valuables::q.eq(coords.q))
+        .filter(valuables::r.eq(coords.r))
+        .first::<Valuable>(&conn)
+        .optional()
+        .expect("Could not load valuables");
+
+    let valuable = match existing {
+        Some(mut valuable) => {
+            valuable.add_to_amount(Some(&conn), amount);
+            valuable
+        }
+        None => Valuable::new(coords, amount, Some(&conn)),
+    };
+    println!(
+        "Valuable {} at {},{} now has {}",
+        valuable.id, coords.q, coords.r, valuable.amount
+    );
+}
+
+/// Remove a valuable
+fn remove_valuable(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let valuable_id: i64 = parse_arg(matches, "valuable_id");
+    let conn = db::establish_connection(dbconfig);
+
+    let valuable = valuables::table
+        .filter(valuables::id.eq(valuable_id))
+        .first::<Valuable>(&conn)
+        .optional()
+        .expect("Could not load valuables");
+
+    match valuable {
+        Some(mut valuable) => {
+            valuable.destroy(Some(&conn));
+        }
+        None => println!("No valuable {}", valuable_id),
+    }
+}
+
+/// Delete all the known cells for a robot
+fn reset_memory(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let robot_id: i64 = parse_arg(matches, "robot_id");
+    let conn = db::establish_connection(dbconfig);
+
+    let deleted =
+        diesel::delete(robot_known_cells::table.filter(robot_known_cells::robot_id.eq(robot_id)))
+            .execute(&conn)
+            .expect("Could not delete known cells");
+    println!("Robot {} forgot {} cells", robot_id, deleted);
+}
+
+/// Print counts of everything in the world
+fn print_stats(dbconfig: &db::DbConfig) {
+    let conn = db::establish_connection(dbconfig);
+
+    let grid = Grid::load(Some(&conn)).expect("Could not load grid");
+    let open_cells = grid.cells.values().filter(|c| c.is_open()).count();
+    println!("Grid radius:      {}", grid.radius());
+    println!("Cells:            {}", grid.cells.len());
+    println!("Open cells:       {}", open_cells);
+
+    let all_robots = robots::table
+        .load::<RobotData>(&conn)
+        .expect("Could not load robots");
+    let known_cells = robot_known_cells::table
+        .count()
+        .get_result::<i64>(&conn)
+        .expect("Could not count known cells");
+    println!("Robots:           {}", all_robots.len());
+    println!(
+        "Robot inventory:  {}",
+        all_robots
+            .iter()
+            .map(|r| r.val_inventory as i64)
+            .sum::<i64>()
+    );
+    println!("Known cells:      {}", known_cells);
+
+    let all_valuables = Valuable::load_all(Some(&conn)).expect("Could not load valuables");
+    println!("Valuables:        {}", all_valuables.len());
+    println!(
+        "Valuables amount: {}",
+        all_valuables.values().map(|v| v.amount as i64).sum::<i64>()
+    );
+}
+
+/// Load the grid along with where the robots and valuables are
+fn load_grid_with_occupants(conn: &PgConnection) -> Grid {
+    let mut grid = Grid::load(Some(conn)).expect("Could not load grid");
+    grid.load_occupants(Some(conn))
+        .expect("Could not load robots and valuables");
+    grid
+}
+
+/// Report the result of an edit, including anything that had to be moved
+fn report_edit(result: Result<Vec<Relocation>, String>) {
+    match result {
+        Ok(relocations) => {
+            for relocation in relocations {
+                match relocation {
+                    Relocation::Robot { id, from, to } => println!(
+                        "Moved robot {} from {},{} to {},{}",
+                        id, from.q, from.r, to.q, to.r
+                    ),
+                    Relocation::Valuable { id, from, to } => println!(
+                        "Moved valuable {} from {},{} to {},{}",
+                        id, from.q, from.r, to.q, to.r
+                    ),
+                }
+            }
+        }
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Grow the grid to a larger radius
+fn grow(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let size: u32 = parse_arg(matches, "size");
+    let conn = db::establish_connection(dbconfig);
+
+    let mut grid = load_grid_with_occupants(&conn);
+    report_edit(grid.grow(size, Some(&conn)));
+    println!("Cells: {}", grid.cells.len());
+}
+
+/// Carve or seal one side of a cell
+fn set_edge(dbconfig: &db::DbConfig, matches: &ArgMatches, edge_type: EdgeType) {
+    let coords = parse_coords(matches);
+    let dir: Dir = parse_arg::<i32>(matches, "dir").into();
+    let conn = db::establish_connection(dbconfig);
+
+    let mut grid = load_grid_with_occupants(&conn);
+    report_edit(grid.set_edge(&coords, dir, edge_type, Some(&conn)));
+}
+
+/// Stamp a room template onto the grid
+fn stamp(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let coords = parse_coords(matches);
+    let size: i32 = parse_arg(matches, "size");
+    let template = match matches.value_of("template").unwrap() {
+        "hexagon" => RoomTemplate::Hexagon { radius: size },
+        "corridor" => RoomTemplate::Corridor {
+            dir: parse_arg::<i32>(matches, "dir").into(),
+            length: size,
+        },
+        _ => RoomTemplate::Cross { length: size },
+    };
+    let conn = db::establish_connection(dbconfig);
+
+    let mut grid = load_grid_with_occupants(&conn);
+    report_edit(grid.stamp_room(&coords, &template, Some(&conn)));
+}
+
+/// List the spawn zones
+fn list_zones(dbconfig: &db::DbConfig) {
+    let conn = db::establish_connection(dbconfig);
+
+    let zones = SpawnZone::load_all(Some(&conn)).expect("Could not load spawn zones");
+    for zone in &zones {
+        let affiliation = match zone.affiliation {
+            Some(affiliation) => format!("team {}", affiliation),
+            None => String::from("shared"),
+        };
+        println!(
+            "{:<16} ({},{}) radius {} {}",
+            zone.name, zone.q, zone.r, zone.radius, affiliation
+        );
+    }
+    println!("{} spawn zones", zones.len());
+}
+
+/// Add a spawn zone, replacing any zone with the same name
+fn add_zone(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let coords = parse_coords(matches);
+    let radius: i32 = parse_arg(matches, "radius");
+    let affiliation: Option<i32> = if matches.is_present("affiliation") {
+        Some(parse_arg(matches, "affiliation"))
+    } else {
+        None
+    };
+    let zone = SpawnZone::new(
+        matches.value_of("name").unwrap(),
+        affiliation,
+        coords,
+        radius,
+    );
+    let conn = db::establish_connection(dbconfig);
+
+    let grid = Grid::load(Some(&conn)).expect("Could not load grid");
+    if !grid.cells.contains_key(&coords) {
+        eprintln!("No cell at {},{}", coords.q, coords.r);
+        std::process::exit(1);
+    }
+
+    match zone.save(&conn) {
+        Ok(_) => println!("Saved spawn zone {}", zone.name),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remove a spawn zone
+fn remove_zone(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let name = matches.value_of("name").unwrap();
+    let conn = db::establish_connection(dbconfig);
+
+    match SpawnZone::delete(&conn, name) {
+        Ok(true) => println!("Removed spawn zone {}", name),
+        Ok(false) => println!("No spawn zone {}", name),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// List the robot owners
+fn list_owners(dbconfig: &db::DbConfig) {
+    let conn = db::establish_connection(dbconfig);
+
+    let owners = Owner::load_all(Some(&conn)).expect("Could not load owners");
+    for owner in &owners {
+        let robot_count = robots::table
+            .filter(robots::owner.eq(owner.id))
+            .count()
+            .get_result::<i64>(&conn)
+            .expect("Could not load robots");
+        println!("{:<4} {:<16} {} robots", owner.id, owner.name, robot_count);
+    }
+    println!("{} owners", owners.len());
+}
+
+/// Add a robot owner; the token is only shown once
+fn add_owner(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let conn = db::establish_connection(dbconfig);
+
+    match Owner::create(&conn, matches.value_of("name").unwrap()) {
+        Ok((owner, token)) => {
+            println!("Added owner {} ({}); token {}", owner.name, owner.id, token)
+        }
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remove a robot owner
+fn remove_owner(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let name = matches.value_of("name").unwrap();
+    let conn = db::establish_connection(dbconfig);
+
+    match Owner::delete(&conn, name) {
+        Ok(true) => println!("Removed owner {}", name),
+        Ok(false) => println!("No owner {}", name),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// List the robot scripts
+fn list_scripts(dbconfig: &db::DbConfig) {
+    let conn = db::establish_connection(dbconfig);
+
+    let scripts = ScriptSource::load_all(Some(&conn)).expect("Could not load scripts");
+    for script in &scripts {
+        println!(
+            "{:<16} api v{} fuel {} ({} lines)",
+            script.name,
+            script.api_version,
+            script.fuel,
+            script.source.lines().count()
+        );
+    }
+    println!("{} scripts", scripts.len());
+}
+
+/// Add a robot script, replacing any script with the same name; the script must
+/// compile before it is saved
+fn add_script(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let file = matches.value_of("file").unwrap();
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file, e);
+            std::process::exit(1);
+        }
+    };
+    let script = ScriptSource::new(
+        matches.value_of("name").unwrap(),
+        parse_arg(matches, "fuel"),
+        &source,
+    );
+    if let Err(reason) = ScriptBehavior::new(&script) {
+        eprintln!("{}", reason);
+        std::process::exit(1);
+    }
+
+    let conn = db::establish_connection(dbconfig);
+    match script.save(&conn) {
+        Ok(_) => println!("Saved script {}", script.name),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remove a robot script
+fn remove_script(dbconfig: &db::DbConfig, matches: &ArgMatches) {
+    let name = matches.value_of("name").unwrap();
+    let conn = db::establish_connection(dbconfig);
+
+    match ScriptSource::delete(&conn, name) {
+        Ok(true) => println!("Removed script {}", name),
+        Ok(false) => println!("No script {}", name),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print the protocol schema, for generating client code
+fn print_protocol_schema() {
+    match serde_json::to_string_pretty(&protocol::schema()) {
+        Ok(schema) => println!("{}", schema),
+        Err(e) => eprintln!("Could not serialize schema: {}", e),
+    }
+}
@@ -1,7 +1,9 @@
+use clap::{App, Arg, ArgMatches};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenv::dotenv;
 
+#[derive(Clone)]
 pub struct DbConfig {
     pub dbuser: String,
     pub dbpw: String,
@@ -16,6 +18,51 @@ impl DbConfig {
             self.dbuser, self.dbpw, self.dbhost, self.dbname
         )
     }
+
+    /// Build the config from arguments added with `add_db_args`
+    pub fn from_matches(matches: &ArgMatches) -> DbConfig {
+        DbConfig {
+            dbuser: matches.value_of("dbuser").unwrap_or("ares").to_string(),
+            dbpw: matches.value_of("dbpw").unwrap_or("ares").to_string(),
+            dbhost: matches
+                .value_of("dbhost")
+                .unwrap_or("localhost")
+                .to_string(),
+            dbname: matches.value_of("db").unwrap_or("ares").to_string(),
+        }
+    }
+}
+
+/// Add the database connection arguments shared by all our binaries
+pub fn add_db_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("dbuser")
+            .short("u")
+            .long("user")
+            .takes_value(true)
+            .help("Database username"),
+    )
+    .arg(
+        Arg::with_name("dbpw")
+            .short("p")
+            .long("password")
+            .takes_value(true)
+            .help("Database password"),
+    )
+    .arg(
+        Arg::with_name("dbhost")
+            .short("o")
+            .long("hostname")
+            .takes_value(true)
+            .help("Database hostname"),
+    )
+    .arg(
+        Arg::with_name("db")
+            .short("n")
+            .long("dbname")
+            .takes_value(true)
+            .help("Database name"),
+    )
 }
 
 pub fn establish_connection(dbconfig: &DbConfig) -> PgConnection {
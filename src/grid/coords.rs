@@ -3,6 +3,7 @@ use diesel::deserialize::{FromSql, Result};
 use diesel::serialize::{self, Output, ToSql};
 use diesel::sql_types::*;
 use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use serde_repr;
 use std::io::Write;
 
@@ -30,6 +31,7 @@ pub enum CoordsKind {
     FromSqlRow,
     PartialOrd,
     serde_repr::Serialize_repr,
+    serde_repr::Deserialize_repr,
 )]
 #[repr(i16)]
 #[sql_type = "SmallInt"]
@@ -245,9 +247,22 @@ impl Dir {
         }
         angle.into()
     }
+
+    /// Rotate by a number of 60 degree steps; positive is clockwise (to the right)
+    pub fn rotated(&self, steps: i32) -> Dir {
+        let angle: i32 = self.into();
+        ((angle / 60 + steps).rem_euclid(6) * 60).into()
+    }
+
+    /// Mirror this direction across an axis
+    pub fn reflected(&self, axis: Dir) -> Dir {
+        let angle: i32 = self.into();
+        let axis: i32 = axis.into();
+        (2 * axis - angle).rem_euclid(360).into()
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct Coords {
     pub q: i32,
     pub r: i32,
@@ -338,6 +353,212 @@ impl Coords {
 
         -1
     }
+
+    /// Get the direction to an adjacent cell; None if the target isn't adjacent
+    pub fn neighbor_dir(&self, target: &Coords) -> Option<Dir> {
+        Dir::get_vec()
+            .into_iter()
+            .find(|dir| self.to(dir, 1) == *target)
+    }
+
+    /// Get the adjacent coords in the given direction
+    pub fn neighbor(&self, dir: Dir) -> Coords {
+        self.to(&dir, 1)
+    }
+
+    /// Get all six adjacent coords, in the same order as `Dir::get_vec`
+    pub fn neighbors(&self) -> Vec<Coords> {
+        Dir::get_vec().iter().map(|dir| self.to(dir, 1)).collect()
+    }
+
+    /// Walk part of the ring at `radius` around these coords
+    ///
+    /// We start at the corner in direction `from` and go clockwise along `sides`
+    /// sides of the ring, including the corners at both ends. With 6 sides, we
+    /// get the whole ring with each coords once.
+    pub fn arc(&self, radius: i32, from: Dir, sides: i32) -> impl Iterator<Item = Coords> {
+        let center = *self;
+        let total = if radius <= 0 {
+            1
+        } else if sides >= 6 {
+            6 * radius
+        } else {
+            sides.max(0) * radius + 1
+        };
+
+        (0..total).map(move |i| {
+            if radius <= 0 {
+                return center;
+            }
+            let side = i / radius;
+            let step = i % radius;
+            center
+                .to(&from.rotated(side), radius)
+                .to(&from.rotated(side + 2), step)
+        })
+    }
+
+    /// Get the coords exactly `radius` away, going clockwise from the bottom left corner
+    pub fn ring(&self, radius: i32) -> impl Iterator<Item = Coords> {
+        self.arc(radius, Dir::Orient240, 6)
+    }
+
+    /// Get all the coords within `radius`, starting with these coords and then
+    /// ring by ring outward
+    pub fn spiral(&self, radius: i32) -> impl Iterator<Item = Coords> {
+        let center = *self;
+        (0..radius + 1).flat_map(move |r| center.ring(r))
+    }
+
+    /// Get the coords in a cone facing `dir`, ring by ring out to `distance`
+    ///
+    /// Each ring goes clockwise from the left edge of the field of view to the
+    /// right edge. A field of view of 0 is a straight line and 360 is every cell
+    /// in range. These coords come last.
+    pub fn cone(&self, dir: Dir, fov: i32, distance: i32) -> impl Iterator<Item = Coords> {
+        let center = *self;
+        let from = dir.left(fov / 2);
+        (1..distance + 1)
+            .flat_map(move |r| center.arc(r, from, fov / 60))
+            .chain(std::iter::once(center))
+    }
+
+    /// Get the coords on a straight line to the target, including both ends
+    pub fn line_to(&self, target: &Coords) -> Vec<Coords> {
+        self.line_to_nudged(target, 1e-6)
+    }
+
+    /// Get the coords on a straight line to the target, including both ends
+    ///
+    /// `nudge` shifts the line slightly off center; a line passing exactly between
+    /// two cells goes through one or the other depending on its sign
+    pub fn line_to_nudged(&self, target: &Coords, nudge: f64) -> Vec<Coords> {
+        let distance = self.distance_to(target);
+
+        let (ax, ay) = (self.q as f64 + nudge, self.r as f64 + nudge);
+        let (bx, by) = (target.q as f64 + nudge, target.r as f64 + nudge);
+        let (az, bz) = (-ax - ay, -bx - by);
+
+        (0..distance + 1)
+            .map(|i| {
+                let t = if distance == 0 {
+                    0.0
+                } else {
+                    i as f64 / distance as f64
+                };
+
+                Coords::round_cube(ax + (bx - ax) * t, ay + (by - ay) * t, az + (bz - az) * t)
+            })
+            .collect()
+    }
+
+    /// Rotate around a center by a number of 60 degree steps; positive is clockwise
+    pub fn rotate_around(&self, center: &Coords, steps: i32) -> Coords {
+        let mut q = self.q - center.q;
+        let mut r = self.r - center.r;
+
+        // one step to the right takes Orient0 (0, 1) to Orient60 (1, 0)
+        for _ in 0..steps.rem_euclid(6) {
+            let turned = (q + r, -q);
+            q = turned.0;
+            r = turned.1;
+        }
+
+        Coords {
+            q: q + center.q,
+            r: r + center.r,
+        }
+    }
+
+    /// Mirror across the line through the center running along the axis direction
+    pub fn reflect_across(&self, center: &Coords, axis: Dir) -> Coords {
+        // turn the axis to face Orient0, mirror across that, then turn back
+        let steps: i32 = axis.into();
+        let steps = steps / 60;
+        let turned = self.rotate_around(center, -steps);
+
+        let q = turned.q - center.q;
+        let r = turned.r - center.r;
+        let mirrored = Coords {
+            q: center.q - q,
+            r: center.r + q + r,
+        };
+
+        mirrored.rotate_around(center, steps)
+    }
+
+    /// Get the coords that are within `radius` of these coords and also within
+    /// `other_radius` of the other coords
+    pub fn range_intersection(
+        &self,
+        radius: i32,
+        other: &Coords,
+        other_radius: i32,
+    ) -> Vec<Coords> {
+        // the overlap is bounded on each cube axis by both ranges
+        let (ax, ay) = (self.q, self.r);
+        let (bx, by) = (other.q, other.r);
+        let (az, bz) = (-ax - ay, -bx - by);
+
+        let x_min = (ax - radius).max(bx - other_radius);
+        let x_max = (ax + radius).min(bx + other_radius);
+        let y_min = (ay - radius).max(by - other_radius);
+        let y_max = (ay + radius).min(by + other_radius);
+        let z_min = (az - radius).max(bz - other_radius);
+        let z_max = (az + radius).min(bz + other_radius);
+
+        let mut found = Vec::new();
+        for x in x_min..(x_max + 1) {
+            let low = y_min.max(-x - z_max);
+            let high = y_max.min(-x - z_min);
+            for y in low..(high + 1) {
+                found.push(Coords { q: x, r: y });
+            }
+        }
+
+        found
+    }
+
+    /// Get the center of the cell in pixel space, with `scale` pixels per flat2d unit
+    /// (adjacent cells are two units apart); y points up
+    pub fn to_pixel(&self, scale: f64) -> (f64, f64) {
+        match self.to_flat2d() {
+            CoordsKind::Flat2D { x, y } => (x * scale, y * scale),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    /// Get the cell containing a point in pixel space; the inverse of `to_pixel`
+    pub fn from_pixel(x: f64, y: f64, scale: f64) -> Coords {
+        let q = x / scale / 3f64.sqrt();
+        let r = (y / scale - q) / 2.0;
+
+        Coords::round_cube(q, r, -q - r)
+    }
+
+    /// Round fractional cube coordinates to the cell containing them
+    fn round_cube(x: f64, y: f64, z: f64) -> Coords {
+        let mut rx = x.round();
+        let mut ry = y.round();
+        let rz = z.round();
+
+        let dx = (rx - x).abs();
+        let dy = (ry - y).abs();
+        let dz = (rz - z).abs();
+
+        // the rounded coords must still add up to zero, so fix whichever axis
+        // had the largest rounding error
+        if dx > dy && dx > dz {
+            rx = -ry - rz;
+        } else if dy > dz {
+            ry = -rx - rz;
+        }
+
+        Coords {
+            q: rx as i32,
+            r: ry as i32,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -398,3 +619,179 @@ pub struct CoordsAndDir {
     pub coords: Coords,
     pub dir: Dir,
 }
+
+#[cfg(test)]
+#[test]
+fn test_geometry() {
+    let center = Coords { q: 0, r: 0 };
+
+    // rings start at the bottom left corner and go clockwise
+    let ring: Vec<Coords> = center.ring(1).collect();
+    assert_eq!(
+        ring,
+        vec![
+            Coords { q: -1, r: 0 },
+            Coords { q: -1, r: 1 },
+            Coords { q: 0, r: 1 },
+            Coords { q: 1, r: 0 },
+            Coords { q: 1, r: -1 },
+            Coords { q: 0, r: -1 },
+        ]
+    );
+
+    assert_eq!(
+        center.line_to(&Coords { q: 3, r: -3 }),
+        vec![
+            Coords { q: 0, r: 0 },
+            Coords { q: 1, r: -1 },
+            Coords { q: 2, r: -2 },
+            Coords { q: 3, r: -3 },
+        ]
+    );
+
+    // between two cells, the nudge decides which one the line passes through
+    let a = center.line_to_nudged(&Coords { q: 1, r: 1 }, 1e-6);
+    let b = center.line_to_nudged(&Coords { q: 1, r: 1 }, -1e-6);
+    assert_eq!(a.len(), 3);
+    assert_ne!(a[1], b[1]);
+
+    let up = Coords { q: 0, r: 2 };
+    assert_eq!(up.rotate_around(&center, 1), Coords { q: 2, r: 0 });
+    assert_eq!(up.rotate_around(&center, -1), Coords { q: -2, r: 2 });
+    assert_eq!(
+        Coords { q: 1, r: 0 }.reflect_across(&center, Dir::Orient0),
+        Coords { q: -1, r: 1 }
+    );
+    assert_eq!(Dir::Orient300.rotated(1), Dir::Orient0);
+    assert_eq!(Dir::Orient60.reflected(Dir::Orient0), Dir::Orient300);
+
+    // a straight cone is just the line of cells ahead
+    let cone: Vec<Coords> = center.cone(Dir::Orient60, 0, 2).collect();
+    assert_eq!(
+        cone,
+        vec![Coords { q: 1, r: 0 }, Coords { q: 2, r: 0 }, center]
+    );
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    fn coords() -> impl Strategy<Value = Coords> {
+        (-50..50i32, -50..50i32).prop_map(|(q, r)| Coords { q, r })
+    }
+
+    fn dir() -> impl Strategy<Value = Dir> {
+        (0..6i32).prop_map(|i| (i * 60).into())
+    }
+
+    proptest! {
+        #[test]
+        fn ring_is_every_cell_at_radius(center in coords(), radius in 1..12i32) {
+            let ring: Vec<Coords> = center.ring(radius).collect();
+            let unique: HashSet<Coords> = ring.iter().cloned().collect();
+
+            prop_assert_eq!(ring.len(), (6 * radius) as usize);
+            prop_assert_eq!(unique.len(), ring.len());
+            prop_assert!(ring.iter().all(|c| c.distance_to(&center) == radius));
+            for pair in ring.windows(2) {
+                prop_assert_eq!(pair[0].distance_to(&pair[1]), 1);
+            }
+        }
+
+        #[test]
+        fn spiral_covers_range(center in coords(), radius in 0..10i32) {
+            let spiral: HashSet<Coords> = center.spiral(radius).collect();
+
+            prop_assert_eq!(spiral.len(), (3 * radius * (radius + 1) + 1) as usize);
+            prop_assert!(spiral.iter().all(|c| c.distance_to(&center) <= radius));
+        }
+
+        #[test]
+        fn full_cone_matches_spiral(center in coords(), facing in dir(), radius in 0..8i32) {
+            let cone: HashSet<Coords> = center.cone(facing, 360, radius).collect();
+            let spiral: HashSet<Coords> = center.spiral(radius).collect();
+
+            prop_assert_eq!(cone, spiral);
+        }
+
+        #[test]
+        fn line_steps_between_neighbors(a in coords(), b in coords()) {
+            let line = a.line_to(&b);
+
+            prop_assert_eq!(line.len() as i32, a.distance_to(&b) + 1);
+            prop_assert_eq!(line[0], a);
+            prop_assert_eq!(*line.last().unwrap(), b);
+            for pair in line.windows(2) {
+                prop_assert!(pair[0].neighbor_dir(&pair[1]).is_some());
+            }
+        }
+
+        #[test]
+        fn rotation_keeps_distance(c in coords(), center in coords(), steps in -12..12i32) {
+            let rotated = c.rotate_around(&center, steps);
+
+            prop_assert_eq!(rotated.distance_to(&center), c.distance_to(&center));
+            prop_assert_eq!(rotated.rotate_around(&center, -steps), c);
+            prop_assert_eq!(c.rotate_around(&center, steps + 6), rotated);
+        }
+
+        #[test]
+        fn rotation_turns_neighbors(c in coords(), facing in dir(), steps in -6..6i32) {
+            let rotated = c.neighbor(facing).rotate_around(&c, steps);
+
+            prop_assert_eq!(rotated, c.neighbor(facing.rotated(steps)));
+        }
+
+        #[test]
+        fn reflection_is_an_involution(c in coords(), center in coords(), axis in dir()) {
+            let mirrored = c.reflect_across(&center, axis);
+
+            prop_assert_eq!(mirrored.distance_to(&center), c.distance_to(&center));
+            prop_assert_eq!(mirrored.reflect_across(&center, axis), c);
+            prop_assert_eq!(center.to(&axis, 3).reflect_across(&center, axis), center.to(&axis, 3));
+        }
+
+        #[test]
+        fn reflection_mirrors_neighbors(c in coords(), facing in dir(), axis in dir()) {
+            let mirrored = c.neighbor(facing).reflect_across(&c, axis);
+
+            prop_assert_eq!(mirrored, c.neighbor(facing.reflected(axis)));
+        }
+
+        #[test]
+        fn range_intersection_matches_brute_force(
+            a in coords(),
+            b in coords(),
+            ra in 0..8i32,
+            rb in 0..8i32,
+        ) {
+            let found: HashSet<Coords> = a.range_intersection(ra, &b, rb).into_iter().collect();
+            let expected: HashSet<Coords> = a
+                .spiral(ra)
+                .filter(|c| c.distance_to(&b) <= rb)
+                .collect();
+
+            prop_assert_eq!(found, expected);
+        }
+
+        #[test]
+        fn pixels_round_trip(c in coords(), scale in 0.5..40.0f64) {
+            let (x, y) = c.to_pixel(scale);
+
+            prop_assert_eq!(Coords::from_pixel(x, y, scale), c);
+            // anywhere inside the inner circle of the cell maps back to it
+            prop_assert_eq!(Coords::from_pixel(x + 0.9 * scale, y, scale), c);
+            prop_assert_eq!(Coords::from_pixel(x, y - 0.9 * scale, scale), c);
+        }
+
+        #[test]
+        fn neighbors_are_adjacent(c in coords(), facing in dir()) {
+            prop_assert_eq!(c.neighbors().len(), 6);
+            prop_assert_eq!(c.neighbor_dir(&c.neighbor(facing)), Some(facing));
+            prop_assert_eq!(c.neighbor(facing).neighbor(facing.get_opposite()), c);
+        }
+    }
+}
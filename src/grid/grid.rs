@@ -1,11 +1,16 @@
+use diesel::pg::upsert::excluded;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use rand::Rng;
+use rand::seq::SliceRandom;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use tracing::info;
 
 use super::coords::*;
 use super::edge::EdgeType;
+use super::spatial::*;
+use super::spawn::SpawnZone;
+use super::utils::RoomTemplate;
 use crate::robot::*;
 use crate::schema::*;
 
@@ -92,13 +97,22 @@ impl GridCell {
     }
 }
 
+/// Something that had to be moved out of a part of the grid that was sealed off
+#[derive(Clone, Debug, PartialEq)]
+pub enum Relocation {
+    Robot { id: i64, from: Coords, to: Coords },
+    Valuable { id: i64, from: Coords, to: Coords },
+}
+
 #[derive(Debug)]
 pub struct Grid {
     pub cells: HashMap<Coords, GridCell>,
-    pub robot_locs: HashMap<Coords, i64>,
+    pub robot_locs: SpatialIndex,
     pub robot_strengths: HashMap<i64, i32>,
-    pub valuables_locs: HashMap<Coords, i64>,
-    less_than_guess: Option<i32>,
+    pub valuables_locs: SpatialIndex,
+    /// affiliations of the robots that have one
+    pub robot_affiliations: HashMap<i64, i32>,
+    pub spawn_zones: Vec<SpawnZone>,
 }
 
 impl Grid {
@@ -122,15 +136,19 @@ impl Grid {
             cells_map.insert(coords, result);
         }
 
+        let spawn_zones = SpawnZone::load_all(conn)?;
+
         Ok(Grid {
             cells: cells_map,
-            robot_locs: HashMap::new(),
+            robot_locs: SpatialIndex::new(),
             robot_strengths: HashMap::new(),
-            valuables_locs: HashMap::new(),
-            less_than_guess: Some(4000),
+            valuables_locs: SpatialIndex::new(),
+            robot_affiliations: HashMap::new(),
+            spawn_zones,
         })
     }
 
+    /// Generate a brand new grid; this deletes all robots, valuables and cells
     pub fn new(size: u32, conn: Option<&PgConnection>) -> Result<Grid, String> {
         if size == 0 {
             return Err(String::from("Improper grid size"));
@@ -148,139 +166,441 @@ impl Grid {
             diesel::delete(gridcells::table)
                 .execute(conn)
                 .expect("Could not drop gridcells table");
-            let cell_values = cells.values().collect::<Vec<&GridCell>>();
-
-            let mut start = 0;
-            let size = cell_values.len();
-            while start < size {
-                let mut end = start + 300;
-                if end > size {
-                    end = size;
-                }
 
-                if let Some(_cells) = cell_values.get(start..end) {
-                    diesel::insert_into(gridcells::table)
-                        .values(_cells.to_vec())
-                        .execute(conn)
-                        .expect("Error saving cells");
-
-                    start = end;
-                    println!("{}/{}", start, size);
-                }
-            }
+            Grid::save_cells(conn, cells.values().collect());
         }
 
         Ok(Grid {
             cells: cells,
-            robot_locs: HashMap::new(),
+            robot_locs: SpatialIndex::new(),
             robot_strengths: HashMap::new(),
-            valuables_locs: HashMap::new(),
-            less_than_guess: Some(5000),
+            valuables_locs: SpatialIndex::new(),
+            robot_affiliations: HashMap::new(),
+            spawn_zones: Vec::new(),
         })
     }
 
-    pub fn get_random_open_cell(&mut self) -> Coords {
-        let mut rng = rand::thread_rng();
-        let mut found_coords: Option<Coords> = None;
-        while let None = found_coords {
-            let max_range = self.less_than_guess.unwrap_or(5000);
-            let q: i32 = rng.gen_range(max_range * -1 - 1, max_range + 1);
-            let r: i32 = rng.gen_range(max_range * -1 - 1, max_range + 1);
-
-            let test_coords = Coords { q, r };
-
-            if self.cells.contains_key(&test_coords) {
-                if self.cells.get(&test_coords).unwrap().is_open() {
-                    if !self.robot_locs.contains_key(&test_coords)
-                        && !self.valuables_locs.contains_key(&test_coords)
-                    {
-                        found_coords = Some(test_coords);
-                    }
-                }
-            } else {
-                self.less_than_guess = std::cmp::max(Some(q.abs()), Some(r.abs()));
+    /// Get the radius of the grid, i.e. the distance from the origin to the outermost ring
+    pub fn radius(&self) -> i32 {
+        let origin = Coords { q: 0, r: 0 };
+        self.cells
+            .keys()
+            .map(|c| c.distance_to(&origin))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Insert or update cells in the db, in batches
+    fn save_cells(conn: &PgConnection, cells: Vec<&GridCell>) {
+        let mut start = 0;
+        let size = cells.len();
+        while start < size {
+            let mut end = start + 300;
+            if end > size {
+                end = size;
+            }
+
+            if let Some(_cells) = cells.get(start..end) {
+                diesel::insert_into(gridcells::table)
+                    .values(_cells.to_vec())
+                    .on_conflict(gridcells::id)
+                    .do_update()
+                    .set((
+                        gridcells::edge0.eq(excluded(gridcells::edge0)),
+                        gridcells::edge60.eq(excluded(gridcells::edge60)),
+                        gridcells::edge120.eq(excluded(gridcells::edge120)),
+                        gridcells::edge180.eq(excluded(gridcells::edge180)),
+                        gridcells::edge240.eq(excluded(gridcells::edge240)),
+                        gridcells::edge300.eq(excluded(gridcells::edge300)),
+                    ))
+                    .execute(conn)
+                    .expect("Error saving cells");
+
+                start = end;
+                info!("Saved {}/{} cells", start, size);
             }
         }
+    }
 
-        let cell = self.cells.get(&found_coords.unwrap()).unwrap();
-        Coords {
-            q: cell.q,
-            r: cell.r,
+    /// Apply a change to the cells; any cells that were added or changed are saved
+    /// and anything the change cut off from where it was is moved somewhere open
+    fn edit_cells<F>(
+        &mut self,
+        conn: Option<&PgConnection>,
+        edit: F,
+    ) -> Result<Vec<Relocation>, String>
+    where
+        F: FnOnce(&mut HashMap<Coords, GridCell>),
+    {
+        let before = self.cells.clone();
+        let regions_before = self.regions();
+        edit(&mut self.cells);
+
+        if let Some(conn) = conn {
+            let changed: Vec<&GridCell> = self
+                .cells
+                .iter()
+                .filter(|(coords, cell)| match before.get(coords) {
+                    Some(old) => Dir::get_vec()
+                        .iter()
+                        .any(|dir| old.get_side(*dir) != cell.get_side(*dir)),
+                    None => true,
+                })
+                .map(|(_, cell)| cell)
+                .collect();
+            Grid::save_cells(conn, changed);
         }
+
+        self.relocate_sealed_occupants(&regions_before, conn)
     }
 
-    /// Given a starting point, direction, field of view and distance, get the cells in this range
-    pub fn get_cells(
-        &self,
-        start_coords: &Coords,
+    /// Add rings to the outside of the grid until it reaches the new radius
+    pub fn grow(
+        &mut self,
+        size: u32,
+        conn: Option<&PgConnection>,
+    ) -> Result<Vec<Relocation>, String> {
+        let old_size = self.radius();
+        if size as i32 <= old_size {
+            return Err(format!(
+                "New size {} must be larger than the current size {}",
+                size, old_size
+            ));
+        }
+
+        self.edit_cells(conn, |cells| {
+            super::utils::grow_cells(cells, old_size, size as i32)
+        })
+    }
+
+    /// Carve or seal the edge on one side of a cell (and the matching side of its neighbor)
+    pub fn set_edge(
+        &mut self,
+        coords: &Coords,
         dir: Dir,
-        fov: i32,
-        distance: i32,
-    ) -> Vec<&GridCell> {
-        let mut found_cells = Vec::new();
+        edge_type: EdgeType,
+        conn: Option<&PgConnection>,
+    ) -> Result<Vec<Relocation>, String> {
+        if !self.cells.contains_key(coords) {
+            return Err(format!("No cell at {},{}", coords.q, coords.r));
+        }
+        if edge_type == EdgeType::Open && !self.cells.contains_key(&coords.to(&dir, 1)) {
+            return Err(String::from("Cannot open the outer wall of the grid"));
+        }
 
-        let start_arm_dir = dir.left(fov / 2);
+        self.edit_cells(conn, |cells| {
+            super::utils::create_edge_between_cells(cells, coords, &dir, edge_type)
+        })
+    }
 
-        // we want to sweep in arcs, moving out by radius to max distance
-        for r in 1..distance + 1 {
-            let mut coord = start_coords.to(&start_arm_dir, r);
-            let mut scan_dir = start_arm_dir.right(60);
+    /// Stamp a room template centered on the given coords.  If that walls the room
+    /// off, a passage is carved out of it to the main region of the grid
+    pub fn stamp_room(
+        &mut self,
+        coords: &Coords,
+        template: &RoomTemplate,
+        conn: Option<&PgConnection>,
+    ) -> Result<Vec<Relocation>, String> {
+        if !self.cells.contains_key(coords) {
+            return Err(format!("No cell at {},{}", coords.q, coords.r));
+        }
 
-            if let Some(cell) = self.cells.get(&coord) {
-                found_cells.push(cell);
+        let size = self.radius();
+        self.edit_cells(conn, |cells| {
+            super::utils::stamp_room(cells, coords, template);
+            super::utils::enforce_outer_walls(cells, size);
+
+            let main_region = super::utils::regions(cells)
+                .into_iter()
+                .max_by_key(|region| region.len())
+                .unwrap_or_default();
+            if !main_region.contains(coords) {
+                super::utils::carve_passage(cells, coords, &main_region);
             }
+        })
+    }
 
-            for step in (0..fov).step_by(60) {
-                scan_dir = scan_dir.right(60);
+    /// Load robot and valuable locations straight from the db; the server does this
+    /// with its own robots but tools working on the grid need it as well
+    pub fn load_occupants(&mut self, conn: Option<&PgConnection>) -> Result<(), String> {
+        if conn.is_none() {
+            return Err("No DB connection given".to_string());
+        }
+        let conn = conn.unwrap();
+
+        let robot_rows = robots::table
+            .inner_join(robot_modules::table)
+            .select((
+                robots::id,
+                robots::q,
+                robots::r,
+                robots::affiliation,
+                robot_modules::m_weapons,
+            ))
+            .load::<(i64, i32, i32, Option<i32>, String)>(conn)
+            .map_err(|e| format!("{}", e))?;
+        for (id, q, r, affiliation, weapons) in robot_rows {
+            self.robot_locs.insert(id, Coords { q, r });
+            self.robot_strengths
+                .insert(id, weapon::WeaponModule::get_max_damage(&weapons));
+            if let Some(affiliation) = affiliation {
+                self.robot_affiliations.insert(id, affiliation);
+            }
+        }
 
-                // if we are doing a 360 scan, we don't want duplicates so...
-                let mut total_steps = r + 1;
-                if step == 300 {
-                    total_steps = r;
-                }
-                for _ in 1..total_steps {
-                    coord = coord.to(&scan_dir, 1);
-                    if let Some(cell) = self.cells.get(&coord) {
-                        found_cells.push(cell);
-                    }
+        let valuable_rows = valuables::table
+            .select((valuables::id, valuables::q, valuables::r))
+            .load::<(i64, i32, i32)>(conn)
+            .map_err(|e| format!("{}", e))?;
+        for (id, q, r) in valuable_rows {
+            self.valuables_locs.insert(id, Coords { q, r });
+        }
+
+        Ok(())
+    }
+
+    /// The areas you can walk around in; anything outside them is walled in on its own cell
+    pub fn regions(&self) -> Vec<HashSet<Coords>> {
+        super::utils::regions(&self.cells)
+    }
+
+    /// The largest area you can walk around in
+    pub fn main_region(&self) -> HashSet<Coords> {
+        self.regions()
+            .into_iter()
+            .max_by_key(|region| region.len())
+            .unwrap_or_default()
+    }
+
+    /// Move any robots or valuables that an edit cut off from the region they were
+    /// in.  When a region is split, whatever ends up outside the largest part of it
+    /// is moved to a random open cell in that part; pockets that were already cut
+    /// off before the edit are left alone.  Errors if there is nowhere left to put
+    /// them or the move could not be saved; whatever was moved before that stays moved
+    pub fn relocate_sealed_occupants(
+        &mut self,
+        regions_before: &[HashSet<Coords>],
+        conn: Option<&PgConnection>,
+    ) -> Result<Vec<Relocation>, String> {
+        let regions_after = self.regions();
+        let region_after: HashMap<Coords, usize> = regions_after
+            .iter()
+            .enumerate()
+            .flat_map(|(index, region)| region.iter().map(move |coords| (*coords, index)))
+            .collect();
+
+        // where the bulk of a region ended up after the edit, if any of it is still open
+        let largest_part = |region: &HashSet<Coords>| -> Option<usize> {
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for coords in region {
+                if let Some(index) = region_after.get(coords) {
+                    *counts.entry(*index).or_insert(0) += 1;
                 }
             }
+            counts
+                .into_iter()
+                .max_by_key(|(_, count)| *count)
+                .map(|(index, _)| index)
+        };
+
+        // for anything that was cut off, the cells it should be moved to
+        let destination = |coords: &Coords| -> Option<&HashSet<Coords>> {
+            let before = regions_before.iter().find(|r| r.contains(coords))?;
+            match largest_part(before) {
+                Some(part) if region_after.get(coords) == Some(&part) => None,
+                Some(part) => Some(&regions_after[part]),
+                None => regions_after.iter().max_by_key(|region| region.len()),
+            }
+        };
+
+        let cut_off_robots: Vec<(Coords, i64, HashSet<Coords>)> = self
+            .robot_locs
+            .iter()
+            .filter_map(|(coords, id)| Some((*coords, *id, destination(coords)?.clone())))
+            .collect();
+        let cut_off_valuables: Vec<(Coords, i64, HashSet<Coords>)> = self
+            .valuables_locs
+            .iter()
+            .filter_map(|(coords, id)| Some((*coords, *id, destination(coords)?.clone())))
+            .collect();
+
+        let mut relocations = Vec::new();
+        for (from, id, region) in cut_off_robots {
+            let to = self.get_random_open_cell_in(&region).ok_or_else(|| {
+                format!(
+                    "No room to move robot {} off {},{} ({} moved before it)",
+                    id,
+                    from.q,
+                    from.r,
+                    relocations.len()
+                )
+            })?;
+            if let Some(conn) = conn {
+                diesel::update(robots::table.filter(robots::id.eq(id)))
+                    .set((robots::q.eq(to.q), robots::r.eq(to.r)))
+                    .execute(conn)
+                    .map_err(|e| format!("Could not move robot {}: {}", id, e))?;
+            }
+            self.update_robot_loc(id, to);
+            relocations.push(Relocation::Robot { id, from, to });
+        }
+
+        for (from, id, region) in cut_off_valuables {
+            let to = self.get_random_open_cell_in(&region).ok_or_else(|| {
+                format!(
+                    "No room to move valuable {} off {},{} ({} moved before it)",
+                    id,
+                    from.q,
+                    from.r,
+                    relocations.len()
+                )
+            })?;
+            if let Some(conn) = conn {
+                diesel::update(valuables::table.filter(valuables::id.eq(id)))
+                    .set((valuables::q.eq(to.q), valuables::r.eq(to.r)))
+                    .execute(conn)
+                    .map_err(|e| format!("Could not move valuable {}: {}", id, e))?;
+            }
+            self.valuables_locs.insert(id, to);
+            relocations.push(Relocation::Valuable { id, from, to });
         }
 
-        // add our current cell
-        if let Some(cell) = self.cells.get(&start_coords) {
-            found_cells.push(cell);
+        Ok(relocations)
+    }
+
+    /// Pick a random open, unoccupied cell out of the given ones
+    fn get_random_open_cell_in(&self, region: &HashSet<Coords>) -> Option<Coords> {
+        let mut rng = rand::thread_rng();
+
+        self.get_open_cells()
+            .into_iter()
+            .filter(|coords| region.contains(coords))
+            .collect::<Vec<Coords>>()
+            .choose(&mut rng)
+            .copied()
+    }
+
+    /// Get all the cells that aren't walled in and don't have a robot or valuable on them
+    pub fn get_open_cells(&self) -> Vec<Coords> {
+        self.cells
+            .iter()
+            .filter(|(coords, cell)| {
+                cell.is_open()
+                    && !self.robot_locs.contains_coords(coords)
+                    && !self.valuables_locs.contains_coords(coords)
+            })
+            .map(|(coords, _)| *coords)
+            .collect()
+    }
+
+    /// Check if a robot could stand on a cell: it's on the grid, isn't walled in
+    /// and has no robot on it already
+    pub fn is_free_for_robot(&self, coords: &Coords) -> bool {
+        self.cells.get(coords).is_some_and(|cell| cell.is_open())
+            && !self.robot_locs.contains_coords(coords)
+    }
+
+    /// Pick a random open, unoccupied cell; errors if there are none left
+    pub fn get_random_open_cell(&self) -> Result<Coords, String> {
+        let mut rng = rand::thread_rng();
+
+        self.get_open_cells()
+            .choose(&mut rng)
+            .copied()
+            .ok_or_else(|| String::from("No open cells left"))
+    }
+
+    /// Check if a robot is hostile to the given affiliation; robots without an
+    /// affiliation are hostile to everyone
+    pub fn is_hostile(&self, robot_id: &i64, affiliation: Option<i32>) -> bool {
+        match (self.robot_affiliations.get(robot_id), affiliation) {
+            (Some(theirs), Some(ours)) => *theirs != ours,
+            _ => true,
         }
+    }
+
+    /// Pick a cell to spawn a robot of the given affiliation
+    ///
+    /// If there are spawn zones for the affiliation, we only pick from those. Otherwise
+    /// we use the shared zones (those without an affiliation) and failing that, the
+    /// whole grid. The cell must be at least `min_hostile_distance` away from any
+    /// hostile robot.
+    pub fn get_spawn_cell(
+        &self,
+        affiliation: Option<i32>,
+        min_hostile_distance: i32,
+    ) -> Result<Coords, String> {
+        let mut zones: Vec<&SpawnZone> = self
+            .spawn_zones
+            .iter()
+            .filter(|z| affiliation.is_some() && z.affiliation == affiliation)
+            .collect();
+        if zones.is_empty() {
+            zones = self
+                .spawn_zones
+                .iter()
+                .filter(|z| z.affiliation.is_none())
+                .collect();
+        }
+
+        let candidates: Vec<Coords> = self
+            .get_open_cells()
+            .into_iter()
+            .filter(|coords| zones.is_empty() || zones.iter().any(|z| z.contains(coords)))
+            .filter(|coords| {
+                !self
+                    .robot_locs
+                    .within_radius(coords, min_hostile_distance - 1)
+                    .iter()
+                    .any(|(_, id)| self.is_hostile(id, affiliation))
+            })
+            .collect();
+
+        let mut rng = rand::thread_rng();
+        candidates.choose(&mut rng).copied().ok_or_else(|| {
+            if min_hostile_distance > 0 {
+                format!(
+                    "No open cells to spawn in at least {} away from hostile robots",
+                    min_hostile_distance
+                )
+            } else {
+                String::from("No open cells to spawn in")
+            }
+        })
+    }
+
+    /// Given a starting point, direction, field of view and distance, get the cells in this range
+    pub fn get_cells(
+        &self,
+        start_coords: &Coords,
+        dir: Dir,
+        fov: i32,
+        distance: i32,
+    ) -> Vec<&GridCell> {
+        start_coords
+            .cone(dir, fov, distance)
+            .filter_map(|coords| self.cells.get(&coords))
+            .collect()
+    }
 
-        found_cells
+    /// Check if there is a clear line of sight between two cells
+    pub fn has_line_of_sight(&self, from: &Coords, to: &Coords) -> bool {
+        super::utils::is_visible(from, to, &self.cells)
     }
 
     /// Change the robot location
-    pub fn update_robot_loc(&mut self, id: i64, old_coords: Coords, new_coords: Coords) {
-        self.robot_locs.remove(&old_coords);
-        self.robot_locs.insert(new_coords.clone(), id);
+    pub fn update_robot_loc(&mut self, id: i64, new_coords: Coords) {
+        self.robot_locs.insert(id, new_coords);
     }
 
     /// Get a robot id based on a location
     pub fn get_robot_id_by_loc(&self, coords: &Coords) -> Option<&i64> {
-        self.robot_locs.get(coords)
+        self.robot_locs.get_id(coords)
     }
 
     /// Get the coords of a robot by id
     pub fn get_coords_by_robot_id(&self, id: &i64) -> Option<&Coords> {
-        let mut coords: Option<&Coords> = None;
-        for coord in self.robot_locs.keys() {
-            let _id = self.robot_locs.get(coord);
-            if _id.is_none() {
-                continue;
-            }
-
-            if _id.unwrap() == id {
-                coords = Some(coord);
-            }
-        }
-
-        coords
+        self.robot_locs.get_coords(id)
     }
 
     /// Add a robot to the grid data
@@ -292,45 +612,36 @@ impl Grid {
         let id = robot.data.id;
         let weapon_strength = weapon::WeaponModule::get_max_damage(&robot.modules.m_weapons);
 
-        self.robot_locs.insert(coords, id);
+        self.robot_locs.insert(id, coords);
         self.robot_strengths.insert(id, weapon_strength);
+        if let Some(affiliation) = robot.data.affiliation {
+            self.robot_affiliations.insert(id, affiliation);
+        }
     }
 
     /// Remove a robot
     pub fn remove_robot_by_loc(&mut self, coords: &Coords) {
-        if let Some(id) = self.robot_locs.get(coords) {
-            self.robot_strengths.remove(id);
+        if let Some(id) = self.robot_locs.remove_by_coords(coords) {
+            self.robot_strengths.remove(&id);
+            self.robot_affiliations.remove(&id);
         }
-        self.robot_locs.remove(coords);
     }
 
     /// Remove a robot by id
     pub fn remove_robot_by_id(&mut self, id: &i64) {
         self.robot_strengths.remove(id);
-        let coords = self.get_coords_by_robot_id(id);
-
-        let mut _coords: Option<Coords> = None;
-        if coords.is_some() {
-            let __coords = coords.unwrap();
-            _coords = Some(Coords {
-                q: __coords.q,
-                r: __coords.r,
-            });
-        } else {
-            return ();
-        }
-
-        self.remove_robot_by_loc(&_coords.unwrap());
+        self.robot_affiliations.remove(id);
+        self.robot_locs.remove_by_id(id);
     }
 
     /// Get a valuable id based on a location
     pub fn get_valuable_id_by_loc(&self, coords: &Coords) -> Option<&i64> {
-        self.valuables_locs.get(coords)
+        self.valuables_locs.get_id(coords)
     }
 
     /// remove a valuable given a location
     pub fn remove_valuable_by_loc(&mut self, coords: &Coords) {
-        self.valuables_locs.remove(coords);
+        self.valuables_locs.remove_by_coords(coords);
     }
 }
 
@@ -366,3 +677,93 @@ fn test_cell_creation() {
             .len()
     );
 }
+
+#[test]
+fn test_grid_editing() {
+    let mut grid = Grid::new(4, None).unwrap();
+    let relocations = grid.grow(6, None).unwrap();
+
+    assert_eq!(127, grid.cells.len());
+    assert_eq!(6, grid.radius());
+    assert!(relocations.is_empty());
+    assert!(grid.grow(5, None).is_err());
+
+    let mut ids: Vec<i32> = grid.cells.values().map(|c| c.id).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(127, ids.len());
+
+    // open up the whole grid, then stamp a room around a robot and a valuable;
+    // the room gets a way out, so they stay where they are
+    let center = Coords { q: 0, r: 0 };
+    grid.stamp_room(&center, &RoomTemplate::Hexagon { radius: 6 }, None)
+        .unwrap();
+    grid.robot_locs.insert(7, center);
+    grid.valuables_locs.insert(8, center.neighbor(Dir::Orient0));
+    let relocations = grid
+        .stamp_room(&center, &RoomTemplate::Hexagon { radius: 1 }, None)
+        .unwrap();
+
+    assert!(relocations.is_empty());
+    assert!(grid.main_region().contains(&center));
+    assert_eq!(127, grid.main_region().len());
+
+    // a pocket that was already walled in is left alone by later edits
+    let pocket = Coords { q: 4, r: -2 };
+    for dir in Dir::get_vec() {
+        super::utils::create_edge_between_cells(&mut grid.cells, &pocket, &dir, EdgeType::Wall);
+    }
+    grid.valuables_locs.insert(9, pocket);
+
+    // but a robot walled in on its own cell is moved out
+    let mut relocations = Vec::new();
+    for dir in Dir::get_vec() {
+        relocations.extend(grid.set_edge(&center, dir, EdgeType::Wall, None).unwrap());
+    }
+
+    assert!(!grid.cells.get(&center).unwrap().is_open());
+    assert_eq!(1, relocations.len());
+    assert_eq!(None, grid.get_robot_id_by_loc(&center));
+    assert!(grid
+        .main_region()
+        .contains(grid.get_coords_by_robot_id(&7).unwrap()));
+    assert_eq!(Some(&pocket), grid.valuables_locs.get_coords(&9));
+}
+
+#[test]
+fn test_spawn_cells() {
+    let mut grid = Grid::new(2, None).unwrap();
+    grid.stamp_room(
+        &Coords { q: 0, r: 0 },
+        &RoomTemplate::Hexagon { radius: 2 },
+        None,
+    )
+    .unwrap();
+    assert_eq!(19, grid.get_open_cells().len());
+
+    // team 1 spawns in the top corner; everyone else shares the bottom corner
+    grid.spawn_zones
+        .push(SpawnZone::new("top", Some(1), Coords { q: 0, r: 2 }, 0));
+    grid.spawn_zones
+        .push(SpawnZone::new("bottom", None, Coords { q: 0, r: -2 }, 1));
+    assert_eq!(Ok(Coords { q: 0, r: 2 }), grid.get_spawn_cell(Some(1), 0));
+    for _ in 0..10 {
+        let coords = grid.get_spawn_cell(Some(2), 0).unwrap();
+        assert!(coords.distance_to(&Coords { q: 0, r: -2 }) <= 1);
+    }
+
+    // a hostile robot near the top keeps team 1 from spawning, but an ally doesn't
+    grid.robot_locs.insert(7, Coords { q: 0, r: 1 });
+    assert!(grid.get_spawn_cell(Some(1), 2).is_err());
+    grid.robot_affiliations.insert(7, 1);
+    assert_eq!(Ok(Coords { q: 0, r: 2 }), grid.get_spawn_cell(Some(1), 2));
+
+    // fill up the grid and we should get an error instead of hanging
+    grid.spawn_zones.clear();
+    let open_cells = grid.get_open_cells();
+    for (id, coords) in open_cells.into_iter().enumerate() {
+        grid.valuables_locs.insert(id as i64, coords);
+    }
+    assert!(grid.get_random_open_cell().is_err());
+    assert!(grid.get_spawn_cell(None, 0).is_err());
+}
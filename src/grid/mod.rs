@@ -1,9 +1,15 @@
 pub mod coords;
 pub mod edge;
 pub mod grid;
+pub mod render;
+pub mod spatial;
+pub mod spawn;
 pub mod utils;
 
 pub use coords::*;
 pub use edge::*;
 pub use grid::*;
+pub use render::*;
+pub use spatial::*;
+pub use spawn::*;
 pub use utils::*;
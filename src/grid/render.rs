@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::coords::*;
+use super::grid::GridCell;
+
+/// Pixels per unit of the flat2d coordinate space
+const SVG_SCALE: f64 = 12.0;
+
+/// Extra data drawn on top of the cells
+#[derive(Debug, Default)]
+pub struct RenderOverlay {
+    pub robots: Vec<CoordsAndDir>,
+    pub valuables: Vec<Coords>,
+    /// if set, these are the cells known by a selected robot
+    pub known_cells: Option<HashSet<Coords>>,
+}
+
+/// The glyphs to use when rendering to the terminal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+/// Get the corner of a cell at the given bearing (clockwise from vertical)
+fn corner(center: (f64, f64), bearing: i32) -> (f64, f64) {
+    // neighbors are 2 units apart so the inner radius is 1
+    let radius = 2.0 / 3f64.sqrt();
+    let angle = (bearing as f64).to_radians();
+
+    (
+        center.0 + radius * angle.sin(),
+        center.1 + radius * angle.cos(),
+    )
+}
+
+/// Convert flat2d coordinates into svg space, which has y pointing down
+fn to_svg(point: (f64, f64), min_x: f64, max_y: f64) -> (f64, f64) {
+    ((point.0 - min_x) * SVG_SCALE, (max_y - point.1) * SVG_SCALE)
+}
+
+/// Render the cells and overlays into an SVG document
+pub fn render_svg(cells: &HashMap<Coords, GridCell>, overlay: &RenderOverlay) -> String {
+    let margin = 2.0;
+    let mut min_x = 0.0f64;
+    let mut max_x = 0.0f64;
+    let mut min_y = 0.0f64;
+    let mut max_y = 0.0f64;
+    for coords in cells.keys() {
+        let (x, y) = coords.to_pixel(1.0);
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+    }
+    min_x -= margin;
+    max_x += margin;
+    min_y -= margin;
+    max_y += margin;
+
+    let width = (max_x - min_x) * SVG_SCALE;
+    let height = (max_y - min_y) * SVG_SCALE;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}">"#,
+        width, height, width, height
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#202020"/>"##
+    );
+
+    // draw the cells sorted so the output is stable between runs
+    let mut sorted: Vec<&GridCell> = cells.values().collect();
+    sorted.sort_by_key(|c| c.id);
+
+    for cell in &sorted {
+        let coords = Coords {
+            q: cell.q,
+            r: cell.r,
+        };
+        let center = coords.to_pixel(1.0);
+
+        let fill = if !cell.is_open() {
+            "#505050"
+        } else if overlay
+            .known_cells
+            .as_ref()
+            .is_some_and(|k| k.contains(&coords))
+        {
+            "#b8d8f0"
+        } else {
+            "#f0f0f0"
+        };
+
+        let points: Vec<String> = (0..6)
+            .map(|i| {
+                let (x, y) = to_svg(corner(center, i * 60 + 30), min_x, max_y);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}"><title>{},{}</title></polygon>"#,
+            points.join(" "),
+            fill,
+            cell.q,
+            cell.r
+        );
+    }
+
+    // the walls go on top of all the cells so neighbors don't paint over them
+    for cell in &sorted {
+        let center = Coords {
+            q: cell.q,
+            r: cell.r,
+        }
+        .to_pixel(1.0);
+
+        for dir in cell.get_walls() {
+            let bearing: i32 = dir.into();
+            let (x1, y1) = to_svg(corner(center, bearing - 30), min_x, max_y);
+            let (x2, y2) = to_svg(corner(center, bearing + 30), min_x, max_y);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#000000" stroke-width="2" stroke-linecap="round"/>"##,
+                x1, y1, x2, y2
+            );
+        }
+    }
+
+    for coords in &overlay.valuables {
+        let (x, y) = to_svg(coords.to_pixel(1.0), min_x, max_y);
+        let _ = writeln!(
+            svg,
+            r##"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="#e0b000"/>"##,
+            x,
+            y,
+            SVG_SCALE * 0.5
+        );
+    }
+
+    for robot in &overlay.robots {
+        // draw a triangle pointing in the direction the robot is facing
+        let center = robot.coords.to_pixel(1.0);
+        let bearing: i32 = robot.dir.into();
+        let points: Vec<String> = [0, 140, 220]
+            .iter()
+            .map(|offset| {
+                let angle = ((bearing + offset) as f64).to_radians();
+                let scale = if *offset == 0 { 0.8 } else { 0.5 };
+                let point = (
+                    center.0 + scale * angle.sin(),
+                    center.1 + scale * angle.cos(),
+                );
+                let (x, y) = to_svg(point, min_x, max_y);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r##"<polygon points="{}" fill="#d02020"><title>{},{}</title></polygon>"##,
+            points.join(" "),
+            robot.coords.q,
+            robot.coords.r
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Get the terminal position of a cell center
+///
+/// Neighbors straight up and down are 4 rows apart and diagonal neighbors
+/// are 4 columns and 2 rows apart, which leaves room for the walls in between
+fn text_pos(coords: &Coords) -> (i32, i32) {
+    (coords.q * 4, -2 * (coords.q + coords.r * 2))
+}
+
+/// Render the cells and overlays as text for the terminal; only useful for
+/// small grids since every cell takes up a 4x4 block of characters
+pub fn render_text(
+    cells: &HashMap<Coords, GridCell>,
+    overlay: &RenderOverlay,
+    charset: Charset,
+) -> String {
+    if cells.is_empty() {
+        return String::new();
+    }
+
+    let min_col = cells.keys().map(|c| text_pos(c).0).min().unwrap() - 3;
+    let max_col = cells.keys().map(|c| text_pos(c).0).max().unwrap() + 3;
+    let min_row = cells.keys().map(|c| text_pos(c).1).min().unwrap() - 2;
+    let max_row = cells.keys().map(|c| text_pos(c).1).max().unwrap() + 2;
+
+    let width = (max_col - min_col + 1) as usize;
+    let height = (max_row - min_row + 1) as usize;
+    let mut canvas: Vec<Vec<char>> = vec![vec![' '; width]; height];
+
+    let mut plot = |col: i32, row: i32, c: char| {
+        canvas[(row - min_row) as usize][(col - min_col) as usize] = c;
+    };
+
+    let (horizontal, rising, falling, open, closed, known, robot_default, valuable) = match charset
+    {
+        Charset::Ascii => ('_', '/', '\\', '.', '#', '+', 'R', '$'),
+        Charset::Unicode => ('─', '╱', '╲', '·', '█', '○', '●', '◆'),
+    };
+
+    for (coords, cell) in cells {
+        let (col, row) = text_pos(coords);
+
+        let center = if !cell.is_open() {
+            closed
+        } else if overlay
+            .known_cells
+            .as_ref()
+            .is_some_and(|k| k.contains(coords))
+        {
+            known
+        } else {
+            open
+        };
+        plot(col, row, center);
+
+        for dir in cell.get_walls() {
+            match dir {
+                Dir::Orient0 => {
+                    for offset in -1..2 {
+                        plot(col + offset, row - 2, horizontal);
+                    }
+                }
+                Dir::Orient60 => plot(col + 2, row - 1, falling),
+                Dir::Orient120 => plot(col + 2, row + 1, rising),
+                Dir::Orient180 => {
+                    for offset in -1..2 {
+                        plot(col + offset, row + 2, horizontal);
+                    }
+                }
+                Dir::Orient240 => plot(col - 2, row + 1, falling),
+                Dir::Orient300 => plot(col - 2, row - 1, rising),
+            }
+        }
+    }
+
+    for coords in &overlay.valuables {
+        if cells.contains_key(coords) {
+            let (col, row) = text_pos(coords);
+            plot(col, row, valuable);
+        }
+    }
+
+    for robot in &overlay.robots {
+        if cells.contains_key(&robot.coords) {
+            let (col, row) = text_pos(&robot.coords);
+            let glyph = match charset {
+                Charset::Ascii => robot_default,
+                Charset::Unicode => match robot.dir {
+                    Dir::Orient0 => '↑',
+                    Dir::Orient60 => '↗',
+                    Dir::Orient120 => '↘',
+                    Dir::Orient180 => '↓',
+                    Dir::Orient240 => '↙',
+                    Dir::Orient300 => '↖',
+                },
+            };
+            plot(col, row, glyph);
+        }
+    }
+
+    let mut text = String::new();
+    for line in canvas {
+        let line: String = line.into_iter().collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+use super::edge::EdgeType;
+
+#[cfg(test)]
+#[test]
+fn test_render_text() {
+    let mut cells: HashMap<Coords, GridCell> = HashMap::new();
+    let mut cell = GridCell::new(0, &Coords { q: 0, r: 0 });
+    cell.change_side(&Dir::Orient0, EdgeType::Open);
+    cells.insert(Coords { q: 0, r: 0 }, cell);
+
+    let mut cell = GridCell::new(1, &Coords { q: 0, r: 1 });
+    cell.change_side(&Dir::Orient180, EdgeType::Open);
+    cells.insert(Coords { q: 0, r: 1 }, cell);
+
+    let overlay = RenderOverlay {
+        robots: vec![CoordsAndDir {
+            coords: Coords { q: 0, r: 1 },
+            dir: Dir::Orient0,
+        }],
+        valuables: vec![Coords { q: 0, r: 0 }],
+        known_cells: None,
+    };
+
+    let text = render_text(&cells, &overlay, Charset::Ascii);
+    let lines: Vec<&str> = text.lines().collect();
+
+    // the upper cell has the robot and the lower cell has the valuable with no wall between
+    assert_eq!(lines[0].trim(), "___");
+    assert_eq!(lines[2].trim(), "R");
+    assert_eq!(lines[4].trim(), "");
+    assert_eq!(lines[6].trim(), "$");
+    assert_eq!(lines[8].trim(), "___");
+
+    let svg = render_svg(&cells, &overlay);
+    assert!(svg.starts_with("<svg"));
+    assert_eq!(svg.matches("<line").count(), 10);
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use super::coords::*;
+
+/// Number of cells within the given distance of a cell (including itself)
+fn area_of_radius(radius: i32) -> usize {
+    (3 * radius * (radius + 1) + 1) as usize
+}
+
+/// Tracks where things with ids (robots, valuables) are on the grid, with at
+/// most one thing per cell
+#[derive(Clone, Debug, Default)]
+pub struct SpatialIndex {
+    by_coords: HashMap<Coords, i64>,
+    by_id: HashMap<i64, Coords>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    /// Put an id at the given coords; anything already at those coords is dropped
+    /// and if the id was elsewhere, it is moved
+    pub fn insert(&mut self, id: i64, coords: Coords) {
+        self.remove_by_id(&id);
+        self.remove_by_coords(&coords);
+
+        self.by_coords.insert(coords, id);
+        self.by_id.insert(id, coords);
+    }
+
+    /// Remove an id, returning where it was
+    pub fn remove_by_id(&mut self, id: &i64) -> Option<Coords> {
+        let coords = self.by_id.remove(id)?;
+        self.by_coords.remove(&coords);
+
+        Some(coords)
+    }
+
+    /// Remove whatever is at the coords, returning its id
+    pub fn remove_by_coords(&mut self, coords: &Coords) -> Option<i64> {
+        let id = self.by_coords.remove(coords)?;
+        self.by_id.remove(&id);
+
+        Some(id)
+    }
+
+    pub fn get_id(&self, coords: &Coords) -> Option<&i64> {
+        self.by_coords.get(coords)
+    }
+
+    pub fn get_coords(&self, id: &i64) -> Option<&Coords> {
+        self.by_id.get(id)
+    }
+
+    pub fn contains_coords(&self, coords: &Coords) -> bool {
+        self.by_coords.contains_key(coords)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Coords, &i64)> {
+        self.by_coords.iter()
+    }
+
+    /// Get everything within the given distance of the center
+    pub fn within_radius(&self, center: &Coords, radius: i32) -> Vec<(Coords, i64)> {
+        if radius < 0 {
+            return Vec::new();
+        }
+
+        // for small areas we look up each cell; otherwise it's quicker to check everything
+        if area_of_radius(radius) <= self.len() {
+            self.lookup_all(center.spiral(radius))
+        } else {
+            self.by_coords
+                .iter()
+                .filter(|(coords, _)| center.distance_to(coords) <= radius)
+                .map(|(coords, id)| (*coords, *id))
+                .collect()
+        }
+    }
+
+    /// Get everything within a cone; see `Coords::cone` for the shape
+    pub fn within_cone(
+        &self,
+        start_coords: &Coords,
+        dir: Dir,
+        fov: i32,
+        distance: i32,
+    ) -> Vec<(Coords, i64)> {
+        self.lookup_all(start_coords.cone(dir, fov, distance))
+    }
+
+    /// Get the closest entry to the center, no farther than max_distance; ties go
+    /// to the lowest id
+    pub fn nearest(&self, center: &Coords, max_distance: i32) -> Option<(Coords, i64)> {
+        self.within_radius(center, max_distance)
+            .into_iter()
+            .min_by_key(|(coords, id)| (center.distance_to(coords), *id))
+    }
+
+    fn lookup_all(&self, coords: impl Iterator<Item = Coords>) -> Vec<(Coords, i64)> {
+        coords
+            .filter_map(|c| self.by_coords.get(&c).map(|id| (c, *id)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_spatial_index() {
+    let mut index = SpatialIndex::new();
+    index.insert(1, Coords { q: 0, r: 0 });
+    index.insert(2, Coords { q: 0, r: 2 });
+    index.insert(3, Coords { q: 3, r: -3 });
+    assert_eq!(3, index.len());
+
+    // moving an id clears its old spot
+    index.insert(1, Coords { q: 0, r: 1 });
+    assert_eq!(None, index.get_id(&Coords { q: 0, r: 0 }));
+    assert_eq!(Some(&Coords { q: 0, r: 1 }), index.get_coords(&1));
+
+    let center = Coords { q: 0, r: 0 };
+    let mut found = index.within_radius(&center, 2);
+    found.sort_by_key(|(_, id)| *id);
+    assert_eq!(
+        vec![(Coords { q: 0, r: 1 }, 1), (Coords { q: 0, r: 2 }, 2)],
+        found
+    );
+
+    // a straight line facing up only finds what is directly above
+    assert_eq!(2, index.within_cone(&center, Dir::Orient0, 0, 5).len());
+    assert!(index.within_cone(&center, Dir::Orient240, 0, 5).is_empty());
+    assert_eq!(1, index.within_cone(&center, Dir::Orient120, 0, 5).len());
+
+    assert_eq!(Some((Coords { q: 0, r: 1 }, 1)), index.nearest(&center, 10));
+    assert_eq!(
+        Some((Coords { q: 3, r: -3 }, 3)),
+        index.nearest(&Coords { q: 3, r: -2 }, 10)
+    );
+    assert_eq!(None, index.nearest(&Coords { q: -3, r: 0 }, 1));
+
+    assert_eq!(Some(2), index.remove_by_coords(&Coords { q: 0, r: 2 }));
+    assert_eq!(None, index.get_coords(&2));
+    assert_eq!(2, index.len());
+}
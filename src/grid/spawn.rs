@@ -0,0 +1,79 @@
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::Serialize;
+
+use super::coords::Coords;
+use crate::schema::*;
+
+/// A named area where robots spawn; zones without an affiliation are shared
+/// by anyone that doesn't have a zone of their own
+#[derive(Clone, Debug, PartialEq, Queryable, Insertable, Serialize)]
+#[table_name = "spawn_zones"]
+pub struct SpawnZone {
+    pub name: String,
+    pub affiliation: Option<i32>,
+    pub q: i32,
+    pub r: i32,
+    pub radius: i32,
+}
+
+impl SpawnZone {
+    pub fn new(name: &str, affiliation: Option<i32>, center: Coords, radius: i32) -> SpawnZone {
+        SpawnZone {
+            name: name.to_string(),
+            affiliation,
+            q: center.q,
+            r: center.r,
+            radius,
+        }
+    }
+
+    pub fn get_center(&self) -> Coords {
+        Coords {
+            q: self.q,
+            r: self.r,
+        }
+    }
+
+    /// Test if the coords fall inside this zone
+    pub fn contains(&self, coords: &Coords) -> bool {
+        self.get_center().distance_to(coords) <= self.radius
+    }
+
+    /// Load all the spawn zones out of the database
+    pub fn load_all(conn: Option<&PgConnection>) -> Result<Vec<SpawnZone>, String> {
+        if conn.is_none() {
+            return Err("No DB connection".to_string());
+        }
+
+        spawn_zones::table
+            .order(spawn_zones::name)
+            .load::<SpawnZone>(conn.unwrap())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Save the zone, replacing any zone with the same name
+    pub fn save(&self, conn: &PgConnection) -> Result<(), String> {
+        diesel::insert_into(spawn_zones::table)
+            .values(self)
+            .on_conflict(spawn_zones::name)
+            .do_update()
+            .set((
+                spawn_zones::affiliation.eq(self.affiliation),
+                spawn_zones::q.eq(self.q),
+                spawn_zones::r.eq(self.r),
+                spawn_zones::radius.eq(self.radius),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Delete a zone by name; returns false if there was no such zone
+    pub fn delete(conn: &PgConnection, name: &str) -> Result<bool, String> {
+        diesel::delete(spawn_zones::table.filter(spawn_zones::name.eq(name)))
+            .execute(conn)
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("{}", e))
+    }
+}
@@ -1,9 +1,11 @@
 pub mod traversal;
+pub mod visibility;
 pub use traversal::*;
+pub use visibility::*;
 
 use rand::seq::SliceRandom;
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use super::*;
 
@@ -16,21 +18,10 @@ pub fn generate_cells(size: i32) -> HashMap<Coords, GridCell> {
     cells.insert(Coords { q: 0, r: 0 }, root_cell);
 
     // CREATE CELLS
-    // for each radius
-    for radius in 1..(size + 1) as i32 {
-        // we start with the bottom left direction at radius distance
-        let mut coords = root_coords.to(&super::Dir::Orient240, radius);
-
-        for angle in (0..360).step_by(60) {
-            // note we never step to angle 360 b/c that's where we started
-            let dir: Dir = (angle as i32).into();
-            for _ in 0..radius {
-                coords = coords.to(&dir, 1);
-                cell_count += 1;
-                let cell = GridCell::new(cell_count, &coords);
-                cells.insert(coords.clone(), cell);
-            }
-        }
+    // ring by ring, out to the full size
+    for coords in root_coords.spiral(size).skip(1) {
+        cell_count += 1;
+        cells.insert(coords, GridCell::new(cell_count, &coords));
     }
 
     if size > 3 {
@@ -47,6 +38,170 @@ pub fn generate_cells(size: i32) -> HashMap<Coords, GridCell> {
     cells
 }
 
+/// Add rings to the outside of an existing set of cells, taking it from `old_size`
+/// to `new_size`.  New paths are carved out from the new rings, breaking through
+/// the old outer wall wherever they cross it.
+pub fn grow_cells(cells: &mut HashMap<Coords, GridCell>, old_size: i32, new_size: i32) {
+    let root_coords = Coords { q: 0, r: 0 };
+    let mut cell_count = cells.values().map(|c| c.id).max().unwrap_or(0);
+    let mut new_coords: Vec<Coords> = Vec::new();
+
+    for radius in (old_size + 1)..(new_size + 1) {
+        for coords in root_coords.ring(radius) {
+            cell_count += 1;
+            cells.insert(coords, GridCell::new(cell_count, &coords));
+            new_coords.push(coords);
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..(new_size - old_size) * 4 {
+        let start = *new_coords.choose(&mut rng).unwrap();
+        make_path_from(cells, start);
+    }
+
+    enforce_outer_walls(cells, new_size);
+}
+
+/// Shapes that can be stamped onto an existing grid
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoomTemplate {
+    /// An open hexagon with a wall around it
+    Hexagon { radius: i32 },
+    /// A straight corridor with walls along both sides
+    Corridor { dir: Dir, length: i32 },
+    /// Three corridors crossing at the center
+    Cross { length: i32 },
+}
+
+/// Stamp a room template onto the cells with its center at `root_coords`
+pub fn stamp_room(
+    cells: &mut HashMap<Coords, GridCell>,
+    root_coords: &Coords,
+    template: &RoomTemplate,
+) {
+    match template {
+        RoomTemplate::Hexagon { radius } => make_room(cells, root_coords, *radius),
+        RoomTemplate::Corridor { dir, length } => {
+            make_corridor(cells, root_coords, dir, *length);
+        }
+        RoomTemplate::Cross { length } => {
+            for dir in &[Dir::Orient0, Dir::Orient120, Dir::Orient240] {
+                make_corridor(cells, root_coords, dir, *length);
+            }
+            // the corridors walled off each other at the center
+            for dir in &[Dir::Orient0, Dir::Orient120, Dir::Orient240] {
+                open_edge_if_exists(cells, root_coords, dir);
+                open_edge_if_exists(cells, root_coords, &dir.get_opposite());
+            }
+        }
+    }
+}
+
+/// Split the open cells into the areas you can walk around in
+pub fn regions(cells: &HashMap<Coords, GridCell>) -> Vec<HashSet<Coords>> {
+    let mut seen: HashSet<Coords> = HashSet::new();
+    let mut regions = Vec::new();
+
+    for (start, cell) in cells {
+        if !cell.is_open() || seen.contains(start) {
+            continue;
+        }
+
+        let mut region = HashSet::new();
+        region.insert(*start);
+        let mut queue = vec![*start];
+        while let Some(coords) = queue.pop() {
+            let cell = &cells[&coords];
+            for dir in Dir::get_vec() {
+                let next = coords.neighbor(dir);
+                if cell.get_side(dir) == EdgeType::Open
+                    && cells.contains_key(&next)
+                    && region.insert(next)
+                {
+                    queue.push(next);
+                }
+            }
+        }
+
+        seen.extend(&region);
+        regions.push(region);
+    }
+
+    regions
+}
+
+/// Carve the shortest passage from `start` to the nearest of the `targets`,
+/// breaking through any walls on the way
+pub fn carve_passage(
+    cells: &mut HashMap<Coords, GridCell>,
+    start: &Coords,
+    targets: &HashSet<Coords>,
+) {
+    let mut came_from: HashMap<Coords, (Coords, Dir)> = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(*start);
+
+    let mut end = None;
+    while let Some(coords) = queue.pop_front() {
+        if targets.contains(&coords) {
+            end = Some(coords);
+            break;
+        }
+        for dir in Dir::get_vec() {
+            let next = coords.neighbor(dir);
+            if next != *start && cells.contains_key(&next) && !came_from.contains_key(&next) {
+                came_from.insert(next, (coords, dir));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut coords = match end {
+        Some(coords) => coords,
+        None => return,
+    };
+    while let Some((previous, dir)) = came_from.get(&coords).copied() {
+        create_edge_between_cells(cells, &previous, &dir, EdgeType::Open);
+        coords = previous;
+    }
+}
+
+/// Make a straight corridor through `root_coords`, extending `length` cells both ways
+fn make_corridor(
+    cells: &mut HashMap<Coords, GridCell>,
+    root_coords: &Coords,
+    dir: &Dir,
+    length: i32,
+) {
+    let start = root_coords.to(&dir.get_opposite(), length);
+
+    for step in 0..(length * 2 + 1) {
+        let coords = start.to(dir, step);
+        if !cells.contains_key(&coords) {
+            continue;
+        }
+
+        for side in Dir::get_vec() {
+            if side == *dir || side == dir.get_opposite() {
+                continue;
+            }
+            create_edge_between_cells(cells, &coords, &side, EdgeType::Wall);
+        }
+
+        if step < length * 2 {
+            open_edge_if_exists(cells, &coords, dir);
+        }
+    }
+}
+
+/// Open the edge between two cells, but only if there is a cell on the other side
+fn open_edge_if_exists(cells: &mut HashMap<Coords, GridCell>, coords: &Coords, dir: &Dir) {
+    if cells.contains_key(coords) && cells.contains_key(&coords.to(dir, 1)) {
+        create_edge_between_cells(cells, coords, dir, EdgeType::Open);
+    }
+}
+
 fn add_rooms(cells: &mut HashMap<Coords, GridCell>, size: i32) {
     let mut rng = rand::thread_rng();
 
@@ -67,30 +222,13 @@ fn add_rooms(cells: &mut HashMap<Coords, GridCell>, size: i32) {
 
 /// Make a room
 fn make_room(cells: &mut HashMap<Coords, GridCell>, root_coords: &Coords, size: i32) {
-    open_cell(cells, root_coords);
-
-    // CREATE CELLS
-    // for each radius
-    for radius in 1..(size + 1) as i32 {
-        // we start with the bottom left direction at radius distance
-        let mut coords = root_coords.to(&super::Dir::Orient240, radius);
-
-        for angle in (0..360).step_by(60) {
-            // note we never step to angle 360 b/c that's where we started
-            let dir: Dir = (angle as i32).into();
-            for step in 0..radius {
-                coords = coords.to(&dir, 1).clone();
-                if !cells.contains_key(&coords) {
-                    continue;
-                }
-
-                open_cell(cells, &coords);
-                if radius == size as i32 {
-                    enforce_perimeter_wall(cells, &coords, &dir, step == radius - 1);
-                }
-            }
+    for coords in root_coords.spiral(size) {
+        if cells.contains_key(&coords) {
+            open_cell(cells, &coords);
         }
     }
+
+    wall_in_ring(cells, root_coords, size);
 }
 
 /// Make a path
@@ -109,7 +247,18 @@ fn make_path(cells: &mut HashMap<Coords, GridCell>) {
         current_cell = cells.get_mut(&current_coord).unwrap();
     }
 
-    let walls = current_cell.get_walls();
+    make_path_from(cells, current_coord);
+}
+
+/// Make a path starting at the given coords, heading out through one of its walls
+fn make_path_from(cells: &mut HashMap<Coords, GridCell>, start: Coords) {
+    let mut rng = rand::thread_rng();
+    let mut current_coord = start;
+
+    let walls = cells.get(&current_coord).unwrap().get_walls();
+    if walls.is_empty() {
+        return;
+    }
     let mut current_dir = walls.choose(&mut rng).unwrap();
     let mut length = rng.gen_range(1, 10);
 
@@ -133,22 +282,27 @@ fn make_path(cells: &mut HashMap<Coords, GridCell>) {
 }
 
 /// Make sure our outer boundry has walls
-fn enforce_outer_walls(cells: &mut HashMap<Coords, GridCell>, size: i32) {
-    let root_coords = Coords { q: 0, r: 0 };
-    let mut coords = root_coords.to(&super::Dir::Orient240, size);
-
-    for angle in (0..360).step_by(60) {
-        // note we never step to angle 360 b/c that's where we started
-        let dir: Dir = (angle as i32).into();
-        for step in 0..size {
-            coords = coords.to(&dir, 1);
-            enforce_perimeter_wall(cells, &coords, &dir, step == size - 1);
+pub fn enforce_outer_walls(cells: &mut HashMap<Coords, GridCell>, size: i32) {
+    wall_in_ring(cells, &Coords { q: 0, r: 0 }, size);
+}
+
+/// Put walls on every side of the ring at `radius` that faces outward
+fn wall_in_ring(cells: &mut HashMap<Coords, GridCell>, center: &Coords, radius: i32) {
+    for coords in center.ring(radius) {
+        if !cells.contains_key(&coords) {
+            continue;
+        }
+
+        for dir in Dir::get_vec() {
+            if coords.neighbor(dir).distance_to(center) > radius {
+                create_edge_between_cells(cells, &coords, &dir, EdgeType::Wall);
+            }
         }
     }
 }
 
 /// Create a wall for a cell and it's neighbor
-fn create_edge_between_cells(
+pub fn create_edge_between_cells(
     cells: &mut HashMap<Coords, GridCell>,
     coords: &Coords,
     dir: &Dir,
@@ -163,58 +317,6 @@ fn create_edge_between_cells(
     }
 }
 
-fn enforce_perimeter_wall(
-    cells: &mut HashMap<Coords, GridCell>,
-    coords: &Coords,
-    dir: &Dir,
-    last: bool,
-) {
-    match dir {
-        Dir::Orient0 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient0, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient240, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient300, EdgeType::Wall);
-        }
-        Dir::Orient60 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient60, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient300, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient0, EdgeType::Wall);
-        }
-        Dir::Orient120 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient120, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient0, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient60, EdgeType::Wall);
-        }
-        Dir::Orient180 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient180, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient60, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient120, EdgeType::Wall);
-        }
-        Dir::Orient240 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient240, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient120, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient180, EdgeType::Wall);
-        }
-        Dir::Orient300 => {
-            if last {
-                create_edge_between_cells(cells, coords, &Dir::Orient300, EdgeType::Wall)
-            }
-            create_edge_between_cells(cells, coords, &Dir::Orient180, EdgeType::Wall);
-            create_edge_between_cells(cells, coords, &Dir::Orient240, EdgeType::Wall);
-        }
-    }
-}
-
 /// Open up all the cell walls
 fn open_cell(cells: &mut HashMap<Coords, GridCell>, coords: &Coords) {
     create_edge_between_cells(cells, coords, &Dir::Orient0, EdgeType::Open);
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
 
 use crate::grid::*;
 use crate::robot::*;
@@ -10,81 +11,311 @@ pub enum MoveStep {
     Right,
 }
 
-#[derive(Debug)]
-pub struct FromStep {
+/// A state in the path search; where we are standing and which way we face
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct PathState {
     coords: Coords,
     dir: Dir,
 }
 
-/// Get a depth and directional map from starting to end coords
-pub fn flood_map(
-    starting_coords: &Coords,
-    starting_orientation: &Dir,
+/// An entry in the open set of the path search, ordered so the BinaryHeap
+/// pops the lowest estimated total cost first
+#[derive(Debug, PartialEq, Eq)]
+struct PathNode {
+    estimate: u32,
+    cost: u32,
+    state: PathState,
+}
+
+impl Ord for PathNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed so the heap acts as a min-heap; on ties, prefer the node
+        // that has made the most progress already
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for PathNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Lower bound on the number of moves to get from a state to the target: we need
+/// at least one step per cell of distance, plus a turn if we aren't facing a
+/// direction that gets us closer
+fn estimate_moves(state: &PathState, target_coords: &Coords) -> u32 {
+    let distance = state.coords.distance_to(target_coords);
+    if distance == 0 {
+        return 0;
+    }
+
+    let ahead = state.coords.to(&state.dir, 1);
+    if ahead.distance_to(target_coords) < distance {
+        distance as u32
+    } else {
+        distance as u32 + 1
+    }
+}
+
+/// Get the states reachable in one move from the given state, along with
+/// the move and its cost
+fn next_states<F>(
+    state: &PathState,
+    known_cells_full: &HashMap<Coords, GridCell>,
+    cell_cost: &F,
+) -> Vec<(PathState, MoveStep, u32)>
+where
+    F: Fn(&Coords) -> u32,
+{
+    let cell = match known_cells_full.get(&state.coords) {
+        Some(cell) => cell,
+        None => return Vec::new(),
+    };
+
+    let mut neighbors = vec![
+        (
+            PathState {
+                coords: state.coords,
+                dir: state.dir.left(60),
+            },
+            MoveStep::Left,
+            1,
+        ),
+        (
+            PathState {
+                coords: state.coords,
+                dir: state.dir.right(60),
+            },
+            MoveStep::Right,
+            1,
+        ),
+    ];
+
+    let ahead = state.coords.to(&state.dir, 1);
+    if cell.get_side(state.dir) != EdgeType::Wall && known_cells_full.contains_key(&ahead) {
+        neighbors.push((
+            PathState {
+                coords: ahead,
+                dir: state.dir,
+            },
+            MoveStep::Forward,
+            1 + cell_cost(&ahead),
+        ));
+    }
+
+    neighbors
+}
+
+/// Follow the came_from map back from a state to the start and return the moves in order
+fn trace_moves(
+    came_from: &HashMap<PathState, (PathState, MoveStep)>,
+    end: &PathState,
+) -> Vec<MoveStep> {
+    let mut moves = Vec::new();
+    let mut state = *end;
+    while let Some((prev, step)) = came_from.get(&state) {
+        moves.push(step.clone());
+        state = *prev;
+    }
+    moves.reverse();
+
+    moves
+}
+
+/// Find the cheapest list of moves from a starting position to the target coords
+///
+/// Every move (a step forward or a 60 degree turn) takes a tick, so turns are
+/// weighed the same as steps. `cell_cost` adds an extra cost for entering a cell,
+/// which lets callers steer around cells they would rather avoid.
+pub fn plan_path<F>(
+    start: &CoordsAndDir,
     target_coords: &Coords,
     known_cells_full: &HashMap<Coords, GridCell>,
-) -> HashMap<Coords, FromStep> {
-    // frontier holds the cells we've discovered that need to be explored
-    let mut frontier: Vec<CoordsAndDir> = Vec::new();
-    frontier.push(CoordsAndDir {
-        coords: starting_coords.clone(),
-        dir: starting_orientation.clone(),
+    cell_cost: F,
+) -> Option<Vec<MoveStep>>
+where
+    F: Fn(&Coords) -> u32,
+{
+    let start_state = PathState {
+        coords: start.coords,
+        dir: start.dir,
+    };
+
+    let mut frontier = BinaryHeap::new();
+    frontier.push(PathNode {
+        estimate: estimate_moves(&start_state, target_coords),
+        cost: 0,
+        state: start_state,
     });
 
-    // came_from tracks how we got to each cell; start with our starting coords
-    let mut came_from: HashMap<Coords, FromStep> = HashMap::new();
-    came_from.insert(
-        starting_coords.clone(),
-        FromStep {
-            coords: starting_coords.clone(),
-            dir: Dir::Orient0,
-        },
-    );
+    // came_from tracks the state and move that got us to each state
+    let mut came_from: HashMap<PathState, (PathState, MoveStep)> = HashMap::new();
+    let mut costs: HashMap<PathState, u32> = HashMap::new();
+    costs.insert(start_state, 0);
+
+    while let Some(current) = frontier.pop() {
+        if current.state.coords == *target_coords {
+            return Some(trace_moves(&came_from, &current.state));
+        }
+
+        // skip stale entries that we've since found a cheaper way to
+        if costs
+            .get(&current.state)
+            .is_some_and(|cost| *cost < current.cost)
+        {
+            continue;
+        }
+
+        for (state, step, step_cost) in next_states(&current.state, known_cells_full, &cell_cost) {
+            let cost = current.cost + step_cost;
+            if costs.get(&state).is_some_and(|known| *known <= cost) {
+                continue;
+            }
 
-    while frontier.len() != 0 {
-        // take an undiscovered cell from the frontier
-        // we take from the start and not the end, in order to favor
-        // the direction we are facing
-        let current = frontier.remove(0);
-        if &current.coords == target_coords {
-            break;
+            costs.insert(state, cost);
+            came_from.insert(state, (current.state, step));
+            frontier.push(PathNode {
+                estimate: cost + estimate_moves(&state, target_coords),
+                cost,
+                state,
+            });
         }
+    }
+
+    None
+}
+
+/// The cost of getting from a starting position to every reachable cell, found
+/// with a single search so we can compare many targets at once
+#[derive(Debug)]
+pub struct DistanceField {
+    came_from: HashMap<PathState, (PathState, MoveStep)>,
+    costs: HashMap<PathState, u32>,
+    /// the cheapest state (i.e. arrival direction) for each cell
+    best: HashMap<Coords, PathState>,
+}
 
-        // load the full cell information
-        let cell = known_cells_full.get(&current.coords).unwrap();
-
-        // we now inspect all the edges, starting with whatever
-        // direction we came into the cell with, and then alternating
-        // left and right between larger and larger angles
-        for orientation in Dir::get_side_scan_iter(current.dir) {
-            // if the side isn't a wall...
-            if cell.get_side(orientation) != EdgeType::Wall {
-                // get coordinates for the adjacent cell
-                let new_coords = current.coords.to(&orientation, 1);
-                // if we've seen this cell, don't re add
-                if let Some(_) = came_from.get(&new_coords) {
+impl DistanceField {
+    /// Search outward from the starting position over all the given cells; the
+    /// costs are the same as for `plan_path`
+    pub fn new<F>(
+        start: &CoordsAndDir,
+        known_cells_full: &HashMap<Coords, GridCell>,
+        cell_cost: F,
+    ) -> DistanceField
+    where
+        F: Fn(&Coords) -> u32,
+    {
+        let start_state = PathState {
+            coords: start.coords,
+            dir: start.dir,
+        };
+
+        let mut frontier = BinaryHeap::new();
+        frontier.push(PathNode {
+            estimate: 0,
+            cost: 0,
+            state: start_state,
+        });
+
+        let mut came_from: HashMap<PathState, (PathState, MoveStep)> = HashMap::new();
+        let mut costs: HashMap<PathState, u32> = HashMap::new();
+        let mut best: HashMap<Coords, PathState> = HashMap::new();
+        costs.insert(start_state, 0);
+
+        while let Some(current) = frontier.pop() {
+            if costs
+                .get(&current.state)
+                .is_some_and(|cost| *cost < current.cost)
+            {
+                continue;
+            }
+
+            // the first time a cell comes off the frontier is the cheapest way there
+            best.entry(current.state.coords).or_insert(current.state);
+
+            for (state, step, step_cost) in
+                next_states(&current.state, known_cells_full, &cell_cost)
+            {
+                let cost = current.cost + step_cost;
+                if costs.get(&state).is_some_and(|known| *known <= cost) {
                     continue;
                 }
 
-                // since we haven't seen this cell adjacent cell before,
-                // add it to the frontier, noting our current orientation
-                // also add it to the tracking of how we get to this
-                // adjacent cell
-                if let Some(_) = known_cells_full.get(&new_coords) {
-                    frontier.push(CoordsAndDir {
-                        coords: new_coords.clone(),
-                        dir: orientation,
-                    });
-                    let from_step = FromStep {
-                        coords: current.coords.clone(),
-                        dir: orientation,
-                    };
-                    came_from.insert(new_coords, from_step);
-                }
+                costs.insert(state, cost);
+                came_from.insert(state, (current.state, step));
+                frontier.push(PathNode {
+                    estimate: cost,
+                    cost,
+                    state,
+                });
+            }
+        }
+
+        DistanceField {
+            came_from,
+            costs,
+            best,
+        }
+    }
+
+    /// Get the number of moves (plus any cell costs) to reach the coords
+    pub fn distance_to(&self, coords: &Coords) -> Option<u32> {
+        self.best
+            .get(coords)
+            .and_then(|state| self.costs.get(state))
+            .copied()
+    }
+
+    /// Check if there is a known path to the coords
+    pub fn is_reachable(&self, coords: &Coords) -> bool {
+        self.best.contains_key(coords)
+    }
+
+    /// Get the first move to make toward the coords; None if unreachable or
+    /// we are already there
+    pub fn first_step(&self, coords: &Coords) -> Option<MoveStep> {
+        self.path_to(coords)
+            .and_then(|moves| moves.into_iter().next())
+    }
+
+    /// Get the full list of moves to reach the coords
+    pub fn path_to(&self, coords: &Coords) -> Option<Vec<MoveStep>> {
+        self.best
+            .get(coords)
+            .map(|state| trace_moves(&self.came_from, state))
+    }
+
+    /// Of the given coords, get the one that is cheapest to reach
+    pub fn closest(&self, locs: &[Coords]) -> Option<Coords> {
+        locs.iter()
+            .filter_map(|coords| self.distance_to(coords).map(|d| (d, *coords)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, coords)| coords)
+    }
+}
+
+/// Get the cells a robot passes through (including where it starts) following the moves
+pub fn path_cells(start: &CoordsAndDir, moves: &[MoveStep]) -> Vec<Coords> {
+    let mut coords = start.coords;
+    let mut dir = start.dir;
+    let mut visited = vec![coords];
+    for step in moves {
+        match step {
+            MoveStep::Left => dir = dir.left(60),
+            MoveStep::Right => dir = dir.right(60),
+            MoveStep::Forward => {
+                coords = coords.to(&dir, 1);
+                visited.push(coords);
             }
         }
     }
 
-    came_from
+    visited
 }
 
 /// add the steps to spin from one orientation to the other
@@ -114,59 +345,6 @@ pub fn find_spin(start_orientation: Dir, end_orientation: Dir) -> Vec<MoveStep>
     steps
 }
 
-/// given our list of cells and where we transition into those cells
-/// traverse this from start to end
-pub fn depth_to_path(
-    came_from: &HashMap<Coords, FromStep>,
-    target_coords: Coords,
-    starting_coords: Coords,
-) -> Result<Vec<&FromStep>, String> {
-    let mut path: Vec<&FromStep> = Vec::new();
-
-    let mut current = match came_from.get(&target_coords) {
-        Some(op_fromstep) => op_fromstep,
-        None => {
-            return Err(String::from(
-                "Error: couldn't find the target coords in the depth map",
-            ));
-        }
-    };
-
-    while current.coords != starting_coords {
-        path.push(current);
-        current = match came_from.get(&current.coords) {
-            Some(op_fromstep) => op_fromstep,
-            None => {
-                return Err(String::from(
-                    "Error: couldn't get a step when traversing depth map",
-                ));
-            }
-        };
-    }
-
-    path.push(current);
-    path.reverse();
-
-    Ok(path)
-}
-
-/// Convert the path into movement steps; for each node we match orientation and then
-/// take a step forward
-pub fn path_to_moves(start: CoordsAndDir, path: &Vec<&FromStep>) -> Result<Vec<MoveStep>, String> {
-    let mut moves: Vec<MoveStep> = Vec::new();
-    let mut current_orientation = start.dir;
-
-    for step in path {
-        if current_orientation != step.dir {
-            moves.append(&mut find_spin(current_orientation, step.dir));
-        }
-        moves.push(MoveStep::Forward);
-        current_orientation = step.dir;
-    }
-
-    Ok(moves)
-}
-
 // Given a target coordinate, find a path there using only known cells by this robot
 pub fn find_path(
     robot: &Robot,
@@ -179,94 +357,53 @@ pub fn find_path(
     } else {
         known_cells_full = robot.get_known_unoccupied_cells();
     }
-    let starting_coords = Coords {
-        q: robot.data.q,
-        r: robot.data.r,
+    let start = CoordsAndDir {
+        coords: robot.get_coords(),
+        dir: robot.data.orientation,
     };
 
-    // Get a flood map so we know how we would get to each cell
-    let came_from: HashMap<Coords, FromStep> = flood_map(
-        &starting_coords,
-        &robot.data.orientation,
-        &target_coords,
-        &known_cells_full,
-    );
+    // steer clear of where we remember seeing threats
+    let threat_cost = |coords: &Coords| robot.sightings.threat_cost(coords);
 
-    // Get the path in FromStep vector
-    let path = depth_to_path(&came_from, target_coords.clone(), starting_coords.clone());
-    if path.is_err() {
-        return Err(path.err().unwrap());
+    match plan_path(&start, &target_coords, &known_cells_full, threat_cost) {
+        Some(moves) => Ok(moves),
+        None => Err(String::from(
+            "Error: couldn't find a path to the target coords",
+        )),
     }
-    let path = path.unwrap();
-
-    let steps = path_to_moves(
-        CoordsAndDir {
-            coords: Coords {
-                q: robot.data.q,
-                r: robot.data.r,
-            },
-            dir: robot.data.orientation,
-        },
-        &path,
-    );
-
-    // println!("PATH FROM: {:?} to {:?}", starting_coords.clone(), target_coords.clone());
-    // for step in &path {
-    //     print!("({},{}) @ {:?} -> ", step.coords.q, step.coords.r, step.dir);
-    // }
-    // println!("PATH LENGTH: {}", path.len());
-    // println!("PATH STEPS: {:?}", steps);
-
-    steps
 }
 
-/// Test if a coordinate is reachable from a starting point within a specified number of steps
-pub fn is_reachable(
-    starting_coords: &Coords,
-    target_coords: &Coords,
-    known_cells_full: &HashMap<Coords, GridCell>,
-    steps: i32,
-) -> bool {
-    // Get a flood map so we know how we would get to each cell
-    // Note the starting direction here won't matter since we never convert this to steps
-    let came_from: HashMap<Coords, FromStep> = flood_map(
-        &starting_coords,
-        &Dir::Orient0,
-        &target_coords,
-        known_cells_full,
-    );
-
-    // Get the path in FromStep vector
-    let path = depth_to_path(&came_from, target_coords.clone(), starting_coords.clone());
-    if path.is_err() {
-        return false;
-    }
+/// Get a distance field over the cells known by this robot, starting from
+/// where the robot is standing
+pub fn distance_field(robot: &Robot, ignore_others: bool) -> DistanceField {
+    let known_cells_full = if ignore_others {
+        robot.get_known_cells()
+    } else {
+        robot.get_known_unoccupied_cells()
+    };
+    let start = CoordsAndDir {
+        coords: robot.get_coords(),
+        dir: robot.data.orientation,
+    };
 
-    path.unwrap().len() <= steps as usize
+    DistanceField::new(&start, &known_cells_full, |coords| {
+        robot.sightings.threat_cost(coords)
+    })
 }
 
 // given a list of coords, pick the one that's closest
 // If `reachable` is true, we must be able to find a path to the coords based on
 // what is in memory
 pub fn find_closest_coords(robot: &Robot, locs: Vec<Coords>, reachable: bool) -> Option<Coords> {
-    let coords = Coords {
-        q: robot.data.q,
-        r: robot.data.r,
-    };
-    let mut closest: Option<Coords> = None;
-    let mut shortest_distance = 100;
-    for coord in locs {
-        if reachable && traversal::find_path(robot, coord, false).is_err() {
-            continue;
-        }
-        let distance = coords.distance_to(&coord);
-        if distance < shortest_distance {
-            closest = Some(coord);
-            shortest_distance = distance;
-        }
+    // when the coords must be reachable, we measure by the number of moves
+    // it takes to get there instead of straight line distance
+    if reachable {
+        return distance_field(robot, false).closest(&locs);
     }
 
-    closest
+    let coords = robot.get_coords();
+    locs.into_iter()
+        .min_by_key(|coord| coords.distance_to(coord))
 }
 
 // given a list of coords, pick the one that's closest
@@ -289,10 +426,16 @@ pub fn find_farthest_coords(
         Some(coords) => coords,
     };
 
+    let field = if reachable {
+        Some(distance_field(robot, false))
+    } else {
+        None
+    };
+
     let mut farthest: Option<Coords> = None;
     let mut farthest_distance = 0;
     for coord in locs {
-        if reachable && traversal::find_path(robot, coord, false).is_err() {
+        if field.as_ref().is_some_and(|f| !f.is_reachable(&coord)) {
             continue;
         }
         let distance = coords.distance_to(&coord);
@@ -325,3 +468,118 @@ fn test_spins() {
         [MoveStep::Right, MoveStep::Right, MoveStep::Right]
     );
 }
+
+#[cfg(test)]
+fn make_open_room(radius: i32) -> HashMap<Coords, GridCell> {
+    let mut cells = HashMap::new();
+    let mut id = 0;
+    for q in -radius..(radius + 1) {
+        for r in -radius..(radius + 1) {
+            let coords = Coords { q, r };
+            if coords.distance_to(&Coords { q: 0, r: 0 }) <= radius {
+                cells.insert(coords, GridCell::new(id, &coords));
+                id += 1;
+            }
+        }
+    }
+
+    let all: Vec<Coords> = cells.keys().cloned().collect();
+    for coords in all {
+        for dir in Dir::get_vec() {
+            if cells.contains_key(&coords.to(&dir, 1)) {
+                create_edge_between_cells(&mut cells, &coords, &dir, EdgeType::Open);
+            }
+        }
+    }
+
+    cells
+}
+
+#[test]
+fn test_plan_path() {
+    let mut cells = make_open_room(2);
+    let start = CoordsAndDir {
+        coords: Coords { q: 0, r: -2 },
+        dir: Dir::Orient60,
+    };
+    let target = Coords { q: 0, r: 2 };
+
+    // one turn and then straight up is quicker than any zig zag of the same length
+    let moves = plan_path(&start, &target, &cells, |_| 0).unwrap();
+    assert_eq!(
+        moves,
+        vec![
+            MoveStep::Left,
+            MoveStep::Forward,
+            MoveStep::Forward,
+            MoveStep::Forward,
+            MoveStep::Forward,
+        ]
+    );
+    assert_eq!(path_cells(&start, &moves).last(), Some(&target));
+
+    // an expensive cell in the middle gets routed around
+    let center = Coords { q: 0, r: 0 };
+    let moves = plan_path(
+        &start,
+        &target,
+        &cells,
+        |c| if *c == center { 10 } else { 0 },
+    )
+    .unwrap();
+    let visited = path_cells(&start, &moves);
+    assert_eq!(visited.last(), Some(&target));
+    assert!(!visited.contains(&center));
+
+    // wall off the target completely and there is no path
+    for dir in Dir::get_vec() {
+        create_edge_between_cells(&mut cells, &target, &dir, EdgeType::Wall);
+    }
+    assert_eq!(plan_path(&start, &target, &cells, |_| 0), None);
+}
+
+#[test]
+fn test_distance_field() {
+    let mut cells = make_open_room(2);
+    let start = CoordsAndDir {
+        coords: Coords { q: 0, r: -2 },
+        dir: Dir::Orient60,
+    };
+    let field = DistanceField::new(&start, &cells, |_| 0);
+
+    // every cell in the room is reachable and agrees with a single path search
+    for coords in cells.keys() {
+        let moves = plan_path(&start, coords, &cells, |_| 0).unwrap();
+        assert_eq!(field.distance_to(coords), Some(moves.len() as u32));
+        assert_eq!(
+            path_cells(&start, &field.path_to(coords).unwrap()).last(),
+            Some(coords)
+        );
+    }
+
+    assert_eq!(field.distance_to(&start.coords), Some(0));
+    assert_eq!(field.first_step(&start.coords), None);
+    assert_eq!(
+        field.first_step(&Coords { q: 1, r: -2 }),
+        Some(MoveStep::Forward)
+    );
+    assert_eq!(
+        field.first_step(&Coords { q: 0, r: 2 }),
+        Some(MoveStep::Left)
+    );
+
+    // the cell straight ahead is closer in moves than the one behind us
+    let ahead = Coords { q: 2, r: -2 };
+    let behind = Coords { q: -1, r: -1 };
+    assert_eq!(field.closest(&[behind, ahead]), Some(ahead));
+
+    // walled off cells aren't reachable
+    let target = Coords { q: 0, r: 2 };
+    for dir in Dir::get_vec() {
+        create_edge_between_cells(&mut cells, &target, &dir, EdgeType::Wall);
+    }
+    let field = DistanceField::new(&start, &cells, |_| 0);
+    assert!(!field.is_reachable(&target));
+    assert_eq!(field.path_to(&target), None);
+    assert_eq!(field.closest(&[target]), None);
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crate::grid::*;
+
+/// How far to push the line off of the cell centers so it never runs exactly
+/// along an edge between two cells
+const NUDGE: f64 = 1e-6;
+
+/// Check that nothing blocks a line of cells; every cell must be known and no
+/// wall can sit between consecutive cells
+fn is_line_clear(line: &[Coords], cells: &HashMap<Coords, GridCell>) -> bool {
+    for pair in line.windows(2) {
+        let cell = cells.get(&pair[0]);
+        let next_cell = cells.get(&pair[1]);
+        if cell.is_none() || next_cell.is_none() {
+            return false;
+        }
+
+        let dir = match pair[0].neighbor_dir(&pair[1]) {
+            Some(dir) => dir,
+            None => return false,
+        };
+
+        if cell.unwrap().get_side(dir) == EdgeType::Wall
+            || next_cell.unwrap().get_side(dir.get_opposite()) == EdgeType::Wall
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Test if the target can be seen from the starting coords, i.e. a straight line
+/// between the two cell centers doesn't cross any walls
+///
+/// When the line runs exactly along the edges between cells, we try it nudged
+/// to either side and count it as visible if either way is clear
+pub fn is_visible(
+    starting_coords: &Coords,
+    target_coords: &Coords,
+    cells: &HashMap<Coords, GridCell>,
+) -> bool {
+    if !cells.contains_key(starting_coords) || !cells.contains_key(target_coords) {
+        return false;
+    }
+
+    is_line_clear(&starting_coords.line_to_nudged(target_coords, NUDGE), cells)
+        || is_line_clear(
+            &starting_coords.line_to_nudged(target_coords, -NUDGE),
+            cells,
+        )
+}
+
+#[cfg(test)]
+fn make_cells(coords: &[Coords]) -> HashMap<Coords, GridCell> {
+    coords
+        .iter()
+        .enumerate()
+        .map(|(id, c)| (*c, GridCell::new(id as i32, c)))
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn test_visibility_straight_corridor() {
+    let coords: Vec<Coords> = (0..4).map(|r| Coords { q: 0, r }).collect();
+    let mut cells = make_cells(&coords);
+    for c in &coords[0..3] {
+        create_edge_between_cells(&mut cells, c, &Dir::Orient0, EdgeType::Open);
+    }
+
+    assert!(is_visible(&coords[0], &coords[3], &cells));
+    assert!(is_visible(&coords[3], &coords[0], &cells));
+
+    // a wall half way down the corridor blocks the view
+    create_edge_between_cells(&mut cells, &coords[1], &Dir::Orient0, EdgeType::Wall);
+    assert!(is_visible(&coords[0], &coords[1], &cells));
+    assert!(!is_visible(&coords[0], &coords[2], &cells));
+    assert!(!is_visible(&coords[0], &coords[3], &cells));
+}
+
+#[test]
+fn test_visibility_around_corner() {
+    // an L shaped corridor: up from the origin, then off to the upper right
+    let path = vec![
+        Coords { q: 0, r: 0 },
+        Coords { q: 0, r: 1 },
+        Coords { q: 0, r: 2 },
+        Coords { q: 1, r: 2 },
+        Coords { q: 2, r: 2 },
+    ];
+    let mut all = path.clone();
+    all.push(Coords { q: 1, r: 1 });
+    let mut cells = make_cells(&all);
+    for pair in path.windows(2) {
+        let dir = pair[0].neighbor_dir(&pair[1]).unwrap();
+        create_edge_between_cells(&mut cells, &pair[0], &dir, EdgeType::Open);
+    }
+
+    // the end of the corridor is as many steps away as it is far, so a path
+    // search would count it as visible, but the straight line crosses walls
+    let start = CoordsAndDir {
+        coords: path[0],
+        dir: Dir::Orient0,
+    };
+    let moves = plan_path(&start, &path[4], &cells, |_| 0).unwrap();
+    let steps = moves.iter().filter(|m| **m == MoveStep::Forward).count();
+    assert_eq!(4, path[0].distance_to(&path[4]));
+    assert_eq!(4, steps);
+    assert!(!is_visible(&path[0], &path[4], &cells));
+
+    // we can see past the corner into the cell right around it
+    assert!(is_visible(&path[1], &path[3], &cells));
+}
+
+#[test]
+fn test_visibility_thin_gap() {
+    // a room with a single open cell between the two walled in cells
+    let coords = vec![
+        Coords { q: 0, r: 0 },
+        Coords { q: 0, r: 1 },
+        Coords { q: 1, r: 0 },
+        Coords { q: 1, r: 1 },
+    ];
+    let mut cells = make_cells(&coords);
+    create_edge_between_cells(&mut cells, &coords[0], &Dir::Orient0, EdgeType::Open);
+    create_edge_between_cells(&mut cells, &coords[1], &Dir::Orient60, EdgeType::Open);
+
+    // the line from (0,0) to (1,1) grazes both (0,1) and (1,0); only the first is open
+    assert!(is_visible(&coords[0], &coords[3], &cells));
+
+    create_edge_between_cells(&mut cells, &coords[1], &Dir::Orient60, EdgeType::Wall);
+    create_edge_between_cells(&mut cells, &coords[0], &Dir::Orient60, EdgeType::Open);
+    create_edge_between_cells(&mut cells, &coords[2], &Dir::Orient0, EdgeType::Open);
+    assert!(is_visible(&coords[0], &coords[3], &cells));
+
+    create_edge_between_cells(&mut cells, &coords[2], &Dir::Orient0, EdgeType::Wall);
+    assert!(!is_visible(&coords[0], &coords[3], &cells));
+}
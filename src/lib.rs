@@ -6,6 +6,9 @@ extern crate tokio;
 
 pub mod db;
 pub mod grid;
+pub mod logging;
+pub mod metrics;
+pub mod owner;
 pub mod robot;
 pub mod schema;
 pub mod server;
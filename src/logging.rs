@@ -0,0 +1,109 @@
+use clap::{App, Arg, ArgMatches};
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// How log events are written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// one readable line per event, with the spans it happened in
+    Human,
+    /// one JSON object per event, with the fields of the spans it happened in
+    Json,
+}
+
+/// What gets logged and how.  Events from ticking a robot happen in a `robot`
+/// span with its `id` and `process`, inside a `tick` span with the tick number,
+/// so the filter can turn up the detail on a single robot, e.g.
+/// `info,ares[robot{id=1004}]=trace`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    /// which events to keep, as `tracing` filter directives
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            filter: "info".to_string(),
+            format: LogFormat::Human,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Build the config from arguments added with `add_log_args`
+    pub fn from_matches(matches: &ArgMatches) -> Result<LogConfig, String> {
+        let filter = matches.value_of("log").unwrap_or("info").to_string();
+        EnvFilter::try_new(&filter).map_err(|e| format!("Bad log filter {}: {}", filter, e))?;
+
+        let format = match matches.value_of("log_format").unwrap_or("human") {
+            "human" => LogFormat::Human,
+            "json" => LogFormat::Json,
+            other => return Err(format!("Unknown log format {}", other)),
+        };
+
+        Ok(LogConfig { filter, format })
+    }
+}
+
+/// Add the arguments for what gets logged and how
+pub fn add_log_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("log")
+            .long("log")
+            .takes_value(true)
+            .env("ARES_LOG")
+            .default_value("info")
+            .help("What to log, e.g. warn or info,ares[robot{id=1004}]=trace"),
+    )
+    .arg(
+        Arg::with_name("log_format")
+            .long("log_format")
+            .takes_value(true)
+            .possible_values(&["human", "json"])
+            .default_value("human")
+            .help("Write logs for people or as JSON"),
+    )
+}
+
+/// Start writing log events to stdout; only the first call in a process does anything
+pub fn init(config: &LogConfig) {
+    let filter = EnvFilter::try_new(&config.filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let logger = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(std::io::stdout().is_terminal());
+
+    let _ = match config.format {
+        LogFormat::Human => logger.try_init(),
+        LogFormat::Json => logger
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+}
+
+#[cfg(test)]
+#[test]
+fn test_log_config() {
+    let matches = |args: &[&str]| {
+        add_log_args(App::new("test"))
+            .get_matches_from_safe(std::iter::once("test").chain(args.iter().cloned()))
+            .unwrap()
+    };
+
+    let config = LogConfig::from_matches(&matches(&[])).unwrap();
+    assert_eq!(LogConfig::default(), config);
+
+    let config = LogConfig::from_matches(&matches(&[
+        "--log",
+        "warn,ares[robot{id=1004}]=trace",
+        "--log_format",
+        "json",
+    ]))
+    .unwrap();
+    assert_eq!(LogFormat::Json, config.format);
+
+    assert!(LogConfig::from_matches(&matches(&["--log", "ares=loud"])).is_err());
+}
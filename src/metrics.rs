@@ -0,0 +1,225 @@
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::error;
+
+use crate::robot::Robot;
+
+/// Everything we measure about the running simulation, in the Prometheus text
+/// format on `/metrics`
+pub struct Metrics {
+    registry: Registry,
+
+    pub ticks: IntCounter,
+    pub tick_duration: Histogram,
+    /// ticks that took longer than the tick length
+    pub tick_overruns: IntCounter,
+
+    /// robots on the grid by active process, as of the last tick
+    pub robots: IntGaugeVec,
+    /// robots on the grid by module in each slot, as of the last tick
+    pub robot_modules: IntGaugeVec,
+    /// robot ticks by the process the robot ran
+    pub robot_ticks: IntCounterVec,
+    pub valuables: IntGauge,
+
+    pub spawns: IntCounter,
+    pub explosions: IntCounter,
+    pub exfiltrations: IntCounter,
+    /// attacks the server carried out, by whether they hit
+    pub attacks: IntCounterVec,
+    pub damage: IntCounter,
+    pub mined: IntCounter,
+
+    /// database queries by the table they are on; the count is the number of queries
+    pub db_queries: HistogramVec,
+
+    pub listeners: IntGauge,
+    pub controllers: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some("ares".to_string()), None)
+            .expect("Could not create metrics registry");
+
+        fn register<T: prometheus::core::Collector + Clone + 'static>(
+            registry: &Registry,
+            metric: prometheus::Result<T>,
+        ) -> T {
+            let metric = metric.expect("Bad metric");
+            registry
+                .register(Box::new(metric.clone()))
+                .expect("Could not register metric");
+            metric
+        }
+
+        Metrics {
+            ticks: register(
+                &registry,
+                IntCounter::new("ticks_total", "Ticks the server has run"),
+            ),
+            tick_duration: register(
+                &registry,
+                Histogram::with_opts(
+                    HistogramOpts::new("tick_duration_seconds", "Time spent working on a tick")
+                        .buckets(vec![0.01, 0.05, 0.1, 0.25, 0.5, 0.75, 1.0, 2.0, 5.0]),
+                ),
+            ),
+            tick_overruns: register(
+                &registry,
+                IntCounter::new(
+                    "tick_overruns_total",
+                    "Ticks that took longer than the tick length",
+                ),
+            ),
+            robots: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("robots", "Robots on the grid by active process"),
+                    &["process"],
+                ),
+            ),
+            robot_modules: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("robot_modules", "Robots on the grid by module in each slot"),
+                    &["slot", "module"],
+                ),
+            ),
+            robot_ticks: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("robot_ticks_total", "Robot ticks by active process"),
+                    &["process"],
+                ),
+            ),
+            valuables: register(
+                &registry,
+                IntGauge::new("valuables", "Piles of valuables on the grid"),
+            ),
+            spawns: register(
+                &registry,
+                IntCounter::new("robots_spawned_total", "Robots spawned"),
+            ),
+            explosions: register(
+                &registry,
+                IntCounter::new("robots_exploded_total", "Robots that exploded"),
+            ),
+            exfiltrations: register(
+                &registry,
+                IntCounter::new("robots_exfiltrated_total", "Robots that exfiltrated"),
+            ),
+            attacks: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("attacks_total", "Attacks by whether they hit"),
+                    &["outcome"],
+                ),
+            ),
+            damage: register(
+                &registry,
+                IntCounter::new("damage_total", "Hull damage done by attacks"),
+            ),
+            mined: register(
+                &registry,
+                IntCounter::new("valuables_mined_total", "Valuables mined by robots"),
+            ),
+            db_queries: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("db_query_duration_seconds", "Database queries by table")
+                        .buckets(vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1]),
+                    &["table"],
+                ),
+            ),
+            listeners: register(
+                &registry,
+                IntGauge::new("listeners", "Connected websocket listeners"),
+            ),
+            controllers: register(
+                &registry,
+                IntGauge::new("controllers", "Connected remote controllers"),
+            ),
+            registry,
+        }
+    }
+
+    /// Count the robots on the grid by process and by module
+    pub fn record_robots<'a>(&self, robots: impl Iterator<Item = &'a Robot>) {
+        self.robots.reset();
+        self.robot_modules.reset();
+
+        for robot in robots {
+            self.robots.with_label_values(&[&process_name(robot)]).inc();
+
+            let modules = &robot.modules;
+            let slots = [
+                ("collector", &modules.m_collector),
+                ("comms", &modules.m_comms),
+                ("drivesystem", &modules.m_drivesystem),
+                ("exfilbeacon", &modules.m_exfilbeacon),
+                ("hull", &modules.m_hull),
+                ("memory", &modules.m_memory),
+                ("power", &modules.m_power),
+                ("scanner", &modules.m_scanner),
+                ("weapons", &modules.m_weapons),
+            ];
+            for (slot, module) in slots.iter() {
+                self.robot_modules.with_label_values(&[slot, module]).inc();
+            }
+        }
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Could not encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// The metrics for this process
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// The label for a robot's active process
+pub fn process_name(robot: &Robot) -> String {
+    match &robot.active_process {
+        Some(process) => format!("{:?}", process).to_lowercase(),
+        None => "none".to_string(),
+    }
+}
+
+/// Run a database query, counting it and timing it against its table
+pub fn db_query<T>(table: &str, query: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = query();
+    metrics()
+        .db_queries
+        .with_label_values(&[table])
+        .observe(started.elapsed().as_secs_f64());
+    result
+}
+
+#[cfg(test)]
+#[test]
+fn test_metrics() {
+    let metrics = metrics();
+    metrics.spawns.inc();
+    metrics.attacks.with_label_values(&["hit"]).inc();
+    let answer = db_query("robots", || 42);
+    assert_eq!(42, answer);
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE ares_robots_spawned_total counter"));
+    assert!(text.contains("ares_attacks_total{outcome=\"hit\"}"));
+    assert!(text.contains("ares_db_query_duration_seconds_count{table=\"robots\"}"));
+}
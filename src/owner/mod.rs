@@ -0,0 +1,133 @@
+use diesel::prelude::*;
+use diesel::PgConnection;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::schema::*;
+
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug, Insertable)]
+#[table_name = "owners"]
+pub struct NewOwner {
+    pub name: String,
+    pub token_hash: String,
+}
+
+/// A player who owns robots and can drive them with a remote controller
+#[derive(Clone, Debug, Queryable, Identifiable, Serialize)]
+#[table_name = "owners"]
+pub struct Owner {
+    pub id: i32,
+    pub name: String,
+    /// SHA-256 of the owner's token, in hex; the token itself is never stored
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+}
+
+impl Owner {
+    /// Create a new owner with a freshly generated token; this is the only time
+    /// the token is seen, so it is returned along with the owner
+    pub fn create(conn: &PgConnection, name: &str) -> Result<(Owner, String), String> {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .collect();
+
+        let owner = diesel::insert_into(owners::table)
+            .values(NewOwner {
+                name: name.to_string(),
+                token_hash: hash_token(&token),
+            })
+            .get_result::<Owner>(conn)
+            .map_err(|e| format!("{}", e))?;
+        Ok((owner, token))
+    }
+
+    /// Load all the owners out of the database
+    pub fn load_all(conn: Option<&PgConnection>) -> Result<Vec<Owner>, String> {
+        if conn.is_none() {
+            return Err("No DB connection".to_string());
+        }
+
+        owners::table
+            .order(owners::name)
+            .load::<Owner>(conn.unwrap())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Load an owner by name
+    pub fn load_by_name(conn: &PgConnection, name: &str) -> Result<Owner, String> {
+        owners::table
+            .filter(owners::name.eq(name))
+            .get_result::<Owner>(conn)
+            .map_err(|e| match e {
+                diesel::result::Error::NotFound => format!("No owner {}", name),
+                e => format!("{}", e),
+            })
+    }
+
+    /// Look up an owner by name and check their token
+    pub fn authenticate(conn: &PgConnection, name: &str, token: &str) -> Result<Owner, String> {
+        match Owner::load_by_name(conn, name) {
+            Ok(owner) if tokens_match(&owner.token_hash, &hash_token(token)) => Ok(owner),
+            // don't tell the caller whether the name or the token was wrong
+            _ => Err("Bad owner name or token".to_string()),
+        }
+    }
+
+    /// Delete an owner by name, releasing their robots; returns false if there
+    /// was no such owner
+    pub fn delete(conn: &PgConnection, name: &str) -> Result<bool, String> {
+        let owner = match Owner::load_by_name(conn, name) {
+            Ok(owner) => owner,
+            Err(_) => return Ok(false),
+        };
+
+        diesel::update(robots::table.filter(robots::owner.eq(owner.id)))
+            .set(robots::owner.eq(None::<i32>))
+            .execute(conn)
+            .map_err(|e| format!("{}", e))?;
+
+        diesel::delete(owners::table.filter(owners::id.eq(owner.id)))
+            .execute(conn)
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("{}", e))
+    }
+}
+
+/// The hash we store in place of a token.  Tokens are long and random, so a
+/// plain SHA-256 is enough to keep them from being read back out of the database
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Compare tokens without bailing out on the first mismatch, so the time taken
+/// doesn't give away how much of a guess was right
+pub fn tokens_match(expected: &str, given: &str) -> bool {
+    if expected.len() != given.len() {
+        return false;
+    }
+
+    expected
+        .bytes()
+        .zip(given.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+#[test]
+fn test_tokens_match() {
+    assert!(tokens_match("abc123", "abc123"));
+    assert!(!tokens_match("abc123", "abc124"));
+    assert!(!tokens_match("abc123", "abc12"));
+    assert!(!tokens_match("abc123", ""));
+
+    assert_eq!(
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        hash_token("abc")
+    );
+}
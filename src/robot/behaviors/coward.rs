@@ -0,0 +1,27 @@
+use super::*;
+
+/// Mines, but runs from any robot that isn't on its team
+pub struct Coward {}
+
+impl Behavior for Coward {
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        let threats = hostiles(
+            robot,
+            scan,
+            &[
+                ThreatLevel::Stronger,
+                ThreatLevel::Equal,
+                ThreatLevel::Weaker,
+                ThreatLevel::Unknown,
+            ],
+        );
+
+        flee_from_closest(robot, &threats)
+    }
+
+    /// Always run, even in the middle of a fight
+    fn respond_to_attack(&self, robot: &Robot) -> Option<ProcessResult> {
+        let coords_toward_attacker = robot.get_coords().to(&robot.get_attack_dir(), 2);
+        flee_from_coords(robot, &coords_toward_attacker)
+    }
+}
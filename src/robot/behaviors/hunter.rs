@@ -0,0 +1,56 @@
+use super::*;
+use crate::robot::sightings::Entity;
+
+/// Goes after anything it might beat and roams around looking for more; only
+/// runs from robots it knows are stronger
+pub struct Hunter {}
+
+impl Behavior for Hunter {
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        // without a weapon, there's no hunting to be done
+        if WeaponModule::get_max_damage(&robot.modules.m_weapons) == 0 {
+            return Standard {}.respond_to_scan(robot, scan);
+        }
+
+        let threats = hostiles(robot, scan, &[ThreatLevel::Stronger]);
+        if let Some(response) = flee_from_closest(robot, &threats) {
+            return Some(response);
+        }
+
+        let targets = hostiles(
+            robot,
+            scan,
+            &[
+                ThreatLevel::Weaker,
+                ThreatLevel::Equal,
+                ThreatLevel::Unknown,
+            ],
+        );
+        pursue_closest(robot, &targets)
+    }
+
+    /// Turn on the attacker; if we don't know where it is, at least face it
+    fn respond_to_attack(&self, robot: &Robot) -> Option<ProcessResult> {
+        if robot.is_pursuing() {
+            return None;
+        }
+        if WeaponModule::get_max_damage(&robot.modules.m_weapons) == 0 {
+            return flee_from_attacker(robot);
+        }
+
+        let attacker = Entity::Robot(robot.data.attacked_by);
+        if robot.sightings.get(&attacker).is_some() {
+            return Some(ProcessResult::TransitionToPursue(robot.data.attacked_by));
+        }
+
+        Some(ProcessResult::TransitionToMove(
+            robot.get_coords(),
+            robot.get_attack_dir(),
+            false,
+        ))
+    }
+
+    fn next(&self, robot: &Robot, _: &ScanResults) -> ProcessResult {
+        explore(robot, Frontier::Random)
+    }
+}
@@ -0,0 +1,12 @@
+use super::*;
+
+/// Only cares about valuables; runs from threats but never starts a fight
+pub struct Miner {}
+
+impl Behavior for Miner {
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        let threats = hostiles(robot, scan, &[ThreatLevel::Stronger, ThreatLevel::Unknown]);
+
+        flee_from_closest(robot, &threats)
+    }
+}
@@ -1,132 +1,386 @@
-use diesel::PgConnection;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use serde::Deserialize;
+use std::collections::HashSet;
 
 use super::process::*;
 use super::robot::Robot;
 use crate::grid::*;
 use crate::robot::modules::weapon::WeaponModule;
 
-impl Robot {
-    /// Flee to the farthest point from the given coords
-    fn flee_from_coords(&self, threat_coords: &Coords) -> Option<ProcessResult> {
-        let flee_coords = traversal::find_farthest_coords(
-            self,
-            self.get_known_unoccupied_cells()
-                .keys()
-                .map(|c| c.clone())
-                .collect(),
-            true,
-            Some(threat_coords),
-        );
-
-        if flee_coords.is_some() {
-            return Some(ProcessResult::TransitionToFlee(
-                flee_coords.unwrap(),
-                self.data.orientation,
-            ));
-        } else {
-            None
-        }
-    }
+mod coward;
+mod hunter;
+mod miner;
+mod scout;
+mod script;
+mod standard;
 
-    /// Check to see if we scanned some threats.  If so, transition to flee
-    fn check_for_threats(&mut self, conn: Option<&PgConnection>) -> Option<ProcessResult> {
-        let _threats: Vec<&VisibleRobot> = self
-            .visible_others
-            .iter()
-            .filter(|r| {
-                r.threat_level == ThreatLevel::Stronger || r.threat_level == ThreatLevel::Unknown
-            })
-            .collect();
-
-        let closest_threat_coords: Option<Coords> = traversal::find_closest_coords(
-            self,
-            _threats.iter().map(|r| r.coords).collect(),
-            false,
-        );
+pub use coward::*;
+pub use hunter::*;
+pub use miner::*;
+pub use scout::*;
+pub use script::*;
+pub use standard::*;
 
-        if closest_threat_coords.is_some() {
-            self.set_status_text(
-                conn,
-                &format!("Must flee from {:?}", closest_threat_coords.unwrap()),
-            );
+/// The names of all the built-in behaviors
+pub const BEHAVIORS: [&str; 5] = ["standard", "miner", "hunter", "scout", "coward"];
 
-            if let Some(response) = self.flee_from_coords(&closest_threat_coords.unwrap()) {
-                return Some(response);
-            }
+/// A robot's personality.  Behaviors look at the robot and what it just scanned
+/// and decide which process the robot should switch to.  The provided methods
+/// are how a standard robot behaves.
+pub trait Behavior {
+    /// Respond to the robots we just scanned by fleeing, pursuing, or ignoring
+    /// them; None means carry on
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        let threats = hostiles(robot, scan, &[ThreatLevel::Stronger, ThreatLevel::Unknown]);
+        if let Some(response) = flee_from_closest(robot, &threats) {
+            return Some(response);
+        }
+
+        if WeaponModule::get_max_damage(&robot.modules.m_weapons) != 0 {
+            let targets = hostiles(robot, scan, &[ThreatLevel::Weaker, ThreatLevel::Equal]);
+            return pursue_closest(robot, &targets);
         }
 
         None
     }
 
-    /// Check to see if there are targets we should pursue
-    fn check_for_targets(&mut self, _: Option<&PgConnection>) -> Option<ProcessResult> {
-        let _targets: Vec<&VisibleRobot> = self
-            .visible_others
-            .iter()
-            .filter(|r| {
-                r.threat_level == ThreatLevel::Weaker || r.threat_level == ThreatLevel::Equal
-            })
-            .collect();
-
-        let closest_target_coords: Option<Coords> = traversal::find_closest_coords(
-            self,
-            _targets.iter().map(|r| r.coords).collect(),
-            false,
-        );
+    /// Respond to being attacked; None means carry on
+    fn respond_to_attack(&self, robot: &Robot) -> Option<ProcessResult> {
+        flee_from_attacker(robot)
+    }
 
-        if closest_target_coords.is_some() {
-            let path_to_coords = traversal::find_path(self, closest_target_coords.unwrap(), true);
-            if path_to_coords.is_err() {
-                return None;
-            }
+    /// Decide what to do when there is nothing else going on
+    fn next(&self, robot: &Robot, scan: &ScanResults) -> ProcessResult {
+        if let Some(response) = go_to_valuables(robot, scan) {
+            return response;
+        }
 
-            let mut target_id: Option<i64> = None;
-            for target in &self.visible_others {
-                if target.coords == closest_target_coords.unwrap() {
-                    target_id = Some(target.robot_id);
-                }
-            }
+        explore(robot, Frontier::Closest)
+    }
+}
+
+/// An action a script or a remote controller asks a robot to take
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum Action {
+    MoveTo(Coords),
+    Turn(Dir),
+    Scan,
+    Mine,
+    Attack(i64),
+    Exfiltrate,
+}
 
-            return Some(ProcessResult::TransitionToPursue(target_id.unwrap()));
+impl Action {
+    /// Turn an action into the process the robot should switch to; None means carry on
+    pub fn to_process_result(&self, robot: &Robot) -> Option<ProcessResult> {
+        match self {
+            Action::MoveTo(coords) => Some(ProcessResult::TransitionToMove(
+                *coords,
+                robot.data.orientation,
+                false,
+            )),
+            Action::Turn(dir) => Some(ProcessResult::TransitionToMove(
+                robot.get_coords(),
+                *dir,
+                false,
+            )),
+            Action::Scan => None,
+            Action::Mine => Some(ProcessResult::TransitionToCollect),
+            Action::Attack(robot_id) => Some(ProcessResult::TransitionToPursue(*robot_id)),
+            Action::Exfiltrate => Some(ProcessResult::TransitionToExfiltrate),
         }
+    }
+}
 
-        None
+/// Get the behavior with the given name; unknown names get the standard behavior
+pub fn get_behavior(name: &str) -> &'static dyn Behavior {
+    match name {
+        "miner" => &Miner {},
+        "hunter" => &Hunter {},
+        "scout" => &Scout {},
+        "coward" => &Coward {},
+        _ => &Standard {},
     }
+}
 
-    /// Respond to scanned robots by fleeing, attacking, or ignoring
-    pub fn respond_to_others(&mut self, conn: Option<&PgConnection>) -> Option<ProcessResult> {
-        // If we just scanned a robot of stronger or unknown capabilites,
-        // we want to flee
+/// Pick a random built-in behavior
+pub fn get_random_behavior() -> String {
+    let mut rng = thread_rng();
 
-        if let Some(response) = self.check_for_threats(conn) {
-            return Some(response);
+    BEHAVIORS.choose(&mut rng).unwrap().to_string()
+}
+
+/// Which unexplored edge of the known map to head for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frontier {
+    Closest,
+    Farthest,
+    Random,
+}
+
+/// Get the scanned robots of the given threat levels that aren't on our team
+fn hostiles<'a>(
+    robot: &Robot,
+    scan: &'a ScanResults,
+    threat_levels: &[ThreatLevel],
+) -> Vec<&'a VisibleRobot> {
+    let grid = robot.grid.lock().unwrap();
+    scan.visible_robots
+        .iter()
+        .filter(|r| threat_levels.contains(&r.threat_level))
+        .filter(|r| grid.is_hostile(&r.robot_id, robot.data.affiliation))
+        .collect()
+}
+
+/// Flee to the farthest point from the given coords
+fn flee_from_coords(robot: &Robot, threat_coords: &Coords) -> Option<ProcessResult> {
+    let flee_coords = traversal::find_farthest_coords(
+        robot,
+        robot.get_known_unoccupied_cells().keys().copied().collect(),
+        true,
+        Some(threat_coords),
+    );
+
+    flee_coords.map(|coords| ProcessResult::TransitionToFlee(coords, robot.data.orientation))
+}
+
+/// Flee from the closest of the given robots
+fn flee_from_closest(robot: &Robot, threats: &[&VisibleRobot]) -> Option<ProcessResult> {
+    let closest_threat_coords =
+        traversal::find_closest_coords(robot, threats.iter().map(|r| r.coords).collect(), false)?;
+
+    flee_from_coords(robot, &closest_threat_coords)
+}
+
+/// Flee from whoever attacked us, unless we're already in a fight
+fn flee_from_attacker(robot: &Robot) -> Option<ProcessResult> {
+    if robot.is_pursuing() {
+        return None;
+    }
+
+    let coords_toward_attacker = robot.get_coords().to(&robot.get_attack_dir(), 2);
+    flee_from_coords(robot, &coords_toward_attacker)
+}
+
+/// Pursue the closest of the given robots that we can get to
+fn pursue_closest(robot: &Robot, targets: &[&VisibleRobot]) -> Option<ProcessResult> {
+    // targets are standing on their cells, so we include occupied cells when
+    // looking for the closest one we can get to
+    let field = traversal::distance_field(robot, true);
+    let closest_target_coords =
+        field.closest(&targets.iter().map(|r| r.coords).collect::<Vec<Coords>>())?;
+
+    targets
+        .iter()
+        .find(|target| target.coords == closest_target_coords)
+        .map(|target| ProcessResult::TransitionToPursue(target.robot_id))
+}
+
+/// Go after the closest valuable we can get to, starting with the ones we (or our
+/// allies) can see, then the ones we remember; collect it if we're on it
+fn go_to_valuables(robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+    // filter out valuables that have a robot sitting on them
+    let visible_valuables: Vec<Coords> = scan
+        .visible_valuables
+        .iter()
+        .chain(robot.ally_valuables.iter())
+        .map(|v| v.coords)
+        .filter(|coords| !scan.visible_robots.iter().any(|r| r.coords == *coords))
+        .filter(|coords| !robot.ally_sightings.iter().any(|r| r.coords == *coords))
+        .collect();
+
+    if let Some(closest_coords) = traversal::find_closest_coords(robot, visible_valuables, true) {
+        if closest_coords == robot.get_coords() {
+            return Some(ProcessResult::TransitionToCollect);
         }
 
-        let weapon_power = WeaponModule::get_max_damage(&self.modules.m_weapons);
+        return Some(ProcessResult::TransitionToMove(
+            closest_coords,
+            Dir::get_random(),
+            false,
+        ));
+    }
+
+    // head back to a pile we remember seeing, if nobody is sitting on it
+    let remembered_valuables: Vec<Coords> = robot
+        .sightings
+        .valuables()
+        .map(|s| s.coords)
+        .filter(|coords| !robot.known_occupied_coords(coords))
+        .collect();
+
+    traversal::find_closest_coords(robot, remembered_valuables, true)
+        .map(|coords| ProcessResult::TransitionToMove(coords, Dir::get_random(), false))
+}
+
+/// Head for an open edge of the map we know about, looking out into the unknown
+fn explore(robot: &Robot, frontier: Frontier) -> ProcessResult {
+    // the following is useful for debugging
+    // return ProcessResult::TransitionToMove(Coords{q: -2, r: -2}, Dir::Orient0, false);
+
+    // we will look for open walls in random order
+    let mut search_order: Vec<Dir> = Dir::get_vec();
+    let mut rng = thread_rng();
+    search_order.shuffle(&mut rng);
+
+    // make a list of all the coordinates we know about and can get to; this
+    // includes cells our allies have shared with us, so we don't re-explore them.
+    // Cells near threats we remember count as farther away
+    let known_cells = robot.get_known_unoccupied_cells();
+    let field = traversal::distance_field(robot, false);
+    let mut known_coords: Vec<Coords> = known_cells
+        .keys()
+        .filter(|coords| field.is_reachable(coords))
+        .copied()
+        .collect();
+
+    // we will search known coords in random order
+    known_coords.shuffle(&mut rng);
+    let known_set: HashSet<Coords> = known_coords.iter().copied().collect();
+    let mut random_pick: Option<(&Coords, &Dir)> = None;
+    let mut closest: Option<(&Coords, &Dir, i32)> = None;
+    let mut farthest: Option<(&Coords, &Dir, i32)> = None;
 
-        if weapon_power != 0 {
-            if let Some(response) = self.check_for_targets(conn) {
-                return Some(response);
+    for cell_coords in &known_coords {
+        let cell = match known_cells.get(cell_coords) {
+            Some(cell) => cell,
+            None => continue,
+        };
+
+        // check the edges in random order; if open, see if we know the cell beyond it
+        for orientation in &search_order {
+            if cell.get_side(*orientation) != EdgeType::Wall {
+                // we will test the coords adjacent to the known coords
+                let test_coords = cell_coords.to(orientation, 1);
+
+                // we will skip testing this if the test coords are also known
+                // or if we know the coords are occupied by a robot we know about
+                if !known_set.contains(&test_coords)
+                    && !robot.known_occupied_coords(cell_coords)
+                    && !robot.known_occupied_coords(&test_coords)
+                {
+                    if random_pick.is_none() {
+                        random_pick = Some((cell_coords, orientation));
+                    }
+
+                    let distance = field.distance_to(cell_coords).unwrap_or(0) as i32;
+                    if closest.is_none() {
+                        closest = Some((cell_coords, orientation, distance));
+                    } else {
+                        if distance < closest.unwrap().2 {
+                            closest = Some((cell_coords, orientation, distance));
+                        }
+                    }
+
+                    if farthest.is_none() {
+                        farthest = Some((cell_coords, orientation, distance));
+                    } else {
+                        if distance > farthest.unwrap().2 {
+                            farthest = Some((cell_coords, orientation, distance));
+                        }
+                    }
+                }
             }
         }
+    }
 
-        None
+    let pick = match frontier {
+        Frontier::Closest => closest.map(|(coords, dir, _)| (coords, dir)),
+        Frontier::Farthest => farthest.map(|(coords, dir, _)| (coords, dir)),
+        Frontier::Random => random_pick,
+    };
+    if let Some((coords, dir)) = pick {
+        return ProcessResult::TransitionToMove(*coords, *dir, false);
     }
 
-    /// Respond to an attack
-    pub fn respond_to_attack(&mut self, conn: Option<&PgConnection>) -> Option<ProcessResult> {
-        // if I'm in battle already, then I'm not going to flee!
-        if self.is_pursuing() {
-            return None;
+    // since we didn't find anything unknown, pick a random place; if we don't
+    // know anywhere yet (say we just spawned), turn around where we are
+    let coords = known_coords
+        .first()
+        .copied()
+        .unwrap_or_else(|| robot.get_coords());
+    let dir = search_order
+        .first()
+        .copied()
+        .unwrap_or(robot.data.orientation);
+    ProcessResult::TransitionToMove(coords, dir, false)
+}
+
+#[cfg(test)]
+#[test]
+fn test_behaviors() {
+    use crate::robot::RobotKnownCell;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    let center = Coords { q: 0, r: 0 };
+    let mut grid = Grid::new(2, None).unwrap();
+    grid.stamp_room(&center, &RoomTemplate::Hexagon { radius: 2 }, None)
+        .unwrap();
+    let known_cells: Vec<RobotKnownCell> = grid
+        .cells
+        .values()
+        .map(|c| RobotKnownCell::new(0, c.id, &Coords { q: c.q, r: c.r }))
+        .collect();
+    let grid = Arc::new(Mutex::new(grid));
+
+    let mut modules = HashMap::new();
+    modules.insert("m_weapon".to_string(), "blaster".to_string());
+    let mut robot = Robot::new(center, Dir::Orient0, None, grid.clone(), Some(modules));
+    robot.update_known_cells(None, known_cells);
+
+    let mut scan = ScanResults {
+        scanned_cells: Vec::new(),
+        visible_robots: vec![VisibleRobot {
+            robot_id: 7,
+            coords: Coords { q: 0, r: 2 },
+            threat_level: ThreatLevel::Weaker,
+        }],
+        visible_valuables: Vec::new(),
+    };
+
+    // a weaker robot is prey to most, but not to everyone
+    assert_eq!(
+        Some(ProcessResult::TransitionToPursue(7)),
+        Standard {}.respond_to_scan(&robot, &scan)
+    );
+    assert_eq!(None, Miner {}.respond_to_scan(&robot, &scan));
+    match (Coward {}).respond_to_scan(&robot, &scan) {
+        Some(ProcessResult::TransitionToFlee(coords, _)) => {
+            assert!(coords.distance_to(&Coords { q: 0, r: 2 }) > 2)
         }
+        other => panic!("Coward should flee, not {:?}", other),
+    }
 
-        let attacker_dir: Dir = self.data.attacked_from.into();
-        let coords_toward_attacker = self.get_coords().to(&attacker_dir, 2);
-        self.set_status_text(
-            conn,
-            &format!("Running away from attacker at {:?}", attacker_dir),
-        );
+    // a robot we can't size up is something to run from, unless we're hunting
+    scan.visible_robots[0].threat_level = ThreatLevel::Unknown;
+    assert!(matches!(
+        Standard {}.respond_to_scan(&robot, &scan),
+        Some(ProcessResult::TransitionToFlee(..))
+    ));
+    assert_eq!(
+        Some(ProcessResult::TransitionToPursue(7)),
+        Hunter {}.respond_to_scan(&robot, &scan)
+    );
 
-        return self.flee_from_coords(&coords_toward_attacker);
+    // nobody goes after a teammate
+    grid.lock().unwrap().robot_affiliations.insert(7, 1);
+    robot.set_affiliation(None, Some(1));
+    for name in BEHAVIORS.iter() {
+        assert_eq!(None, get_behavior(name).respond_to_scan(&robot, &scan));
     }
+
+    // or steers around one
+    let mut teammate = scan.visible_robots[0].clone();
+    teammate.threat_level = ThreatLevel::Stronger;
+    robot.receive_shared(None, Vec::new(), vec![teammate], Vec::new());
+    assert!(!robot.sightings.threat_near(&Coords { q: 0, r: 2 }, 0));
+
+    // a robot that doesn't know anywhere yet looks around where it is
+    let newcomer = Robot::new(center, Dir::Orient0, None, grid.clone(), None);
+    assert!(matches!(
+        explore(&newcomer, Frontier::Random),
+        ProcessResult::TransitionToMove(coords, _, _) if coords == center
+    ));
 }
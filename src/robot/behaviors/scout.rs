@@ -0,0 +1,24 @@
+use super::*;
+
+/// Heads for the far edges of the map and stays out of trouble
+pub struct Scout {}
+
+impl Behavior for Scout {
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        let threats = hostiles(
+            robot,
+            scan,
+            &[
+                ThreatLevel::Stronger,
+                ThreatLevel::Equal,
+                ThreatLevel::Unknown,
+            ],
+        );
+
+        flee_from_closest(robot, &threats)
+    }
+
+    fn next(&self, robot: &Robot, _: &ScanResults) -> ProcessResult {
+        explore(robot, Frontier::Farthest)
+    }
+}
@@ -0,0 +1,487 @@
+use diesel::prelude::*;
+use diesel::PgConnection;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, trace, warn};
+
+use super::*;
+use crate::metrics;
+use crate::schema::*;
+
+/// The version of the API that scripts are written against.  Bump this when the
+/// views or actions change in a way that would break existing scripts
+pub const SCRIPT_API_VERSION: i32 = 1;
+
+/// Behaviors with this prefix are scripts; the rest of the name is the script name
+pub const SCRIPT_PREFIX: &str = "script:";
+
+/// The source of a user written script, as stored in the database
+///
+/// Scripts can define any of these functions; anything left out falls back to the
+/// standard behavior:
+///
+/// * `on_scan(robot, scan)`: react to what we just scanned; return an action or
+///   nothing to carry on
+/// * `on_attacked(robot)`: react to being attacked; return an action or nothing
+/// * `on_idle(robot, scan)`: decide what to do when there's nothing else going on
+///
+/// Actions are made with `move_to(q, r)`, `turn(dir)`, `scan()`, `mine()`,
+/// `attack(robot_id)` and `exfiltrate()`.
+/// Anything passed to `print` or `debug` goes to the server log at debug or
+/// trace level.
+#[derive(Clone, Debug, PartialEq, Queryable, Insertable, Serialize)]
+#[table_name = "robot_scripts"]
+pub struct ScriptSource {
+    pub name: String,
+    pub api_version: i32,
+    /// how many operations the script may run each tick
+    pub fuel: i64,
+    pub source: String,
+}
+
+impl ScriptSource {
+    pub fn new(name: &str, fuel: i64, source: &str) -> ScriptSource {
+        ScriptSource {
+            name: name.to_string(),
+            api_version: SCRIPT_API_VERSION,
+            fuel,
+            source: source.to_string(),
+        }
+    }
+
+    /// Load a script by name
+    pub fn load(conn: Option<&PgConnection>, name: &str) -> Result<ScriptSource, String> {
+        if conn.is_none() {
+            return Err("No DB connection".to_string());
+        }
+
+        metrics::db_query("robot_scripts", || {
+            robot_scripts::table
+                .filter(robot_scripts::name.eq(name))
+                .get_result::<ScriptSource>(conn.unwrap())
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => format!("No script {}", name),
+            e => format!("{}", e),
+        })
+    }
+
+    /// Load all the scripts out of the database
+    pub fn load_all(conn: Option<&PgConnection>) -> Result<Vec<ScriptSource>, String> {
+        if conn.is_none() {
+            return Err("No DB connection".to_string());
+        }
+
+        robot_scripts::table
+            .order(robot_scripts::name)
+            .load::<ScriptSource>(conn.unwrap())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Save the script, replacing any script with the same name
+    pub fn save(&self, conn: &PgConnection) -> Result<(), String> {
+        diesel::insert_into(robot_scripts::table)
+            .values(self)
+            .on_conflict(robot_scripts::name)
+            .do_update()
+            .set((
+                robot_scripts::api_version.eq(self.api_version),
+                robot_scripts::fuel.eq(self.fuel),
+                robot_scripts::source.eq(&self.source),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Delete a script by name; returns false if there was no such script
+    pub fn delete(conn: &PgConnection, name: &str) -> Result<bool, String> {
+        diesel::delete(robot_scripts::table.filter(robot_scripts::name.eq(name)))
+            .execute(conn)
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("{}", e))
+    }
+}
+
+/// A compiled script that drives a robot
+pub struct ScriptBehavior {
+    pub name: String,
+    engine: Engine,
+    ast: AST,
+    fuel: u64,
+    /// operations used so far this tick
+    fuel_used: Arc<AtomicU64>,
+    /// operations used by the call that is running
+    call_ops: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for ScriptBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ScriptBehavior({})", self.name)
+    }
+}
+
+impl ScriptBehavior {
+    /// Compile a script into a behavior
+    pub fn new(script: &ScriptSource) -> Result<ScriptBehavior, String> {
+        if script.api_version != SCRIPT_API_VERSION {
+            return Err(format!(
+                "Script {} is written for API version {}; we run version {}",
+                script.name, script.api_version, SCRIPT_API_VERSION
+            ));
+        }
+
+        let fuel = script.fuel.max(0) as u64;
+        let fuel_used = Arc::new(AtomicU64::new(0));
+        let call_ops = Arc::new(AtomicU64::new(0));
+
+        let mut engine = Engine::new();
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(4096);
+        engine.set_max_array_size(4096);
+        engine.set_max_map_size(256);
+        engine.disable_symbol("eval");
+
+        // stop the script once it has used up the fuel for this tick
+        let used = fuel_used.clone();
+        let ops_now = call_ops.clone();
+        engine.on_progress(move |ops| {
+            ops_now.store(ops, Ordering::Relaxed);
+            if used.load(Ordering::Relaxed) + ops > fuel {
+                Some(Dynamic::from("out of fuel"))
+            } else {
+                None
+            }
+        });
+
+        // scripts write to our log, in the span of the robot running them, rather
+        // than straight to stdout
+        let name = script.name.clone();
+        engine.on_print(move |text| debug!(script = %name, "{}", text));
+        let name = script.name.clone();
+        engine.on_debug(move |text, _, pos| trace!(script = %name, %pos, "{}", text));
+
+        register_actions(&mut engine);
+
+        let ast = engine
+            .compile(&script.source)
+            .map_err(|e| format!("Could not compile script {}: {}", script.name, e))?;
+
+        Ok(ScriptBehavior {
+            name: script.name.clone(),
+            engine,
+            ast,
+            fuel,
+            fuel_used,
+            call_ops,
+        })
+    }
+
+    /// Load a script by name out of the database and compile it
+    pub fn load(conn: Option<&PgConnection>, name: &str) -> Result<ScriptBehavior, String> {
+        ScriptBehavior::new(&ScriptSource::load(conn, name)?)
+    }
+
+    /// Give the script a full tank for the next tick
+    pub fn refuel(&self) {
+        self.fuel_used.store(0, Ordering::Relaxed);
+    }
+
+    /// How much fuel is left for this tick
+    pub fn fuel_left(&self) -> u64 {
+        self.fuel
+            .saturating_sub(self.fuel_used.load(Ordering::Relaxed))
+    }
+
+    fn has_fn(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    /// Call a script function; Ok(None) means the script didn't ask for anything
+    fn call(
+        &self,
+        robot: &Robot,
+        name: &str,
+        args: Vec<Dynamic>,
+    ) -> Result<Option<Action>, String> {
+        let mut scope = Scope::new();
+        self.call_ops.store(0, Ordering::Relaxed);
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut scope, &self.ast, name, args);
+        self.fuel_used
+            .fetch_add(self.call_ops.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        let result = result.map_err(|e| match *e {
+            EvalAltResult::ErrorTerminated(..) => format!(
+                "Robot {}: script {} ran out of fuel in {}",
+                robot.data.id, self.name, name
+            ),
+            e => format!(
+                "Robot {}: script {} failed in {}: {}",
+                robot.data.id, self.name, name, e
+            ),
+        })?;
+
+        if result.is_unit() {
+            return Ok(None);
+        }
+        match result.try_cast::<Action>() {
+            Some(action) => Ok(Some(action)),
+            None => Err(format!(
+                "Robot {}: script {} returned something other than an action from {}",
+                robot.data.id, self.name, name
+            )),
+        }
+    }
+}
+
+impl Behavior for ScriptBehavior {
+    fn respond_to_scan(&self, robot: &Robot, scan: &ScanResults) -> Option<ProcessResult> {
+        if !self.has_fn("on_scan") {
+            return Standard {}.respond_to_scan(robot, scan);
+        }
+
+        match self.call(robot, "on_scan", vec![robot_view(robot), scan_view(scan)]) {
+            Ok(action) => action.and_then(|a| a.to_process_result(robot)),
+            Err(reason) => {
+                warn!("Script failed: {}", reason);
+                Standard {}.respond_to_scan(robot, scan)
+            }
+        }
+    }
+
+    fn respond_to_attack(&self, robot: &Robot) -> Option<ProcessResult> {
+        if !self.has_fn("on_attacked") {
+            return Standard {}.respond_to_attack(robot);
+        }
+
+        match self.call(robot, "on_attacked", vec![robot_view(robot)]) {
+            Ok(action) => action.and_then(|a| a.to_process_result(robot)),
+            Err(reason) => {
+                warn!("Script failed: {}", reason);
+                Standard {}.respond_to_attack(robot)
+            }
+        }
+    }
+
+    fn next(&self, robot: &Robot, scan: &ScanResults) -> ProcessResult {
+        if !self.has_fn("on_idle") {
+            return Standard {}.next(robot, scan);
+        }
+
+        match self.call(robot, "on_idle", vec![robot_view(robot), scan_view(scan)]) {
+            Ok(action) => action
+                .and_then(|a| a.to_process_result(robot))
+                .unwrap_or(ProcessResult::Ok),
+            Err(reason) => {
+                warn!("Script failed: {}", reason);
+                Standard {}.next(robot, scan)
+            }
+        }
+    }
+}
+
+/// Add the functions scripts use to make actions
+fn register_actions(engine: &mut Engine) {
+    engine.register_type_with_name::<Action>("Action");
+    engine.register_fn("move_to", |q: i64, r: i64| {
+        Action::MoveTo(Coords {
+            q: q as i32,
+            r: r as i32,
+        })
+    });
+    engine.register_fn("turn", |dir: i64| -> Result<Action, Box<EvalAltResult>> {
+        if dir % 60 != 0 {
+            return Err(format!("{} is not a direction", dir).into());
+        }
+        Ok(Action::Turn(Dir::Orient0.rotated((dir / 60) as i32)))
+    });
+    engine.register_fn("scan", || Action::Scan);
+    engine.register_fn("mine", || Action::Mine);
+    engine.register_fn("attack", Action::Attack);
+    engine.register_fn("exfiltrate", || Action::Exfiltrate);
+    engine.register_fn("distance", |q1: i64, r1: i64, q2: i64, r2: i64| {
+        let from = Coords {
+            q: q1 as i32,
+            r: r1 as i32,
+        };
+        from.distance_to(&Coords {
+            q: q2 as i32,
+            r: r2 as i32,
+        }) as i64
+    });
+}
+
+fn coords_view(coords: &Coords) -> Map {
+    let mut view = Map::new();
+    view.insert("q".into(), (coords.q as i64).into());
+    view.insert("r".into(), (coords.r as i64).into());
+    view
+}
+
+fn dir_view(dir: Dir) -> Dynamic {
+    let angle: i32 = dir.into();
+    (angle as i64).into()
+}
+
+/// What a script gets to see about its own robot
+fn robot_view(robot: &Robot) -> Dynamic {
+    let mut view = coords_view(&robot.get_coords());
+    view.insert("api_version".into(), (SCRIPT_API_VERSION as i64).into());
+    view.insert("id".into(), robot.data.id.into());
+    view.insert("orientation".into(), dir_view(robot.data.orientation));
+    view.insert("power".into(), (robot.data.power as i64).into());
+    view.insert("max_power".into(), (robot.data.max_power as i64).into());
+    view.insert("hull".into(), (robot.data.hull_strength as i64).into());
+    view.insert(
+        "max_hull".into(),
+        (robot.data.max_hull_strength as i64).into(),
+    );
+    view.insert("inventory".into(), (robot.data.val_inventory as i64).into());
+    view.insert(
+        "max_inventory".into(),
+        (robot.data.max_val_inventory as i64).into(),
+    );
+    view.insert(
+        "affiliation".into(),
+        match robot.data.affiliation {
+            Some(affiliation) => (affiliation as i64).into(),
+            None => Dynamic::UNIT,
+        },
+    );
+    view.insert(
+        "attacked_from".into(),
+        if robot.is_under_attack() {
+            dir_view(robot.get_attack_dir())
+        } else {
+            Dynamic::UNIT
+        },
+    );
+
+    let known_cells: Array = robot
+        .get_known_cells()
+        .iter()
+        .map(|(coords, cell)| {
+            let mut view = coords_view(coords);
+            let open: Array = Dir::get_vec()
+                .into_iter()
+                .filter(|dir| cell.get_side(*dir) != EdgeType::Wall)
+                .map(dir_view)
+                .collect();
+            view.insert("open".into(), open.into());
+            view.into()
+        })
+        .collect();
+    view.insert("known_cells".into(), known_cells.into());
+
+    view.into()
+}
+
+/// What a script gets to see of the latest scan
+fn scan_view(scan: &ScanResults) -> Dynamic {
+    let mut view = Map::new();
+
+    let cells: Array = scan
+        .scanned_cells
+        .iter()
+        .map(|c| coords_view(c).into())
+        .collect();
+    view.insert("scanned_cells".into(), cells.into());
+
+    let robots: Array = scan
+        .visible_robots
+        .iter()
+        .map(|r| {
+            let mut view = coords_view(&r.coords);
+            view.insert("id".into(), r.robot_id.into());
+            let threat = match r.threat_level {
+                ThreatLevel::Unknown => "unknown",
+                ThreatLevel::Weaker => "weaker",
+                ThreatLevel::Stronger => "stronger",
+                ThreatLevel::Equal => "equal",
+            };
+            view.insert("threat".into(), threat.into());
+            view.into()
+        })
+        .collect();
+    view.insert("robots".into(), robots.into());
+
+    let valuables: Array = scan
+        .visible_valuables
+        .iter()
+        .map(|v| {
+            let mut view = coords_view(&v.coords);
+            view.insert("id".into(), v.valuable_id.into());
+            view.into()
+        })
+        .collect();
+    view.insert("valuables".into(), valuables.into());
+
+    view.into()
+}
+
+#[cfg(test)]
+#[test]
+fn test_script_behavior() {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    let center = Coords { q: 0, r: 0 };
+    let mut grid = Grid::new(2, None).unwrap();
+    grid.stamp_room(&center, &RoomTemplate::Hexagon { radius: 2 }, None)
+        .unwrap();
+    let grid = Arc::new(Mutex::new(grid));
+    let robot = Robot::new(center, Dir::Orient0, None, grid, Some(HashMap::new()));
+    let scan = ScanResults {
+        scanned_cells: vec![center],
+        visible_robots: Vec::new(),
+        visible_valuables: vec![VisibleValuable {
+            valuable_id: 3,
+            coords: Coords { q: 1, r: 0 },
+        }],
+    };
+
+    let source = r#"
+        fn on_idle(robot, scan) {
+            if scan.valuables.len() > 0 {
+                let v = scan.valuables[0];
+                return move_to(v.q, v.r);
+            }
+            turn(robot.orientation + 60)
+        }
+        fn on_scan(robot, scan) {
+            if robot.power > 100 {
+                loop { }
+            }
+        }
+    "#;
+    let script = ScriptBehavior::new(&ScriptSource::new("greedy", 500, source)).unwrap();
+    assert_eq!(
+        ProcessResult::TransitionToMove(Coords { q: 1, r: 0 }, Dir::Orient0, false),
+        script.next(&robot, &scan)
+    );
+
+    // a script that runs away with itself is cut off once it runs out of fuel, and
+    // the robot carries on as if the script had nothing to say
+    let mut robot = robot;
+    robot.data.power = 1000;
+    assert_eq!(
+        None,
+        script.respond_to_scan(&robot, &ScanResults::default())
+    );
+    assert_eq!(0, script.fuel_left());
+    script.refuel();
+    assert!(script.fuel_left() > 0);
+
+    let outdated = ScriptSource {
+        api_version: 0,
+        ..ScriptSource::new("old", 500, source)
+    };
+    assert!(ScriptBehavior::new(&outdated).is_err());
+    assert!(ScriptBehavior::new(&ScriptSource::new("broken", 500, "fn on_idle(")).is_err());
+}
@@ -0,0 +1,6 @@
+use super::*;
+
+/// Mines what it finds, runs from anything stronger and goes after anything weaker
+pub struct Standard {}
+
+impl Behavior for Standard {}
@@ -2,8 +2,10 @@ pub mod behaviors;
 pub mod modules;
 pub mod process;
 pub mod robot;
+pub mod sightings;
 
 pub use behaviors::*;
 pub use modules::*;
 pub use process::*;
 pub use robot::*;
+pub use sightings::*;
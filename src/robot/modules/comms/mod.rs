@@ -0,0 +1,106 @@
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+
+use crate::robot::RobotKnownCell;
+
+pub struct CommsModule {}
+
+impl CommsModule {
+    pub fn get_random() -> String {
+        let list = ["none", "basic", "relay", "mesh"];
+
+        let mut rng = rand::thread_rng();
+
+        list.choose(&mut rng).unwrap().to_string()
+    }
+
+    /// How far away an ally can be and still hear us
+    pub fn get_range(name: &str) -> i32 {
+        match name {
+            "none" => 0,
+            "basic" => 3,
+            "relay" => 5,
+            "mesh" => 8,
+            _ => 0,
+        }
+    }
+
+    /// How many entries (cells or sightings) we can send out each tick
+    pub fn get_bandwidth(name: &str) -> usize {
+        match name {
+            "none" => 0,
+            "basic" => 4,
+            "relay" => 8,
+            "mesh" => 16,
+            _ => 0,
+        }
+    }
+
+    /// Power used on each tick that we transmit
+    pub fn get_power_usage(name: &str) -> i32 {
+        match name {
+            "none" => 0,
+            "basic" => 20,
+            "relay" => 40,
+            "mesh" => 80,
+            _ => 0,
+        }
+    }
+
+    /// Pick the cells the receiver doesn't know about yet, newest first, up to the limit
+    pub fn pick_cells_to_share(
+        sender_cells: &[RobotKnownCell],
+        receiver_cells: &[RobotKnownCell],
+        limit: usize,
+    ) -> Vec<RobotKnownCell> {
+        let already_known: HashSet<i32> = receiver_cells.iter().map(|c| c.gridcell_id).collect();
+
+        let mut cells: Vec<RobotKnownCell> = sender_cells
+            .iter()
+            .filter(|c| !already_known.contains(&c.gridcell_id))
+            .cloned()
+            .collect();
+        cells.sort();
+        cells.reverse();
+        cells.truncate(limit);
+
+        cells
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_pick_cells_to_share() {
+    use crate::grid::Coords;
+    use std::time::{Duration, SystemTime};
+
+    let now = SystemTime::now();
+    let make_cell = |robot_id: i64, gridcell_id: i32, age: u64| RobotKnownCell {
+        discovery_time: now - Duration::from_secs(age),
+        ..RobotKnownCell::new(
+            robot_id,
+            gridcell_id,
+            &Coords {
+                q: gridcell_id,
+                r: 0,
+            },
+        )
+    };
+
+    let sender = vec![
+        make_cell(1, 1, 30),
+        make_cell(1, 2, 10),
+        make_cell(1, 3, 20),
+        make_cell(1, 4, 0),
+    ];
+    let receiver = vec![make_cell(2, 4, 50)];
+
+    // the receiver already knows cell 4, so we get the two newest of the rest
+    let shared = CommsModule::pick_cells_to_share(&sender, &receiver, 2);
+    assert_eq!(
+        vec![2, 3],
+        shared.iter().map(|c| c.gridcell_id).collect::<Vec<i32>>()
+    );
+
+    assert!(CommsModule::pick_cells_to_share(&sender, &receiver, 0).is_empty());
+}
@@ -1,3 +1,15 @@
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+use crate::robot::RobotKnownCell;
+
+/// Confidence of a cell we just scanned ourselves
+pub const FULL_CONFIDENCE: i32 = 100;
+
+/// A cell loses a point of confidence for every this many seconds since we last
+/// saw it or used it
+const DECAY_SECS: u64 = 3;
+
 pub struct MemoryModule {}
 
 impl MemoryModule {
@@ -10,4 +22,177 @@ impl MemoryModule {
             _ => 20,
         }
     }
+
+    pub fn get_eviction_policy(name: &str) -> EvictionPolicy {
+        match name {
+            "basic" => EvictionPolicy::Oldest,
+            "plus" => EvictionPolicy::LeastRecentlyUsed,
+            "ikito" => EvictionPolicy::KeepRoutes,
+            "jindai" => EvictionPolicy::Decay,
+            _ => EvictionPolicy::Oldest,
+        }
+    }
+}
+
+/// How a robot decides which known cells to forget when its memory is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvictionPolicy {
+    /// Forget the cells discovered longest ago
+    Oldest,
+    /// Forget the cells we haven't planned a path through for the longest
+    LeastRecentlyUsed,
+    /// Like LeastRecentlyUsed, but hold on to cells on routes to valuables
+    KeepRoutes,
+    /// Forget the cells we are least confident about; cells that fade out
+    /// completely are forgotten even if there is room
+    Decay,
+}
+
+impl EvictionPolicy {
+    /// Remove cells so that no more than `limit` remain, returning the removed cells
+    pub fn evict(
+        &self,
+        cells: &mut Vec<RobotKnownCell>,
+        limit: usize,
+        now: SystemTime,
+    ) -> Vec<RobotKnownCell> {
+        let mut removed = Vec::new();
+        if *self == EvictionPolicy::Decay {
+            let (faded, kept) = cells
+                .drain(..)
+                .partition(|c| current_confidence(c, now) <= 0);
+            removed = faded;
+            *cells = kept;
+        }
+
+        // put the cells we most want to keep first
+        match self {
+            EvictionPolicy::Oldest => cells.sort_by_key(|c| Reverse(c.discovery_time)),
+            EvictionPolicy::LeastRecentlyUsed => cells.sort_by_key(|c| Reverse(c.last_used)),
+            EvictionPolicy::KeepRoutes => {
+                cells.sort_by_key(|c| (Reverse(c.on_route), Reverse(c.last_used)))
+            }
+            EvictionPolicy::Decay => cells.sort_by_key(|c| Reverse(current_confidence(c, now))),
+        }
+
+        if cells.len() > limit {
+            removed.extend(cells.split_off(limit));
+        }
+
+        removed
+    }
+}
+
+/// How much we still trust our memory of a cell, after decaying since we last
+/// saw it or used it
+pub fn current_confidence(cell: &RobotKnownCell, now: SystemTime) -> i32 {
+    let last_touched = cell.discovery_time.max(cell.last_used);
+    let elapsed = now
+        .duration_since(last_touched)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    cell.confidence - (elapsed / DECAY_SECS) as i32
+}
+
+#[cfg(test)]
+#[test]
+fn test_eviction_policies() {
+    use std::time::Duration;
+
+    let now = SystemTime::now();
+    let ago = |secs: u64| now - Duration::from_secs(secs);
+    let make_cell = |gridcell_id: i32, discovered: u64, used: u64, on_route: bool| RobotKnownCell {
+        robot_id: 1,
+        gridcell_id,
+        discovery_time: ago(discovered),
+        q: gridcell_id,
+        r: 0,
+        last_used: ago(used),
+        confidence: FULL_CONFIDENCE,
+        on_route,
+    };
+    let cells = vec![
+        make_cell(1, 10, 10, false),
+        make_cell(2, 100, 0, false),
+        make_cell(3, 400, 400, true),
+        make_cell(4, 20, 20, false),
+    ];
+    let evict = |policy: EvictionPolicy, limit: usize| {
+        let mut kept = cells.clone();
+        let removed = policy.evict(&mut kept, limit, now);
+        (
+            kept.iter().map(|c| c.gridcell_id).collect::<Vec<i32>>(),
+            removed.iter().map(|c| c.gridcell_id).collect::<Vec<i32>>(),
+        )
+    };
+
+    assert_eq!((vec![1, 4], vec![2, 3]), evict(EvictionPolicy::Oldest, 2));
+
+    // cell 2 is old but we just walked through it
+    assert_eq!(
+        (vec![2, 1], vec![4, 3]),
+        evict(EvictionPolicy::LeastRecentlyUsed, 2)
+    );
+
+    // cell 3 is the oldest and least used, but it's on the way to a valuable
+    assert_eq!(
+        (vec![3, 2], vec![1, 4]),
+        evict(EvictionPolicy::KeepRoutes, 2)
+    );
+
+    // cell 3 has faded out completely, so it goes even though there is room
+    assert_eq!((vec![2, 1, 4], vec![3]), evict(EvictionPolicy::Decay, 10));
+}
+
+#[test]
+fn test_routes() {
+    use crate::grid::*;
+    use crate::robot::process::VisibleValuable;
+    use crate::robot::Robot;
+    use std::sync::{Arc, Mutex};
+
+    let center = Coords { q: 0, r: 0 };
+    let mut grid = Grid::new(2, None).unwrap();
+    grid.stamp_room(&center, &RoomTemplate::Hexagon { radius: 2 }, None)
+        .unwrap();
+    let known_cells: Vec<RobotKnownCell> = grid
+        .cells
+        .values()
+        .map(|c| RobotKnownCell::new(0, c.id, &Coords { q: c.q, r: c.r }))
+        .collect();
+    let mut robot = Robot::new(center, Dir::Orient0, None, Arc::new(Mutex::new(grid)), None);
+    robot.update_known_cells(None, known_cells);
+
+    let on_route = |robot: &Robot| {
+        let mut cells: Vec<(i32, i32)> = robot
+            .known_cells
+            .iter()
+            .filter(|c| c.on_route)
+            .map(|c| (c.q, c.r))
+            .collect();
+        cells.sort();
+        cells
+    };
+    let pile = |valuable_id, q, r| VisibleValuable {
+        valuable_id,
+        coords: Coords { q, r },
+    };
+
+    // the way to a pile we remember is kept in memory
+    let east = Coords { q: 2, r: 0 };
+    robot.sightings.record_valuable(&pile(1, 2, 0), 0);
+    robot.use_known_cells(None, &[center, Coords { q: 1, r: 0 }, east], Some(east));
+    assert_eq!(vec![(0, 0), (1, 0), (2, 0)], on_route(&robot));
+
+    // a route to another pile replaces it
+    let north = Coords { q: 0, r: 2 };
+    robot.sightings.record_valuable(&pile(2, 0, 2), 0);
+    robot.use_known_cells(None, &[center, Coords { q: 0, r: 1 }, north], Some(north));
+    assert_eq!(vec![(0, 0), (0, 1), (0, 2)], on_route(&robot));
+
+    // and once we look and the pile is gone, so is the route
+    robot.sightings.update_from_scan(&[north], &[], &[], 1);
+    robot.forget_stale_route(None);
+    assert!(on_route(&robot).is_empty());
 }
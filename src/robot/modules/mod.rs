@@ -1,4 +1,5 @@
 pub mod collector;
+pub mod comms;
 pub mod drivesystem;
 pub mod exfilbeacon;
 pub mod hull;
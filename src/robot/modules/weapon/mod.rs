@@ -2,6 +2,7 @@ use rand::seq::SliceRandom;
 
 use crate::grid::Coords;
 use crate::grid::Dir;
+use crate::grid::Grid;
 use crate::utils::get_bearing;
 
 pub struct WeaponModule {}
@@ -69,6 +70,18 @@ impl WeaponModule {
         }
     }
 
+    /// see if a target @ coords2 can be hit: it must be in range and there
+    /// can't be any walls in the way
+    pub fn has_clear_shot(
+        name: &str,
+        grid: &Grid,
+        coords1: &Coords,
+        dir: &Dir,
+        coords2: &Coords,
+    ) -> bool {
+        Self::in_range(name, coords1, dir, coords2) && grid.has_line_of_sight(coords1, coords2)
+    }
+
     /// see if a target @ coords2 is in range of the weapon (_name) given bearing
     /// from coords1 facing dir
     pub fn in_range(_name: &str, coords1: &Coords, dir: &Dir, coords2: &Coords) -> bool {
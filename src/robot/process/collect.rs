@@ -1,4 +1,5 @@
 use diesel::PgConnection;
+use tracing::debug;
 
 use super::ProcessResult;
 use super::*;
@@ -31,10 +32,7 @@ impl Process for Collect {
         if robot.data.mined_amount >= collection_rate * 10
             || robot.data.val_inventory == max_val_inventory
         {
-            println!(
-                "Robot {} has collected max amount for this iteration",
-                robot.data.id
-            );
+            debug!("Collected the most allowed for this run");
             return ProcessResult::TransitionToNeutral;
         }
 
@@ -70,7 +68,7 @@ impl Process for Collect {
         robot: &mut Robot,
         _: Option<ProcessResult>,
     ) -> ProcessResult {
-        println!("Robot {}: Transition to Collect", robot.data.id);
+        debug!("Transition to Collect");
         robot.set_status_text(conn, "I'm mining valuables.");
         robot.start_new_mining_operation(conn);
 
@@ -1,4 +1,5 @@
 use diesel::PgConnection;
+use tracing::info;
 
 use super::ProcessResult;
 use super::*;
@@ -37,7 +38,7 @@ impl Process for Exfil {
         robot: &mut Robot,
         _: Option<ProcessResult>,
     ) -> ProcessResult {
-        println!("Transition to Exfiltrate");
+        info!("Transition to Exfiltrate");
         robot.set_status_text(conn, "I'm calling for exfiltration.");
         robot.start_exfil_countdown(conn);
 
@@ -1,4 +1,5 @@
 use diesel::PgConnection;
+use tracing::info;
 
 use super::ProcessResult;
 use super::*;
@@ -23,10 +24,10 @@ impl Process for Explode {
 
     fn init(
         _: Option<&PgConnection>,
-        robot: &mut Robot,
+        _: &mut Robot,
         _: Option<ProcessResult>,
     ) -> ProcessResult {
-        println!("Robot {}: Transition to Explode", robot.data.id);
+        info!("Transition to Explode");
         return ProcessResult::Ok;
     }
 }
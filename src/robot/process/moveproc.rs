@@ -1,4 +1,5 @@
 use diesel::PgConnection;
+use tracing::{debug, info};
 
 use super::ProcessResult;
 use super::*;
@@ -28,15 +29,13 @@ impl Process for Move {
         }
 
         // we scan only so we can react to other robots
-        let mut _visible_robots: Vec<VisibleRobot> = Vec::new();
-        let scan_results = Scan::run(conn, robot, None);
-        if let ProcessResult::ScannedCells(scan_result) = scan_results {
-            _visible_robots = scan_result.visible_robots;
-        } else if scan_results == ProcessResult::OutOfPower {
-            return ProcessResult::OutOfPower;
-        }
+        let scan_results = match Scan::run(conn, robot, None) {
+            ProcessResult::ScannedCells(scan_results) => scan_results,
+            ProcessResult::OutOfPower => return ProcessResult::OutOfPower,
+            _ => ScanResults::default(),
+        };
 
-        if let Some(response) = robot.respond_to_others(conn) {
+        if let Some(response) = robot.behavior().respond_to_scan(robot, &scan_results) {
             return response;
         }
 
@@ -77,20 +76,14 @@ impl Process for Move {
                 target_coords = tc;
                 orientation = o;
                 spin = s;
-                println!(
-                    "Robot {}: Move to {:?}, {:?}, {:?}",
-                    robot.data.id, &target_coords, &orientation, spin
-                );
+                info!(to = ?target_coords, ?orientation, spin, "Move");
                 robot.set_status_text(conn, format!("I'm moving to {},{}.", tc.q, tc.r).as_str());
             }
             ProcessResult::TransitionToFlee(tc, o) => {
                 target_coords = tc;
                 orientation = o;
                 spin = true;
-                println!(
-                    "Robot {}: Flee to {:?}, {:?}, {:?}",
-                    robot.data.id, &target_coords, &orientation, spin
-                );
+                info!(to = ?target_coords, ?orientation, spin, "Flee");
                 robot.set_status_text(conn, format!("I'm fleeing to {},{}!", tc.q, tc.r).as_str());
             }
             _ => return ProcessResult::Fail,
@@ -101,9 +94,18 @@ impl Process for Move {
         } else {
             let moves = traversal::find_path(robot, target_coords, false);
             match moves {
-                Ok(path_queue) => robot.movement_queue = Some(path_queue),
+                Ok(path_queue) => {
+                    // remember the cells we're using; the way to a valuable is worth keeping
+                    let route_to =
+                        Some(target_coords).filter(|coords| robot.knows_valuable_at(coords));
+                    let path =
+                        traversal::path_cells(&robot.get_coords_and_orientation(), &path_queue);
+                    robot.use_known_cells(conn, &path, route_to);
+
+                    robot.movement_queue = Some(path_queue);
+                }
                 Err(s) => {
-                    println!("{}", s);
+                    debug!("No path: {}", s);
                     return ProcessResult::Fail;
                 }
             }
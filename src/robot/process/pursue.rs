@@ -1,4 +1,5 @@
 use diesel::PgConnection;
+use tracing::{debug, info};
 
 use super::ProcessResult;
 use super::*;
@@ -29,18 +30,25 @@ impl Process for Pursue {
         match message {
             ProcessResult::TransitionToPursue(id) => {
                 target_id = id;
-                println!("Robot {}: Pursue Robot {}", robot.data.id, id);
+                info!(target_id, "Pursue");
                 robot.set_status_text(conn, &format!("I'm pursuing Robot {}.", id));
             }
             _ => return ProcessResult::Fail,
         }
 
-        // find the coords of the other robot
+        // find the coords of the other robot; if we can't see it, go by where we
+        // remember it being
         for other in &robot.visible_others {
             if other.robot_id == target_id {
                 target_coords = Some(other.coords);
             }
         }
+        if target_coords.is_none() {
+            target_coords = robot
+                .sightings
+                .get(&Entity::Robot(target_id))
+                .map(|s| s.coords);
+        }
 
         if target_coords.is_none() {
             return ProcessResult::Fail;
@@ -51,7 +59,7 @@ impl Process for Pursue {
         match moves {
             Ok(path_queue) => robot.movement_queue = Some(path_queue),
             Err(s) => {
-                println!("{}", s);
+                debug!("No path: {}", s);
                 return ProcessResult::Fail;
             }
         }
@@ -67,11 +75,6 @@ impl Process for Pursue {
         robot: &mut Robot,
         _: Option<ProcessResult>,
     ) -> ProcessResult {
-        let robot_coords = Coords {
-            q: robot.data.q,
-            r: robot.data.r,
-        };
-
         let current_target_coords = Coords {
             q: robot.data.pursuit_last_q,
             r: robot.data.pursuit_last_r,
@@ -113,23 +116,37 @@ impl Process for Pursue {
             }
         }
         if latest_coords.is_none() {
-            return ProcessResult::TransitionToMove(
-                current_target_coords,
-                Dir::get_random(),
-                false,
-            );
+            // an ally may have seen it more recently than we did
+            let last_seen = robot
+                .sightings
+                .get(&Entity::Robot(robot.data.pursuit_id))
+                .map(|s| s.coords)
+                .unwrap_or(current_target_coords);
+
+            // don't chase it into a threat
+            if robot.sightings.threat_near(&last_seen, 1) {
+                return ProcessResult::TransitionToNeutral;
+            }
+
+            return ProcessResult::TransitionToMove(last_seen, Dir::get_random(), false);
         }
 
         // we still see the target so update our info on the target
         robot.update_pursuit_details(conn, robot.data.pursuit_id, &latest_coords.unwrap());
 
-        let in_range = WeaponModule::in_range(
+        // aim from where the step above left us
+        let robot_coords = Coords {
+            q: robot.data.q,
+            r: robot.data.r,
+        };
+        let clear_shot = WeaponModule::has_clear_shot(
             &robot.modules.m_weapons,
+            &robot.grid.lock().unwrap(),
             &robot_coords,
             &robot.data.orientation,
             &latest_coords.unwrap(),
         );
-        if in_range {
+        if clear_shot {
             return ProcessResult::ServerRequest(Request::Attack {
                 target_id: robot.data.pursuit_id,
             });
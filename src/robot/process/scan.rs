@@ -1,13 +1,11 @@
 use rand::Rng;
-use std::collections::HashMap;
-use std::time::SystemTime;
+use serde::Serialize;
 
 use super::*;
-use crate::grid::utils::traversal::is_reachable;
 use crate::grid::*;
 use crate::robot::*;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum ThreatLevel {
     Unknown,
     Weaker,
@@ -16,20 +14,20 @@ pub enum ThreatLevel {
 }
 
 /// Holds information about robots visible from the last scan
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct VisibleRobot {
     pub robot_id: i64,
     pub coords: Coords,
     pub threat_level: ThreatLevel,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct VisibleValuable {
     pub valuable_id: i64,
     pub coords: Coords,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
 pub struct ScanResults {
     pub scanned_cells: Vec<Coords>,
     pub visible_robots: Vec<VisibleRobot>,
@@ -65,105 +63,90 @@ impl Process for Scan {
         let weapon_strength = weapon::WeaponModule::get_max_damage(&robot.modules.m_weapons);
 
         let cells = grid.get_cells(&our_coords, robot.data.orientation, fov, range);
-        let cells_full: HashMap<Coords, GridCell> = cells
-            .iter()
-            .map(|cell| {
-                (
-                    Coords {
-                        q: cell.q,
-                        r: cell.r,
-                    },
-                    *cell.to_owned(),
-                )
-            })
-            .collect();
 
         let mut known_cells: Vec<RobotKnownCell> = Vec::new();
         let mut scanned_cells: Vec<Coords> = Vec::new();
         let mut visible_robots: Vec<VisibleRobot> = Vec::new();
         let mut visible_valuables: Vec<VisibleValuable> = Vec::new();
         for cell in cells {
-            // TODO: see if this cell is visible from this starting location
             let cell_coords = Coords {
                 q: cell.q,
                 r: cell.r,
             };
             let distance = our_coords.distance_to(&cell_coords);
-            let visible = is_reachable(&our_coords, &cell_coords, &cells_full, distance);
+            let visible = grid.has_line_of_sight(&our_coords, &cell_coords);
 
             // if visible (or is the location we are standing on), add it to known cells
             if distance == 0 || visible {
-                known_cells.push(RobotKnownCell {
-                    robot_id: robot.data.id,
-                    gridcell_id: cell.id,
-                    discovery_time: SystemTime::now(),
-                    q: cell.q,
-                    r: cell.r,
-                });
-                scanned_cells.push(Coords {
-                    q: cell.q,
-                    r: cell.r,
-                });
+                known_cells.push(RobotKnownCell::new(robot.data.id, cell.id, &cell_coords));
+                scanned_cells.push(cell_coords);
+            }
+        }
 
-                // also see if there is a robot in that cell
-                let other_robot = grid.get_robot_id_by_loc(&Coords {
-                    q: cell.q,
-                    r: cell.r,
-                });
+        // look up the robots and valuables in the scanned area, only keeping the visible ones
+        let robots_in_range =
+            grid.robot_locs
+                .within_cone(&our_coords, robot.data.orientation, fov, range);
+        let valuables_in_range =
+            grid.valuables_locs
+                .within_cone(&our_coords, robot.data.orientation, fov, range);
+
+        for (coords, other_robot) in robots_in_range {
+            if other_robot == robot.data.id || !scanned_cells.contains(&coords) {
+                continue;
+            }
+
+            let threat_level = match grid.robot_strengths.get(&other_robot) {
+                Some(_) if rng.gen_range(0, 101) > accuracy => ThreatLevel::Unknown,
+                Some(strength) if *strength > weapon_strength => ThreatLevel::Stronger,
+                Some(strength) if *strength < weapon_strength => ThreatLevel::Weaker,
+                Some(_) => ThreatLevel::Equal,
+                None => ThreatLevel::Unknown,
+            };
+
+            visible_robots.push(VisibleRobot {
+                robot_id: other_robot,
+                coords,
+                threat_level,
+            });
+        }
 
-                // if there is a robot on that cell (and it isn't this robot)...
-                if other_robot.is_some() && *other_robot.unwrap() != robot.data.id {
-                    let other_strength = grid.robot_strengths.get(other_robot.unwrap());
-                    let threat_level: ThreatLevel;
-                    if other_strength.is_none() {
-                        threat_level = ThreatLevel::Unknown;
-                    } else if rng.gen_range(0, 101) > accuracy {
-                        threat_level = ThreatLevel::Unknown;
-                    } else if other_strength.unwrap() > &weapon_strength {
-                        threat_level = ThreatLevel::Stronger;
-                    } else if other_strength.unwrap() < &weapon_strength {
-                        threat_level = ThreatLevel::Weaker;
-                    } else {
-                        threat_level = ThreatLevel::Equal;
-                    }
-
-                    visible_robots.push(VisibleRobot {
-                        robot_id: *other_robot.unwrap(),
-                        coords: Coords {
-                            q: cell.q,
-                            r: cell.r,
-                        },
-                        threat_level,
-                    });
-                }
-
-                // and then see if there are valuables in that cell
-                let valuable = grid.get_valuable_id_by_loc(&Coords {
-                    q: cell.q,
-                    r: cell.r,
+        for (coords, valuable_id) in valuables_in_range {
+            if scanned_cells.contains(&coords) {
+                visible_valuables.push(VisibleValuable {
+                    valuable_id,
+                    coords,
                 });
-                if valuable.is_some() {
-                    visible_valuables.push(VisibleValuable {
-                        valuable_id: *valuable.unwrap(),
-                        coords: Coords {
-                            q: cell.q,
-                            r: cell.r,
-                        },
-                    })
-                }
             }
         }
 
+        // teammates are no threat, so they don't go in our memory of robots to watch out for
+        let hostile_robots: Vec<VisibleRobot> = visible_robots
+            .iter()
+            .filter(|r| grid.is_hostile(&r.robot_id, robot.data.affiliation))
+            .cloned()
+            .collect();
+
         drop(grid);
         robot.update_known_cells(conn, known_cells);
         robot.update_visible_others(&visible_robots);
         robot.update_visible_valuables(&visible_valuables);
-
-        ProcessResult::ScannedCells(ScanResults {
+        robot.sightings.update_from_scan(
+            &scanned_cells,
+            &hostile_robots,
+            &visible_valuables,
+            robot.tick_count,
+        );
+        robot.forget_stale_route(conn);
+
+        let results = ScanResults {
             scanned_cells,
             visible_robots,
             visible_valuables,
-        })
+        };
+        robot.last_scan = results.clone();
+
+        ProcessResult::ScannedCells(results)
     }
 
     // transition
@@ -1,14 +1,19 @@
+use diesel::pg::upsert::excluded;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use serde;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
+use tracing::{error, info, trace, warn};
 
+use super::behaviors::*;
 use super::modules::*;
 use super::process::*;
+use super::sightings::*;
 use crate::grid::*;
+use crate::metrics::{self, metrics, process_name};
 use crate::schema::*;
 use crate::server::*;
 use crate::utils;
@@ -51,6 +56,7 @@ pub struct RobotData {
     pub attacked_by: i64,
     pub attacked: i64,
     pub damage_done: i32,
+    pub behavior: String,
 }
 
 /// Represents a grid cell that is known by a robot
@@ -63,9 +69,30 @@ pub struct RobotKnownCell {
     pub discovery_time: std::time::SystemTime,
     pub q: i32,
     pub r: i32,
+    /// last time we planned a path through this cell
+    pub last_used: std::time::SystemTime,
+    /// how much we trust our memory of this cell; see `memory::current_confidence`
+    pub confidence: i32,
+    /// set if the cell is on a path to somewhere worth coming back to
+    pub on_route: bool,
 }
 
 impl RobotKnownCell {
+    /// Create a freshly scanned known cell
+    pub fn new(robot_id: i64, gridcell_id: i32, coords: &Coords) -> RobotKnownCell {
+        let now = SystemTime::now();
+        RobotKnownCell {
+            robot_id,
+            gridcell_id,
+            discovery_time: now,
+            q: coords.q,
+            r: coords.r,
+            last_used: now,
+            confidence: memory::FULL_CONFIDENCE,
+            on_route: false,
+        }
+    }
+
     /// Load all the known grid cells for a robot out of memory
     pub fn load_all(
         conn: Option<&PgConnection>,
@@ -74,9 +101,11 @@ impl RobotKnownCell {
         if conn.is_none() {
             return Err("No DB connection".to_string());
         }
-        let results = robot_known_cells::table
-            .filter(robot_known_cells::robot_id.eq(robot_id))
-            .load::<RobotKnownCell>(conn.unwrap());
+        let results = metrics::db_query("robot_known_cells", || {
+            robot_known_cells::table
+                .filter(robot_known_cells::robot_id.eq(robot_id))
+                .load::<RobotKnownCell>(conn.unwrap())
+        });
 
         if let Ok(cells) = results {
             Ok(cells)
@@ -125,6 +154,7 @@ pub struct RobotModules {
     pub m_power: String,
     pub m_scanner: String,
     pub m_weapons: String,
+    pub m_comms: String,
 }
 
 impl RobotModules {
@@ -144,6 +174,7 @@ impl RobotModules {
             m_power: String::from("basic"),
             m_scanner: String::from("basic"),
             m_weapons: String::from("basic"),
+            m_comms: String::from("none"),
         };
 
         if modmap.is_some() {
@@ -157,17 +188,20 @@ impl RobotModules {
                     "m_power" => modules.m_power = val.to_string(),
                     "m_scanner" => modules.m_scanner = val.to_string(),
                     "m_weapon" => modules.m_weapons = val.to_string(),
+                    "m_comms" => modules.m_comms = val.to_string(),
                     _ => continue,
                 }
             }
         }
 
         if let Some(conn) = conn {
-            if let Err(_) = diesel::insert_into(robot_modules::table)
-                .values(&modules)
-                .execute(conn)
-            {
-                println!("Error saving modules");
+            let query = metrics::db_query("robot_modules", || {
+                diesel::insert_into(robot_modules::table)
+                    .values(&modules)
+                    .execute(conn)
+            });
+            if query.is_err() {
+                error!(robot_id, "Could not save modules");
             }
         }
 
@@ -180,10 +214,12 @@ impl RobotModules {
             return Err("No DB connection".to_string());
         }
 
-        if let Ok(loaded_modules) = robot_modules::table
-            .filter(robot_modules::robot_id.eq(robot_id))
-            .get_result::<RobotModules>(conn.unwrap())
-        {
+        let query = metrics::db_query("robot_modules", || {
+            robot_modules::table
+                .filter(robot_modules::robot_id.eq(robot_id))
+                .get_result::<RobotModules>(conn.unwrap())
+        });
+        if let Ok(loaded_modules) = query {
             return Ok(loaded_modules);
         } else {
             return Ok(RobotModules::new(robot_id, None, conn));
@@ -208,6 +244,38 @@ pub struct Robot {
     #[serde(skip_serializing)]
     pub visible_valuables: Vec<VisibleValuable>,
 
+    /// robots that allies told us about over comms
+    #[serde(skip_serializing)]
+    pub ally_sightings: Vec<VisibleRobot>,
+
+    /// valuables that allies told us about over comms
+    #[serde(skip_serializing)]
+    pub ally_valuables: Vec<VisibleValuable>,
+
+    /// what we remember seeing over the last several ticks
+    #[serde(skip_serializing)]
+    pub sightings: Sightings,
+
+    /// the valuable our known cells marked `on_route` lead to
+    #[serde(skip_serializing)]
+    pub route_to: Option<Coords>,
+
+    /// how many ticks this robot has been running since it was loaded
+    #[serde(skip_serializing)]
+    pub tick_count: u64,
+
+    /// the compiled script driving this robot, if its behavior is a script
+    #[serde(skip_serializing)]
+    pub script: Option<Arc<ScriptBehavior>>,
+
+    /// what we saw on our last scan
+    #[serde(skip_serializing)]
+    pub last_scan: ScanResults,
+
+    /// an action from our owner's remote controller to take on the next tick
+    #[serde(skip_serializing)]
+    pub remote_action: Option<Action>,
+
     pub active_process: Option<Processes>,
 
     #[serde(skip_serializing)]
@@ -239,12 +307,20 @@ impl Robot {
                 Err(_) => Vec::new(),
             };
 
-            let robot = Robot {
+            let mut robot = Robot {
                 grid: grid.clone(),
                 data: result,
                 known_cells,
                 visible_others: Vec::new(),
                 visible_valuables: Vec::new(),
+                ally_sightings: Vec::new(),
+                ally_valuables: Vec::new(),
+                sightings: Sightings::new(),
+                route_to: None,
+                tick_count: 0,
+                script: None,
+                last_scan: ScanResults::default(),
+                remote_action: None,
                 active_process: None,
                 movement_queue: None,
                 modules: match RobotModules::load(id, conn) {
@@ -252,6 +328,7 @@ impl Robot {
                     Err(_) => RobotModules::new(id, None, conn),
                 },
             };
+            robot.load_script(conn);
 
             _robots.insert(id, robot);
         }
@@ -276,10 +353,12 @@ impl Robot {
 
         let mut _robot: RobotData;
         if let Some(conn) = conn {
-            _robot = diesel::insert_into(robots::table)
-                .values(new_robot)
-                .get_result(conn)
-                .expect("Error saving cells");
+            _robot = metrics::db_query("robots", || {
+                diesel::insert_into(robots::table)
+                    .values(new_robot)
+                    .get_result(conn)
+            })
+            .expect("Error saving cells");
         } else {
             _robot = RobotData {
                 id: 0,
@@ -307,6 +386,7 @@ impl Robot {
                 attacked_by: -1,
                 attacked: -1,
                 damage_done: -1,
+                behavior: String::from("standard"),
             }
         }
 
@@ -318,6 +398,14 @@ impl Robot {
             known_cells: Vec::new(),
             visible_others: Vec::new(),
             visible_valuables: Vec::new(),
+            ally_sightings: Vec::new(),
+            ally_valuables: Vec::new(),
+            sightings: Sightings::new(),
+            route_to: None,
+            tick_count: 0,
+            script: None,
+            last_scan: ScanResults::default(),
+            remote_action: None,
             active_process: None,
             movement_queue: None,
             modules: modules,
@@ -343,9 +431,83 @@ impl Robot {
             return;
         }
 
-        let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-            .set(robots::status_text.eq(self.data.status_text.clone()))
-            .execute(conn.unwrap());
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::status_text.eq(self.data.status_text.clone()))
+                .execute(conn.unwrap())
+        });
+    }
+
+    /// Set which side the robot is on; robots that share an affiliation are allies
+    pub fn set_affiliation(&mut self, conn: Option<&PgConnection>, affiliation: Option<i32>) {
+        self.data.affiliation = affiliation;
+
+        if conn.is_none() {
+            return;
+        }
+
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::affiliation.eq(affiliation))
+                .execute(conn.unwrap())
+        });
+    }
+
+    /// Set the owner whose remote controller may drive this robot
+    pub fn set_owner(&mut self, conn: Option<&PgConnection>, owner: Option<i32>) {
+        self.data.owner = owner;
+
+        if conn.is_none() {
+            return;
+        }
+
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::owner.eq(owner))
+                .execute(conn.unwrap())
+        });
+    }
+
+    /// Set the personality that decides what this robot does; see `behaviors::get_behavior`
+    pub fn set_behavior(&mut self, conn: Option<&PgConnection>, behavior: &str) {
+        self.data.behavior = behavior.to_string();
+        self.load_script(conn);
+
+        if conn.is_none() {
+            return;
+        }
+
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::behavior.eq(&self.data.behavior))
+                .execute(conn.unwrap())
+        });
+    }
+
+    /// If our behavior is a script, load and compile it; if that fails we fall
+    /// back to the standard behavior
+    fn load_script(&mut self, conn: Option<&PgConnection>) {
+        self.script = None;
+
+        if let Some(name) = self.data.behavior.strip_prefix(SCRIPT_PREFIX) {
+            match ScriptBehavior::load(conn, name) {
+                Ok(script) => self.script = Some(Arc::new(script)),
+                Err(e) => warn!(
+                    robot_id = self.data.id,
+                    script = name,
+                    "Can't load script: {}",
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Get the personality that decides what this robot does
+    pub fn behavior(&self) -> &dyn Behavior {
+        match &self.script {
+            Some(script) => script.as_ref(),
+            None => get_behavior(&self.data.behavior),
+        }
     }
 
     /// update the max power based on the power module
@@ -364,16 +526,18 @@ impl Robot {
         self.data.max_val_inventory = max_val_inventory;
 
         if let Some(conn) = conn {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set((
-                    robots::max_power.eq(max_power),
-                    robots::recharge_rate.eq(recharge_rate),
-                    robots::power.eq(max_power),
-                    robots::hull_strength.eq(self.data.hull_strength),
-                    robots::max_hull_strength.eq(self.data.max_hull_strength),
-                    robots::max_val_inventory.eq(self.data.max_val_inventory),
-                ))
-                .execute(conn);
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set((
+                        robots::max_power.eq(max_power),
+                        robots::recharge_rate.eq(recharge_rate),
+                        robots::power.eq(max_power),
+                        robots::hull_strength.eq(self.data.hull_strength),
+                        robots::max_hull_strength.eq(self.data.max_hull_strength),
+                        robots::max_val_inventory.eq(self.data.max_val_inventory),
+                    ))
+                    .execute(conn)
+            });
         }
     }
 
@@ -386,9 +550,11 @@ impl Robot {
         self.data.power -= amount;
 
         if let Some(conn) = conn {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set(robots::power.eq(self.data.power))
-                .execute(conn);
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set(robots::power.eq(self.data.power))
+                    .execute(conn)
+            });
         }
 
         return ProcessResult::Ok;
@@ -403,9 +569,11 @@ impl Robot {
         }
 
         if let Some(conn) = conn {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set(robots::power.eq(self.data.power))
-                .execute(conn);
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set(robots::power.eq(self.data.power))
+                    .execute(conn)
+            });
         }
     }
 
@@ -414,18 +582,22 @@ impl Robot {
         self.data.hull_strength += adjustment;
 
         if let Some(conn) = conn {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set(robots::hull_strength.eq(self.data.hull_strength))
-                .execute(conn);
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set(robots::hull_strength.eq(self.data.hull_strength))
+                    .execute(conn)
+            });
         }
     }
 
     /// print id and status text
     pub fn ident(&self) {
-        // println!(
-        //     "Robot {}: ({},{}) @ {:?}",
-        //     self.data.id, self.data.q, self.data.r, self.data.orientation
-        // );
+        trace!(
+            q = self.data.q,
+            r = self.data.r,
+            orientation = ?self.data.orientation,
+            "Ticking"
+        );
     }
 
     /// Update the orientation on turn left
@@ -434,9 +606,11 @@ impl Robot {
         self.data.orientation = orientation;
 
         if conn.is_some() {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set(robots::orientation.eq(&orientation))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set(robots::orientation.eq(&orientation))
+                    .execute(conn.unwrap())
+            });
         }
     }
 
@@ -446,9 +620,11 @@ impl Robot {
         self.data.orientation = orientation;
 
         if conn.is_some() {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set(robots::orientation.eq(&orientation))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set(robots::orientation.eq(&orientation))
+                    .execute(conn.unwrap())
+            });
         }
     }
 
@@ -464,14 +640,31 @@ impl Robot {
         self.data.r = new_coords.r;
 
         if conn.is_some() {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set((robots::q.eq(self.data.q), robots::r.eq(self.data.r)))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set((robots::q.eq(self.data.q), robots::r.eq(self.data.r)))
+                    .execute(conn.unwrap())
+            });
         }
 
         new_coords
     }
 
+    /// Put the robot somewhere else on the grid, forgetting where it was headed
+    pub fn relocate(&mut self, conn: Option<&PgConnection>, coords: &Coords) {
+        self.data.q = coords.q;
+        self.data.r = coords.r;
+        self.movement_queue = None;
+
+        if let Some(conn) = conn {
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set((robots::q.eq(self.data.q), robots::r.eq(self.data.r)))
+                    .execute(conn)
+            });
+        }
+    }
+
     /// get the next step from the movement queue
     pub fn get_move(&mut self) -> Option<MoveStep> {
         if self.movement_queue.is_none() {
@@ -494,12 +687,14 @@ impl Robot {
 
     /// Store the latest attacker into the database
     fn persist_attacker_info(&self, conn: &PgConnection) {
-        let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-            .set((
-                robots::attacked_by.eq(self.data.attacked_by),
-                robots::attacked_from.eq(self.data.attacked_from),
-            ))
-            .execute(conn);
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set((
+                    robots::attacked_by.eq(self.data.attacked_by),
+                    robots::attacked_from.eq(self.data.attacked_from),
+                ))
+                .execute(conn)
+        });
     }
 
     /// Register an attack
@@ -524,12 +719,14 @@ impl Robot {
 
     /// Store the latest attack results into the database
     fn persist_attack_info(&self, conn: &PgConnection) {
-        let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-            .set((
-                robots::attacked.eq(self.data.attacked),
-                robots::damage_done.eq(self.data.damage_done),
-            ))
-            .execute(conn);
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set((
+                    robots::attacked.eq(self.data.attacked),
+                    robots::damage_done.eq(self.data.damage_done),
+                ))
+                .execute(conn)
+        });
     }
 
     pub fn clear_attack_info(&mut self, conn: Option<&PgConnection>) {
@@ -559,10 +756,14 @@ impl Robot {
         new_cells: Vec<RobotKnownCell>,
     ) {
         let mut new_known_cells: Vec<RobotKnownCell> = Vec::new();
+        let mut new_cells = new_cells;
         for cell in &self.known_cells {
             let mut found = false;
-            for cell2 in &new_cells {
+            for cell2 in new_cells.iter_mut() {
                 if cell.robot_id == cell2.robot_id && cell.gridcell_id == cell2.gridcell_id {
+                    // a rescan refreshes the cell, but we keep what we learned about using it
+                    cell2.last_used = cell2.last_used.max(cell.last_used);
+                    cell2.on_route = cell2.on_route || cell.on_route;
                     found = true;
                     continue;
                 }
@@ -571,51 +772,126 @@ impl Robot {
                 new_known_cells.push(cell.clone());
             }
         }
+        self.save_known_cells(conn, &new_cells);
         for cell in new_cells {
             new_known_cells.push(cell);
         }
 
         self.known_cells = new_known_cells;
 
-        if conn.is_some() {
-            let query = diesel::insert_into(robot_known_cells::table)
-                .values(&self.known_cells)
-                .on_conflict((robot_known_cells::robot_id, robot_known_cells::gridcell_id))
-                .do_update()
-                .set(robot_known_cells::discovery_time.eq(SystemTime::now()))
-                .execute(conn.unwrap());
+        self.limit_known_cells(conn);
+    }
+
+    /// Record that we planned a path through these cells.  If the path leads to a
+    /// valuable at `route_to`, it is marked as the route worth keeping in memory,
+    /// replacing any route we had before
+    pub fn use_known_cells(
+        &mut self,
+        conn: Option<&PgConnection>,
+        coords: &[Coords],
+        route_to: Option<Coords>,
+    ) {
+        let now = SystemTime::now();
+        let path: HashSet<Coords> = coords.iter().copied().collect();
+        if route_to.is_some() {
+            self.route_to = route_to;
+        }
 
-            if let Err(reason) = query {
-                println!("Could not update known cells: {:?}", reason);
+        let mut used_cells = Vec::new();
+        for cell in self.known_cells.iter_mut() {
+            let on_path = path.contains(&Coords {
+                q: cell.q,
+                r: cell.r,
+            });
+            let on_route = match route_to {
+                Some(_) => on_path,
+                None => cell.on_route,
+            };
+
+            if on_path || on_route != cell.on_route {
+                if on_path {
+                    cell.last_used = now;
+                }
+                cell.on_route = on_route;
+                used_cells.push(cell.clone());
             }
         }
 
-        self.limit_known_cells(conn);
+        self.save_known_cells(conn, &used_cells);
     }
 
-    pub fn limit_known_cells(&mut self, conn: Option<&PgConnection>) {
-        self.known_cells.sort();
-        self.known_cells.reverse();
+    /// Check if we saw, remember or were told about a valuable at the coords
+    pub fn knows_valuable_at(&self, coords: &Coords) -> bool {
+        self.visible_valuables
+            .iter()
+            .chain(self.ally_valuables.iter())
+            .any(|v| v.coords == *coords)
+            || self.sightings.valuables().any(|s| s.coords == *coords)
+    }
 
-        let mem_limit = memory::MemoryModule::get_memory_size(self.modules.m_memory.as_str());
-        let mut removed_cells = Vec::new();
-        while self.known_cells.len() > mem_limit {
-            let cell = self.known_cells.pop();
-            if cell.is_some() {
-                removed_cells.push(cell.unwrap());
+    /// Stop keeping our route in memory once we no longer know of a valuable at
+    /// the end of it, say because it was mined out
+    pub fn forget_stale_route(&mut self, conn: Option<&PgConnection>) {
+        if let Some(coords) = self.route_to {
+            if self.knows_valuable_at(&coords) {
+                return;
             }
         }
+        self.route_to = None;
+
+        let mut cleared = Vec::new();
+        for cell in self.known_cells.iter_mut().filter(|c| c.on_route) {
+            cell.on_route = false;
+            cleared.push(cell.clone());
+        }
+        self.save_known_cells(conn, &cleared);
+    }
+
+    /// Persist the given known cells, replacing what was stored for them
+    fn save_known_cells(&self, conn: Option<&PgConnection>, cells: &[RobotKnownCell]) {
+        if conn.is_none() || cells.is_empty() {
+            return;
+        }
+
+        let query = metrics::db_query("robot_known_cells", || {
+            diesel::insert_into(robot_known_cells::table)
+                .values(cells)
+                .on_conflict((robot_known_cells::robot_id, robot_known_cells::gridcell_id))
+                .do_update()
+                .set((
+                    robot_known_cells::discovery_time
+                        .eq(excluded(robot_known_cells::discovery_time)),
+                    robot_known_cells::last_used.eq(excluded(robot_known_cells::last_used)),
+                    robot_known_cells::confidence.eq(excluded(robot_known_cells::confidence)),
+                    robot_known_cells::on_route.eq(excluded(robot_known_cells::on_route)),
+                ))
+                .execute(conn.unwrap())
+        });
+
+        if let Err(reason) = query {
+            error!("Could not update known cells: {:?}", reason);
+        }
+    }
+
+    /// Forget cells to stay within our memory limit; which ones go depends on the
+    /// eviction policy of our memory module
+    pub fn limit_known_cells(&mut self, conn: Option<&PgConnection>) {
+        let mem_limit = memory::MemoryModule::get_memory_size(self.modules.m_memory.as_str());
+        let policy = memory::MemoryModule::get_eviction_policy(self.modules.m_memory.as_str());
+        let removed_cells = policy.evict(&mut self.known_cells, mem_limit, SystemTime::now());
 
         if conn.is_some() {
             for removed_cell in removed_cells {
-                let query = diesel::delete(
-                    robot_known_cells::table
-                        .filter(robot_known_cells::robot_id.eq(removed_cell.robot_id))
-                        .filter(robot_known_cells::gridcell_id.eq(removed_cell.gridcell_id)),
-                )
-                .execute(conn.unwrap());
+                let query = metrics::db_query("robot_known_cells", || {
+                    diesel::delete(
+                        robot_known_cells::table
+                            .filter(robot_known_cells::robot_id.eq(removed_cell.robot_id))
+                            .filter(robot_known_cells::gridcell_id.eq(removed_cell.gridcell_id)),
+                    )
+                    .execute(conn.unwrap())
+                });
                 if let Err(reason) = query {
-                    println!("Could not update known cells: {:?}", reason);
+                    error!("Could not update known cells: {:?}", reason);
                 }
             }
         }
@@ -629,6 +905,65 @@ impl Robot {
         self.visible_valuables = visible_valuables.to_owned().to_vec();
     }
 
+    /// Forget what allies told us on the last comms exchange
+    pub fn clear_ally_sightings(&mut self) {
+        self.ally_sightings.clear();
+        self.ally_valuables.clear();
+    }
+
+    /// Take in cells and sightings shared by an ally; shared cells keep the time
+    /// the ally discovered them so our own fresher memories are kept first
+    pub fn receive_shared(
+        &mut self,
+        conn: Option<&PgConnection>,
+        cells: Vec<RobotKnownCell>,
+        others: Vec<VisibleRobot>,
+        valuables: Vec<VisibleValuable>,
+    ) {
+        for other in others {
+            let hostile = self
+                .grid
+                .lock()
+                .unwrap()
+                .is_hostile(&other.robot_id, self.data.affiliation);
+            if hostile {
+                self.sightings.record_robot(&other, self.tick_count);
+            }
+            if !self
+                .ally_sightings
+                .iter()
+                .any(|r| r.robot_id == other.robot_id)
+            {
+                self.ally_sightings.push(other);
+            }
+        }
+        for valuable in valuables {
+            self.sightings.record_valuable(&valuable, self.tick_count);
+            if !self
+                .ally_valuables
+                .iter()
+                .any(|v| v.valuable_id == valuable.valuable_id)
+            {
+                self.ally_valuables.push(valuable);
+            }
+        }
+
+        if !cells.is_empty() {
+            let robot_id = self.data.id;
+            let cells = cells
+                .into_iter()
+                .map(|cell| RobotKnownCell {
+                    robot_id,
+                    // secondhand knowledge isn't trusted as much as our own scans
+                    confidence: cell.confidence / 2,
+                    on_route: false,
+                    ..cell
+                })
+                .collect();
+            self.update_known_cells(conn, cells);
+        }
+    }
+
     /// Get a map of coords to full gridcessl that this robot knows about
     pub fn get_known_cells(&self) -> HashMap<Coords, GridCell> {
         let grid = self.grid.lock().unwrap();
@@ -685,7 +1020,10 @@ impl Robot {
 
     /// Check if the coords are known to be occupied
     pub fn known_occupied_coords(&self, coords: &Coords) -> bool {
-        self.visible_others.iter().any(|r| r.coords == *coords)
+        self.visible_others
+            .iter()
+            .chain(self.ally_sightings.iter())
+            .any(|r| r.coords == *coords)
     }
 
     /// Get the robot's coordinates
@@ -744,8 +1082,7 @@ impl Robot {
 
                 if grid
                     .robot_locs
-                    .get(&robot_coords.to(&orientation, 1))
-                    .is_some()
+                    .contains_coords(&robot_coords.to(&orientation, 1))
                 {
                     return ProcessResult::Fail;
                 }
@@ -755,7 +1092,7 @@ impl Robot {
                 let new_robot_coords = self.move_forward(conn).clone();
                 let mut grid = self.grid.lock().unwrap();
 
-                grid.update_robot_loc(self.data.id, robot_coords.clone(), new_robot_coords);
+                grid.update_robot_loc(self.data.id, new_robot_coords);
             }
         }
         ProcessResult::Ok
@@ -771,9 +1108,11 @@ impl Robot {
 
         let conn = conn.unwrap();
 
-        let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-            .set(robots::mined_amount.eq(0))
-            .execute(conn);
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::mined_amount.eq(0))
+                .execute(conn)
+        });
     }
 
     /// Successfully attacked a target; record it for this tick
@@ -802,12 +1141,14 @@ impl Robot {
         self.data.val_inventory += amount;
 
         if conn.is_some() {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set((
-                    robots::mined_amount.eq(self.data.mined_amount),
-                    robots::val_inventory.eq(self.data.val_inventory),
-                ))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set((
+                        robots::mined_amount.eq(self.data.mined_amount),
+                        robots::val_inventory.eq(self.data.val_inventory),
+                    ))
+                    .execute(conn.unwrap())
+            });
         }
     }
 
@@ -823,13 +1164,15 @@ impl Robot {
         self.data.pursuit_last_r = other_coords.r;
 
         if conn.is_some() {
-            let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-                .set((
-                    robots::pursuit_id.eq(self.data.pursuit_id),
-                    robots::pursuit_last_q.eq(self.data.pursuit_last_q),
-                    robots::pursuit_last_r.eq(self.data.pursuit_last_r),
-                ))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                    .set((
+                        robots::pursuit_id.eq(self.data.pursuit_id),
+                        robots::pursuit_last_q.eq(self.data.pursuit_last_q),
+                        robots::pursuit_last_r.eq(self.data.pursuit_last_r),
+                    ))
+                    .execute(conn.unwrap())
+            });
         }
     }
 
@@ -847,9 +1190,11 @@ impl Robot {
 
         let conn = conn.unwrap();
 
-        let _ = diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
-            .set(robots::exfil_countdown.eq(self.data.exfil_countdown))
-            .execute(conn);
+        let _ = metrics::db_query("robots", || {
+            diesel::update(robots::table.filter(robots::id.eq(self.data.id)))
+                .set(robots::exfil_countdown.eq(self.data.exfil_countdown))
+                .execute(conn)
+        });
     }
 
     /// start the exfil countdown
@@ -873,8 +1218,10 @@ impl Robot {
     /// Delete self
     pub fn destroy(&mut self, conn: Option<&PgConnection>) {
         if conn.is_some() {
-            let _ = diesel::delete(robots::table.filter(robots::id.eq(self.data.id)))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("robots", || {
+                diesel::delete(robots::table.filter(robots::id.eq(self.data.id)))
+                    .execute(conn.unwrap())
+            });
         }
     }
 
@@ -901,34 +1248,82 @@ impl Robot {
         }
     }
 
+    /// Initialize the process a result transitions to and make it our active process
+    fn start_process(&mut self, conn: Option<&PgConnection>, result: ProcessResult) {
+        let (process, init_result) = match result {
+            ProcessResult::TransitionToCollect => {
+                (Processes::Collect, Collect::init(conn, self, Some(result)))
+            }
+            ProcessResult::TransitionToExfiltrate => {
+                (Processes::Exfil, Exfil::init(conn, self, Some(result)))
+            }
+            ProcessResult::TransitionToFlee { .. } | ProcessResult::TransitionToMove { .. } => {
+                (Processes::Move, Move::init(conn, self, Some(result)))
+            }
+            ProcessResult::TransitionToNeutral => {
+                Neutral::init(conn, self, Some(result));
+                (Processes::Neutral, ProcessResult::Ok)
+            }
+            ProcessResult::TransitionToPursue { .. } => {
+                Pursue::init(conn, self, Some(result));
+                (Processes::Pursue, ProcessResult::Ok)
+            }
+            _ => return,
+        };
+
+        if init_result == ProcessResult::Ok {
+            self.active_process = Some(process);
+        }
+    }
+
     /// Handles a tick
     pub fn tick(&mut self, conn: Option<&PgConnection>) -> Option<Request> {
         self.ident();
+        self.tick_count += 1;
+        self.sightings.age_out(self.tick_count);
+        if let Some(script) = &self.script {
+            script.refuel();
+        }
 
         // if our hull strength is less than or equal to zero, explode!
         if self.data.hull_strength <= 0 {
             Explode::init(conn, self, None);
             self.active_process = Some(Processes::Explode);
         }
-        // if we were attacked, we may need to flee
-        else if self.is_under_attack() && !self.is_pursuing() {
+        // our owner's controller decides for us over our own behavior
+        else if let Some(action) = self.remote_action.take() {
+            if let Some(result) = action.to_process_result(self) {
+                self.start_process(conn, result);
+            }
+        }
+        // if we were attacked, our behavior decides if we flee or fight
+        else if self.is_under_attack() {
             let attacker_dir: Dir = self.data.attacked_from.into();
-            println!(
-                "Robot {}: I was attacked by {} from direction {:?}",
-                self.data.id, self.data.attacked_by, attacker_dir
+            info!(
+                attacked_by = self.data.attacked_by,
+                from = ?attacker_dir,
+                "I was attacked"
             );
-            let response = self.respond_to_attack(conn);
+            let response = self.behavior().respond_to_attack(self);
             match response {
-                Some(ProcessResult::TransitionToFlee { .. }) => {
+                Some(ProcessResult::TransitionToFlee { .. })
+                | Some(ProcessResult::TransitionToMove { .. }) => {
                     if Move::init(conn, self, response) == ProcessResult::Ok {
                         self.active_process = Some(Processes::Move);
                     }
                 }
+                Some(ProcessResult::TransitionToPursue { .. }) => {
+                    let result = Pursue::init(conn, self, response);
+                    if result == ProcessResult::Ok {
+                        self.active_process = Some(Processes::Pursue);
+                    }
+                }
                 _ => {}
             }
         }
+
         // if we have no active process, go to neutral
-        else if let None = self.active_process {
+        if self.active_process.is_none() {
             self.active_process = Some(Processes::Neutral);
         }
 
@@ -938,6 +1333,10 @@ impl Robot {
 
         // make the next move based on our active process
         let process = self.active_process.as_ref().unwrap().clone();
+        metrics()
+            .robot_ticks
+            .with_label_values(&[&process_name(self)])
+            .inc();
         let result = match process {
             Processes::Collect => Some(Collect::run(conn, self, None)),
             Processes::Exfil => Some(Exfil::run(conn, self, None)),
@@ -951,41 +1350,12 @@ impl Robot {
         // recharge batteries
         self.recharge_power(conn);
 
-        // If we are transitioning, initialize it
-        // If we have a request to the server, return it
+        // If we have a request to the server, return it; if we are
+        // transitioning, initialize it
         match result {
-            Some(ProcessResult::TransitionToCollect) => {
-                if Collect::init(conn, self, result) == ProcessResult::Ok {
-                    self.active_process = Some(Processes::Collect);
-                }
-            }
-            Some(ProcessResult::TransitionToExfiltrate) => {
-                if Exfil::init(conn, self, result) == ProcessResult::Ok {
-                    self.active_process = Some(Processes::Exfil);
-                }
-            }
-            Some(ProcessResult::TransitionToFlee { .. }) => {
-                if Move::init(conn, self, result) == ProcessResult::Ok {
-                    self.active_process = Some(Processes::Move);
-                }
-            }
-            Some(ProcessResult::TransitionToMove { .. }) => {
-                if Move::init(conn, self, result) == ProcessResult::Ok {
-                    self.active_process = Some(Processes::Move);
-                }
-            }
-            Some(ProcessResult::TransitionToNeutral) => {
-                Neutral::init(conn, self, result);
-                self.active_process = Some(Processes::Neutral);
-            }
-            Some(ProcessResult::TransitionToPursue { .. }) => {
-                Pursue::init(conn, self, result);
-                self.active_process = Some(Processes::Pursue);
-            }
-            Some(ProcessResult::ServerRequest(request)) => {
-                return Some(request);
-            }
-            _ => {}
+            Some(ProcessResult::ServerRequest(request)) => return Some(request),
+            Some(result) => self.start_process(conn, result),
+            None => {}
         }
 
         None
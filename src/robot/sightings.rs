@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use super::process::*;
+use crate::grid::*;
+
+/// How many ticks we remember where we saw a robot; they move around a lot
+const ROBOT_MEMORY_TICKS: u64 = 20;
+
+/// How many ticks we remember where we saw a valuable
+const VALUABLE_MEMORY_TICKS: u64 = 200;
+
+/// How close to a remembered threat a cell must be to cost extra to path through
+const THREAT_RADIUS: i32 = 2;
+
+/// Extra path cost for a cell right next to a remembered threat; it drops off
+/// with distance from the threat
+const THREAT_COST: u32 = 4;
+
+/// Something we saw on the grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Entity {
+    Robot(i64),
+    Valuable(i64),
+}
+
+/// Where and when we last saw something
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sighting {
+    pub entity: Entity,
+    pub coords: Coords,
+    pub tick: u64,
+    /// how dangerous we thought it was; None for valuables
+    pub threat_level: Option<ThreatLevel>,
+}
+
+impl Sighting {
+    pub fn is_threat(&self) -> bool {
+        matches!(
+            self.threat_level,
+            Some(ThreatLevel::Stronger) | Some(ThreatLevel::Unknown)
+        )
+    }
+}
+
+/// A robot's memory of the hostile robots and valuables it has seen, which
+/// ages out over time
+#[derive(Clone, Debug, Default)]
+pub struct Sightings {
+    entries: HashMap<Entity, Sighting>,
+}
+
+impl Sightings {
+    pub fn new() -> Sightings {
+        Sightings::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, entity: &Entity) -> Option<&Sighting> {
+        self.entries.get(entity)
+    }
+
+    /// Remember a robot, replacing anything older we knew about it
+    pub fn record_robot(&mut self, robot: &VisibleRobot, tick: u64) {
+        self.record(Sighting {
+            entity: Entity::Robot(robot.robot_id),
+            coords: robot.coords,
+            tick,
+            threat_level: Some(robot.threat_level.clone()),
+        });
+    }
+
+    /// Remember a valuable, replacing anything older we knew about it
+    pub fn record_valuable(&mut self, valuable: &VisibleValuable, tick: u64) {
+        self.record(Sighting {
+            entity: Entity::Valuable(valuable.valuable_id),
+            coords: valuable.coords,
+            tick,
+            threat_level: None,
+        });
+    }
+
+    fn record(&mut self, sighting: Sighting) {
+        if let Some(known) = self.entries.get(&sighting.entity) {
+            if known.tick > sighting.tick {
+                return;
+            }
+        }
+        self.entries.insert(sighting.entity, sighting);
+    }
+
+    /// Update our memory with a scan.  Anything we remembered in a scanned cell
+    /// that we didn't see this time has moved on or is gone
+    pub fn update_from_scan(
+        &mut self,
+        scanned_cells: &[Coords],
+        robots: &[VisibleRobot],
+        valuables: &[VisibleValuable],
+        tick: u64,
+    ) {
+        self.entries
+            .retain(|_, sighting| !scanned_cells.contains(&sighting.coords));
+
+        for robot in robots {
+            self.record_robot(robot, tick);
+        }
+        for valuable in valuables {
+            self.record_valuable(valuable, tick);
+        }
+    }
+
+    /// Forget anything we haven't seen in a while
+    pub fn age_out(&mut self, tick: u64) {
+        self.entries.retain(|_, sighting| {
+            let max_age = match sighting.entity {
+                Entity::Robot(_) => ROBOT_MEMORY_TICKS,
+                Entity::Valuable(_) => VALUABLE_MEMORY_TICKS,
+            };
+            tick.saturating_sub(sighting.tick) <= max_age
+        });
+    }
+
+    pub fn robots(&self) -> impl Iterator<Item = &Sighting> {
+        self.entries
+            .values()
+            .filter(|s| matches!(s.entity, Entity::Robot(_)))
+    }
+
+    pub fn valuables(&self) -> impl Iterator<Item = &Sighting> {
+        self.entries
+            .values()
+            .filter(|s| matches!(s.entity, Entity::Valuable(_)))
+    }
+
+    pub fn threats(&self) -> impl Iterator<Item = &Sighting> {
+        self.robots().filter(|s| s.is_threat())
+    }
+
+    /// Extra cost of pathing through a cell because of threats seen near it
+    pub fn threat_cost(&self, coords: &Coords) -> u32 {
+        self.threats()
+            .map(|threat| threat.coords.distance_to(coords))
+            .filter(|distance| *distance <= THREAT_RADIUS)
+            .map(|distance| (THREAT_RADIUS + 1 - distance) as u32 * THREAT_COST)
+            .sum()
+    }
+
+    /// Check if any threat was seen within the given distance of the coords
+    pub fn threat_near(&self, coords: &Coords, distance: i32) -> bool {
+        self.threats()
+            .any(|threat| threat.coords.distance_to(coords) <= distance)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_sightings() {
+    let mut sightings = Sightings::new();
+    let threat = VisibleRobot {
+        robot_id: 7,
+        coords: Coords { q: 2, r: 0 },
+        threat_level: ThreatLevel::Stronger,
+    };
+    let pile = VisibleValuable {
+        valuable_id: 3,
+        coords: Coords { q: 0, r: 2 },
+    };
+    sightings.update_from_scan(&[], &[threat.clone()], &[pile.clone()], 1);
+    assert_eq!(1, sightings.threats().count());
+    assert_eq!(1, sightings.valuables().count());
+
+    // a threat makes the cells around it cost more, the closer the more it costs
+    let next_to = sightings.threat_cost(&Coords { q: 1, r: 0 });
+    let farther = sightings.threat_cost(&Coords { q: 0, r: 0 });
+    assert!(next_to > farther);
+    assert!(farther > 0);
+    assert_eq!(0, sightings.threat_cost(&Coords { q: -2, r: 0 }));
+
+    // looking at the pile's cell and not seeing it means it's gone
+    sightings.update_from_scan(&[pile.coords], &[], &[], 2);
+    assert_eq!(0, sightings.valuables().count());
+
+    // old news of an ally doesn't replace what we saw ourselves
+    let old_news = VisibleRobot {
+        coords: Coords { q: 5, r: 0 },
+        ..threat.clone()
+    };
+    sightings.record_robot(&old_news, 0);
+    assert_eq!(
+        Some(Coords { q: 2, r: 0 }),
+        sightings.get(&Entity::Robot(7)).map(|s| s.coords)
+    );
+
+    sightings.age_out(1 + ROBOT_MEMORY_TICKS);
+    assert_eq!(1, sightings.len());
+    sightings.age_out(2 + ROBOT_MEMORY_TICKS);
+    assert!(sightings.is_empty());
+}
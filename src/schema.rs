@@ -12,6 +12,14 @@ table! {
     }
 }
 
+table! {
+    owners (id) {
+        id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+    }
+}
+
 table! {
     robot_known_cells (robot_id, gridcell_id) {
         robot_id -> Int8,
@@ -19,6 +27,9 @@ table! {
         discovery_time -> Timestamp,
         q -> Int4,
         r -> Int4,
+        last_used -> Timestamp,
+        confidence -> Int4,
+        on_route -> Bool,
     }
 }
 
@@ -33,6 +44,16 @@ table! {
         m_power -> Varchar,
         m_scanner -> Varchar,
         m_weapons -> Varchar,
+        m_comms -> Varchar,
+    }
+}
+
+table! {
+    robot_scripts (name) {
+        name -> Varchar,
+        api_version -> Int4,
+        fuel -> Int8,
+        source -> Text,
     }
 }
 
@@ -63,6 +84,17 @@ table! {
         attacked_by -> Int8,
         attacked -> Int8,
         damage_done -> Int4,
+        behavior -> Varchar,
+    }
+}
+
+table! {
+    spawn_zones (name) {
+        name -> Varchar,
+        affiliation -> Nullable<Int4>,
+        q -> Int4,
+        r -> Int4,
+        radius -> Int4,
     }
 }
 
@@ -82,8 +114,11 @@ joinable!(robot_modules -> robots (robot_id));
 
 allow_tables_to_appear_in_same_query!(
     gridcells,
+    owners,
     robot_known_cells,
     robot_modules,
+    robot_scripts,
     robots,
+    spawn_zones,
     valuables,
 );
@@ -0,0 +1,132 @@
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout, Duration};
+use tracing::info;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use super::endpoints::*;
+use crate::grid::Coords;
+use crate::owner::tokens_match;
+
+/// How long we wait for the server to get to a command before giving up on it
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest command body we accept
+const MAX_BODY: u64 = 16 * 1024;
+
+/// Changes an administrator can make to the running world.  The server applies
+/// them between ticks and broadcasts what they changed
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum AdminCommand {
+    /// Spawn a robot at the given cell, or wherever robots normally spawn
+    SpawnRobot { at: Option<Coords> },
+    /// Take a robot off the grid without leaving anything behind
+    DestroyRobot { robot_id: i64 },
+    /// Move a robot to another cell
+    MoveRobot { robot_id: i64, to: Coords },
+    /// Put valuables on a cell, adding to any already there
+    PlaceValuable { at: Coords, amount: i32 },
+    /// Change how many robots and valuables the server keeps on the grid; together
+    /// they have to fit on the open cells
+    SetLimits {
+        max_bots: Option<usize>,
+        max_valuables: Option<usize>,
+    },
+}
+
+/// A command on its way to the server, with where to send the outcome
+#[derive(Debug)]
+pub struct AdminRequest {
+    pub command: AdminCommand,
+    pub reply: oneshot::Sender<Result<String, String>>,
+}
+
+/// Transmitter of admin requests to the server
+pub type AdminSender = mpsc::UnboundedSender<AdminRequest>;
+
+/// Receiver of admin requests, on the server's side
+pub type AdminReceiver = mpsc::UnboundedReceiver<AdminRequest>;
+
+fn reply(status: StatusCode, text: String) -> Result<Box<dyn Reply>, Infallible> {
+    Ok(Box::new(warp::reply::with_status(text, status)))
+}
+
+async fn handle_command(
+    authorization: Option<String>,
+    body: warp::hyper::body::Bytes,
+    token: Arc<String>,
+    admin_tx: AdminSender,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let given = authorization.as_deref().unwrap_or("");
+    if !tokens_match(&format!("Bearer {}", token), given) {
+        return reply(StatusCode::UNAUTHORIZED, "Bad admin token".to_string());
+    }
+
+    let command: AdminCommand = match serde_json::from_slice(&body) {
+        Ok(command) => command,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, format!("Bad command: {}", e)),
+    };
+    info!(?command, "Admin command");
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let request = AdminRequest {
+        command,
+        reply: reply_tx,
+    };
+    if admin_tx.send(request).is_err() {
+        return reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The server is shutting down".to_string(),
+        );
+    }
+
+    match timeout(REPLY_TIMEOUT, reply_rx).await {
+        Ok(Ok(Ok(outcome))) => reply(StatusCode::OK, outcome),
+        Ok(Ok(Err(reason))) => reply(StatusCode::UNPROCESSABLE_ENTITY, reason),
+        _ => reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The server did not get to the command in time".to_string(),
+        ),
+    }
+}
+
+/// The admin API: POST an `AdminCommand` as JSON to the given path, with the
+/// token as `Authorization: Bearer <token>`.  None if no token is set, in which
+/// case there is no admin API at all
+pub fn routes(admin_path: &str, token: Option<String>, admin_tx: AdminSender) -> Option<Routes> {
+    let token = Arc::new(token?);
+    let token = warp::any().map(move || token.clone());
+    let admin_tx = warp::any().map(move || admin_tx.clone());
+
+    Some(
+        at_path(admin_path)
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::content_length_limit(MAX_BODY))
+            .and(warp::body::bytes())
+            .and(token)
+            .and(admin_tx)
+            .and_then(handle_command)
+            .boxed(),
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn test_admin_commands() {
+    let command: AdminCommand =
+        serde_json::from_str(r#"{"MoveRobot":{"robot_id":4,"to":{"q":1,"r":-2}}}"#).unwrap();
+    assert_eq!(
+        AdminCommand::MoveRobot {
+            robot_id: 4,
+            to: Coords { q: 1, r: -2 }
+        },
+        command
+    );
+
+    let command: AdminCommand = serde_json::from_str(r#"{"SpawnRobot":{}}"#).unwrap();
+    assert_eq!(AdminCommand::SpawnRobot { at: None }, command);
+}
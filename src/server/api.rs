@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use super::endpoints::*;
+use super::protocol::*;
+use super::subscription::Subscription;
+use crate::grid::Coords;
+
+/// The world as it was at the end of a tick
+#[derive(Debug, Default)]
+pub struct WorldSnapshot {
+    pub tick: u64,
+    pub cells: Arc<Vec<CellState>>,
+    pub robots: BTreeMap<i64, RobotState>,
+    pub known_cells: HashMap<i64, Vec<KnownCellState>>,
+    pub valuables: Vec<ValuableState>,
+}
+
+impl WorldSnapshot {
+    /// Cells in the area given by the `q`, `r` and `radius` query parameters, if any
+    pub fn grid(&self, query: &HashMap<String, String>) -> Result<Vec<&CellState>, String> {
+        let subscription = Subscription::from_query(query)?;
+        Ok(self
+            .cells
+            .iter()
+            .filter(|c| subscription.wants_coords(&Coords { q: c.q, r: c.r }))
+            .collect())
+    }
+
+    /// Robots picked by the query parameters, the same way `/listen` picks them
+    pub fn robots(&self, query: &HashMap<String, String>) -> Result<Vec<&RobotState>, String> {
+        let subscription = Subscription::from_query(query)?;
+        Ok(self
+            .robots
+            .values()
+            .filter(|robot| subscription.wants_full_robot(robot))
+            .collect())
+    }
+
+    /// Valuables in the area given by the query parameters, if any
+    pub fn valuables(
+        &self,
+        query: &HashMap<String, String>,
+    ) -> Result<Vec<&ValuableState>, String> {
+        let subscription = Subscription::from_query(query)?;
+        Ok(self
+            .valuables
+            .iter()
+            .filter(|v| subscription.wants_coords(&Coords { q: v.q, r: v.r }))
+            .collect())
+    }
+}
+
+/// Shared read handle on the latest snapshot of the world.  The server swaps in
+/// a new snapshot after each tick, and readers keep the one they got for as
+/// long as they need it, so they never hold up a tick or see one half done
+#[derive(Clone, Debug, Default)]
+pub struct WorldView {
+    latest: Arc<RwLock<Arc<WorldSnapshot>>>,
+}
+
+impl WorldView {
+    pub fn new() -> WorldView {
+        WorldView::default()
+    }
+
+    pub fn latest(&self) -> Arc<WorldSnapshot> {
+        self.latest.read().unwrap().clone()
+    }
+
+    pub fn publish(&self, snapshot: WorldSnapshot) {
+        *self.latest.write().unwrap() = Arc::new(snapshot);
+    }
+}
+
+/// Every answer says which tick it is from
+#[derive(Debug, Serialize)]
+struct ApiReply<T> {
+    tick: u64,
+    data: T,
+}
+
+fn respond<T: Serialize>(tick: u64, result: Result<T, (StatusCode, String)>) -> Box<dyn Reply> {
+    match result {
+        Ok(data) => Box::new(warp::reply::json(&ApiReply { tick, data })),
+        Err((status, reason)) => Box::new(warp::reply::with_status(reason, status)),
+    }
+}
+
+fn bad_request(reason: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, reason)
+}
+
+fn no_robot(robot_id: i64) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("No robot {}", robot_id))
+}
+
+/// The read-only HTTP API, under the given path:
+///
+/// * `grid` - the cells, optionally just those in an area
+/// * `robots` - the robots, picked by the same query parameters as `/listen`
+/// * `robots/{id}` - one robot
+/// * `robots/{id}/known_cells` - the cells a robot remembers
+/// * `valuables` - the valuables, optionally just those in an area
+pub fn routes(api_path: &str, world: WorldView) -> Routes {
+    let api = under_path(api_path).and(warp::get());
+    let snapshot = warp::any().map(move || world.latest());
+    let query = warp::query::<HashMap<String, String>>();
+
+    let grid = api
+        .clone()
+        .and(warp::path!("grid"))
+        .and(query)
+        .and(snapshot.clone())
+        .map(|query, world: Arc<WorldSnapshot>| {
+            respond(world.tick, world.grid(&query).map_err(bad_request))
+        });
+
+    let robots = api
+        .clone()
+        .and(warp::path!("robots"))
+        .and(query)
+        .and(snapshot.clone())
+        .map(|query, world: Arc<WorldSnapshot>| {
+            respond(world.tick, world.robots(&query).map_err(bad_request))
+        });
+
+    let robot = api
+        .clone()
+        .and(warp::path!("robots" / i64))
+        .and(snapshot.clone())
+        .map(|robot_id, world: Arc<WorldSnapshot>| {
+            respond(
+                world.tick,
+                world
+                    .robots
+                    .get(&robot_id)
+                    .ok_or_else(|| no_robot(robot_id)),
+            )
+        });
+
+    let known_cells = api
+        .clone()
+        .and(warp::path!("robots" / i64 / "known_cells"))
+        .and(snapshot.clone())
+        .map(|robot_id, world: Arc<WorldSnapshot>| {
+            respond(
+                world.tick,
+                world
+                    .known_cells
+                    .get(&robot_id)
+                    .ok_or_else(|| no_robot(robot_id)),
+            )
+        });
+
+    let valuables = api
+        .and(warp::path!("valuables"))
+        .and(query)
+        .and(snapshot)
+        .map(|query, world: Arc<WorldSnapshot>| {
+            respond(world.tick, world.valuables(&query).map_err(bad_request))
+        });
+
+    grid.or(robots)
+        .unify()
+        .or(robot)
+        .unify()
+        .or(known_cells)
+        .unify()
+        .or(valuables)
+        .unify()
+        .boxed()
+}
+
+#[cfg(test)]
+#[test]
+fn test_world_view() {
+    let valuable = |id, q, r| ValuableState {
+        id,
+        q,
+        r,
+        kind: "gold".to_string(),
+        amount: 10,
+    };
+
+    let view = WorldView::new();
+    let before = view.latest();
+    view.publish(WorldSnapshot {
+        tick: 7,
+        valuables: vec![valuable(1, 0, 0), valuable(2, 5, -5)],
+        ..WorldSnapshot::default()
+    });
+
+    // readers keep what they got until they ask again
+    assert_eq!(0, before.tick);
+    let world = view.latest();
+    assert_eq!(7, world.tick);
+
+    let mut query = HashMap::new();
+    assert_eq!(2, world.valuables(&query).unwrap().len());
+    query.insert("q".to_string(), "0".to_string());
+    query.insert("r".to_string(), "0".to_string());
+    query.insert("radius".to_string(), "2".to_string());
+    assert_eq!(
+        vec![1],
+        world
+            .valuables(&query)
+            .unwrap()
+            .iter()
+            .map(|v| v.id)
+            .collect::<Vec<i64>>()
+    );
+
+    query.remove("radius");
+    assert!(world.robots(&query).is_err());
+}
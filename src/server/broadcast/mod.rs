@@ -1,27 +1,44 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 
-use crate::grid::GridCell;
-use crate::robot::Robot;
-use crate::valuable::Valuable;
+mod tracker;
 
-/// Broadcast message of updates to the world
-#[derive(Debug, Serialize)]
+pub use tracker::*;
+
+use super::protocol::{CellState, RobotDelta, RobotState, ValuableState};
+
+/// Broadcast message of updates to the world; goes out to listeners in an
+/// `Envelope`
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum BroadcastMessage {
+    /// Everything a listener needs to draw the world, sent when it connects
     InitializerData {
         id: usize,
-        cells: Vec<GridCell>,
-        robots: Vec<Robot>,
-        valuables: Vec<Valuable>,
+        cells: Vec<CellState>,
+        robots: Vec<RobotState>,
+        valuables: Vec<ValuableState>,
     },
     RobotAttacked {
         attacker_id: i64,
         target_id: i64,
     },
-    RobotMoved {
-        robot: Robot,
+    /// The robots that changed this tick, with only the fields that changed
+    RobotsMoved {
+        robots: Vec<RobotDelta>,
+        /// The same robots in full and in the same order, for listeners they
+        /// have just become relevant to; not sent as is
+        #[serde(skip)]
+        full: Vec<RobotState>,
+    },
+    /// Every robot and valuable in full, sent now and then so listeners that
+    /// missed a change catch up
+    Keyframe {
+        robots: Vec<RobotState>,
+        valuables: Vec<ValuableState>,
     },
     RobotSpawned {
-        robot: Robot,
+        robot: RobotState,
     },
     RobotDestroyed {
         robot_id: i64,
@@ -30,10 +47,10 @@ pub enum BroadcastMessage {
         robot_id: i64,
     },
     ValuableCreated {
-        valuable: Valuable,
+        valuable: ValuableState,
     },
     ValuableUpdated {
-        valuable: Valuable,
+        valuable: ValuableState,
     },
     ValuableDepleted {
         valuable_id: i64,
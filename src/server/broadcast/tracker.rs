@@ -0,0 +1,126 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::robot::Robot;
+use crate::server::protocol::{RobotDelta, RobotState};
+
+/// Remembers what we last told listeners about each robot, so we only need to
+/// send the fields that changed
+#[derive(Debug, Default)]
+pub struct RobotTracker {
+    last_sent: HashMap<i64, Map<String, Value>>,
+}
+
+impl RobotTracker {
+    pub fn new() -> RobotTracker {
+        RobotTracker::default()
+    }
+
+    /// Get the fields of the robot that changed since we last sent it, along with
+    /// its id, and remember them as sent; None if nothing changed.  A robot we
+    /// haven't sent before comes back in full
+    pub fn changes(&mut self, robot: &Robot) -> Option<RobotDelta> {
+        let current = to_map(robot);
+        let mut changes: Map<String, Value> = match self.last_sent.get(&robot.data.id) {
+            Some(last) => current
+                .iter()
+                .filter(|(key, value)| last.get(*key) != Some(value))
+                .map(|(key, value)| match key.as_str() {
+                    "status_text" => (
+                        "status_added".to_string(),
+                        status_added(last.get(key), value),
+                    ),
+                    _ => (key.clone(), value.clone()),
+                })
+                .collect(),
+            None => current.clone(),
+        };
+        self.last_sent.insert(robot.data.id, current);
+
+        if changes.is_empty() {
+            return None;
+        }
+        changes.insert("id".to_string(), robot.data.id.into());
+        Some(RobotDelta(changes))
+    }
+
+    /// Remember the robots as sent in full
+    pub fn record_all<'a>(&mut self, robots: impl Iterator<Item = &'a Robot>) {
+        for robot in robots {
+            self.last_sent.insert(robot.data.id, to_map(robot));
+        }
+    }
+
+    /// Forget a robot that has left the grid
+    pub fn forget(&mut self, robot_id: i64) {
+        self.last_sent.remove(&robot_id);
+    }
+}
+
+/// The status text is a log of the robot's last few statuses, newest first.  Get
+/// just the lines added to the top of it since we last sent it
+fn status_added(last: Option<&Value>, current: &Value) -> Value {
+    let last: Vec<&str> = last
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .lines()
+        .collect();
+    let current: Vec<&str> = current.as_str().unwrap_or("").lines().collect();
+
+    // the old lines get pushed down and the oldest fall off the bottom
+    let added = (0..current.len())
+        .find(|added| {
+            let kept = &current[*added..];
+            last.len() >= kept.len() && last[..kept.len()] == *kept
+        })
+        .unwrap_or(current.len());
+
+    current[..added].join("\n").into()
+}
+
+fn to_map(robot: &Robot) -> Map<String, Value> {
+    match serde_json::to_value(RobotState::from(robot)) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_robot_tracker() {
+    use crate::grid::*;
+    use std::sync::{Arc, Mutex};
+
+    let grid = Arc::new(Mutex::new(Grid::new(2, None).unwrap()));
+    let mut robot = Robot::new(Coords { q: 0, r: 0 }, Dir::Orient0, None, grid, None);
+    let mut tracker = RobotTracker::new();
+
+    let first = tracker.changes(&robot).unwrap();
+    assert_eq!(to_map(&robot), first.0);
+    assert_eq!(None, tracker.changes(&robot));
+
+    // a typical tick: the robot takes a step and uses some power
+    robot.data.q = 1;
+    robot.data.power -= 10;
+    let changes = tracker.changes(&robot).unwrap();
+    assert_eq!(
+        vec!["id", "power", "q"],
+        changes.0.keys().map(|k| k.as_str()).collect::<Vec<&str>>()
+    );
+
+    // what each robot costs in a batch, next to sending it in full
+    let delta = serde_json::to_string(&changes).unwrap();
+    let full = serde_json::to_string(&RobotState::from(&robot)).unwrap();
+    assert!(delta.len() * 10 < full.len());
+
+    // only the new status line goes out, not the whole log
+    robot.set_status_text(None, "I'm idle.");
+    robot.set_status_text(None, "I'm pursuing Robot 4.");
+    tracker.changes(&robot);
+    robot.set_status_text(None, "I'm fleeing to 3,-1!");
+    let changes = tracker.changes(&robot).unwrap();
+    assert_eq!(
+        Some(&Value::from("I'm fleeing to 3,-1!")),
+        changes.0.get("status_added")
+    );
+}
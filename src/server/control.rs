@@ -0,0 +1,186 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use super::protocol::{CoordsState, ModulesState, ScanState};
+use crate::grid::{Coords, Dir};
+use crate::robot::behaviors::Action;
+use crate::robot::Robot;
+
+/// Messages a remote controller sends to the server.  The first message on a
+/// connection must be `Authenticate`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub enum ControlRequest {
+    Authenticate {
+        owner: String,
+        token: String,
+    },
+    /// The action one of our robots should take on the given tick
+    Act {
+        tick: u64,
+        robot_id: i64,
+        action: ActionRequest,
+    },
+}
+
+/// An action for one of our robots
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq)]
+pub enum ActionRequest {
+    MoveTo(CoordsState),
+    /// Degrees; one of 0, 60, 120, 180, 240 or 300
+    Turn(i16),
+    Scan,
+    Mine,
+    /// The id of the robot to attack
+    Attack(i64),
+    Exfiltrate,
+}
+
+impl TryFrom<ActionRequest> for Action {
+    type Error = String;
+
+    fn try_from(request: ActionRequest) -> Result<Action, String> {
+        Ok(match request {
+            ActionRequest::MoveTo(coords) => Action::MoveTo(Coords {
+                q: coords.q,
+                r: coords.r,
+            }),
+            ActionRequest::Turn(degrees) => Action::Turn(
+                Dir::get_vec()
+                    .into_iter()
+                    .find(|dir| *dir as i16 == degrees)
+                    .ok_or_else(|| format!("Can't turn to {} degrees", degrees))?,
+            ),
+            ActionRequest::Scan => Action::Scan,
+            ActionRequest::Mine => Action::Mine,
+            ActionRequest::Attack(robot_id) => Action::Attack(robot_id),
+            ActionRequest::Exfiltrate => Action::Exfiltrate,
+        })
+    }
+}
+
+/// Messages the server sends to a remote controller
+#[derive(Debug, Serialize, JsonSchema)]
+pub enum ControlEvent {
+    Authenticated {
+        owner_id: i32,
+    },
+    AuthenticationFailed {
+        reason: String,
+    },
+    /// What one of our robots sees at the start of a tick; reply with an `Act`
+    /// within `deadline_ms` or the robot falls back to its own behavior
+    Observation {
+        tick: u64,
+        deadline_ms: u64,
+        robot: Box<RobotObservation>,
+        scan: ScanState,
+    },
+    /// A request we could not act on
+    Rejected {
+        reason: String,
+    },
+}
+
+/// What a controller gets to see of one of its robots
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RobotObservation {
+    pub id: i64,
+    pub name: String,
+    pub affiliation: Option<i32>,
+    pub q: i32,
+    pub r: i32,
+    /// Degrees; one of 0, 60, 120, 180, 240 or 300
+    pub orientation: i16,
+    pub power: i32,
+    pub max_power: i32,
+    pub hull_strength: i32,
+    pub max_hull_strength: i32,
+    pub val_inventory: i32,
+    pub max_val_inventory: i32,
+    pub exfil_countdown: i32,
+    /// The side the robot was last attacked from, in degrees, while it is under attack
+    pub attacked_from: Option<i16>,
+    pub active_process: Option<String>,
+    pub modules: ModulesState,
+}
+
+impl RobotObservation {
+    pub fn new(robot: &Robot) -> RobotObservation {
+        RobotObservation {
+            id: robot.data.id,
+            name: robot.data.name.clone(),
+            affiliation: robot.data.affiliation,
+            q: robot.data.q,
+            r: robot.data.r,
+            orientation: robot.data.orientation as i16,
+            power: robot.data.power,
+            max_power: robot.data.max_power,
+            hull_strength: robot.data.hull_strength,
+            max_hull_strength: robot.data.max_hull_strength,
+            val_inventory: robot.data.val_inventory,
+            max_val_inventory: robot.data.max_val_inventory,
+            exfil_countdown: robot.data.exfil_countdown,
+            attacked_from: if robot.is_under_attack() {
+                Some(robot.get_attack_dir() as i16)
+            } else {
+                None
+            },
+            active_process: robot
+                .active_process
+                .as_ref()
+                .map(|process| format!("{:?}", process)),
+            modules: ModulesState::from(&robot.modules),
+        }
+    }
+}
+
+/// An event for every connected controller of an owner
+#[derive(Debug)]
+pub struct ControlOutgoing {
+    pub owner_id: i32,
+    pub event: ControlEvent,
+}
+
+/// Updates from the websocket server about remote controllers
+#[derive(Debug)]
+pub enum ControllerUpdate {
+    Connected {
+        owner_id: i32,
+    },
+    Disconnected {
+        owner_id: i32,
+    },
+    Act {
+        owner_id: i32,
+        tick: u64,
+        robot_id: i64,
+        action: Action,
+    },
+}
+
+#[cfg(test)]
+#[test]
+fn test_control_requests() {
+    let action = |request: &str| match serde_json::from_str::<ControlRequest>(request) {
+        Ok(ControlRequest::Act {
+            tick: 4,
+            robot_id: 7,
+            action,
+        }) => Action::try_from(action),
+        other => panic!("Unexpected {:?}", other),
+    };
+
+    let request = r#"{"Act": {"tick": 4, "robot_id": 7, "action": {"MoveTo": {"q": 1, "r": -2}}}}"#;
+    assert_eq!(Ok(Action::MoveTo(Coords { q: 1, r: -2 })), action(request));
+
+    let request = r#"{"Act": {"tick": 4, "robot_id": 7, "action": {"Turn": 120}}}"#;
+    assert_eq!(Ok(Action::Turn(Dir::Orient120)), action(request));
+
+    // a direction that isn't one of the six is rejected
+    let request = r#"{"Act": {"tick": 4, "robot_id": 7, "action": {"Turn": 100}}}"#;
+    assert!(action(request).is_err());
+
+    let request = r#"{"Act": {"tick": 4, "robot_id": 7, "action": "Exfiltrate"}}"#;
+    assert_eq!(Ok(Action::Exfiltrate), action(request));
+}
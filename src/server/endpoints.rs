@@ -0,0 +1,309 @@
+use clap::{App, Arg, ArgMatches};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Reply};
+
+/// Everything we serve, whatever it replies with
+pub type Routes = BoxedFilter<(Box<dyn Reply>,)>;
+
+/// Certificate and private key files, in PEM format, for serving over TLS
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Where the websocket server listens and the paths it serves
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndpointConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    /// where observers connect for broadcasts
+    pub listen_path: String,
+    /// where remote controllers connect
+    pub control_path: String,
+    /// if set, remote controllers connect on this port rather than `port`
+    pub control_port: Option<u16>,
+    /// answers with a small status report, for load balancers and proxies
+    pub health_path: String,
+    /// Prometheus metrics, served on every port like the health check
+    pub metrics_path: String,
+    /// prefix of the read-only HTTP API
+    pub api_path: String,
+    /// where administrators send commands, on the same port as controllers
+    pub admin_path: String,
+    /// token administrators must send; without one there is no admin API
+    pub admin_token: Option<String>,
+    pub tls: Option<TlsConfig>,
+}
+
+impl EndpointConfig {
+    /// Build the config from arguments added with `add_endpoint_args`
+    pub fn from_matches(matches: &ArgMatches) -> Result<EndpointConfig, String> {
+        let host = matches.value_of("ws_host").unwrap_or("127.0.0.1");
+        let port = matches.value_of("ws_port").unwrap_or("3820");
+        let control_port = match matches.value_of("control_port") {
+            Some(port) => Some(
+                port.parse::<u16>()
+                    .map_err(|_| format!("Bad control port {}", port))?,
+            ),
+            None => None,
+        };
+
+        let config = EndpointConfig {
+            host: host
+                .parse::<IpAddr>()
+                .map_err(|_| format!("Bad host address {}", host))?,
+            port: port
+                .parse::<u16>()
+                .map_err(|_| format!("Bad port {}", port))?,
+            listen_path: normalize_path(matches.value_of("listen_path").unwrap_or("listen"))?,
+            control_path: normalize_path(matches.value_of("control_path").unwrap_or("control"))?,
+            control_port,
+            health_path: normalize_path(matches.value_of("health_path").unwrap_or("health"))?,
+            metrics_path: normalize_path(matches.value_of("metrics_path").unwrap_or("metrics"))?,
+            api_path: normalize_path(matches.value_of("api_path").unwrap_or("api"))?,
+            admin_path: normalize_path(matches.value_of("admin_path").unwrap_or("admin"))?,
+            admin_token: matches
+                .value_of("admin_token")
+                .filter(|token| !token.is_empty())
+                .map(|token| token.to_string()),
+            tls: match (matches.value_of("tls_cert"), matches.value_of("tls_key")) {
+                (Some(cert), Some(key)) => Some(TlsConfig {
+                    cert: PathBuf::from(cert),
+                    key: PathBuf::from(key),
+                }),
+                _ => None,
+            },
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.control_port == Some(self.port) {
+            return Err("The control port must differ from the main port".to_string());
+        }
+
+        // the health check and metrics are served on every port; everything
+        // else only clashes with what is served on the same port
+        fn has_duplicates(paths: &[&String]) -> bool {
+            paths
+                .iter()
+                .enumerate()
+                .any(|(i, path)| paths[..i].contains(path))
+        }
+        let clashes = match self.control_port {
+            Some(_) => {
+                has_duplicates(&[
+                    &self.listen_path,
+                    &self.api_path,
+                    &self.health_path,
+                    &self.metrics_path,
+                ]) || has_duplicates(&[
+                    &self.control_path,
+                    &self.admin_path,
+                    &self.health_path,
+                    &self.metrics_path,
+                ])
+            }
+            None => has_duplicates(&[
+                &self.listen_path,
+                &self.api_path,
+                &self.control_path,
+                &self.admin_path,
+                &self.health_path,
+                &self.metrics_path,
+            ]),
+        };
+        if clashes {
+            return Err(
+                "Listen, control, health, metrics, API and admin paths must differ".to_string(),
+            );
+        }
+
+        if let Some(tls) = &self.tls {
+            for file in &[&tls.cert, &tls.key] {
+                if !file.is_file() {
+                    return Err(format!("Cannot read TLS file {}", file.display()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+
+    /// Where remote controllers connect, if not on the main address
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.control_port
+            .map(|port| SocketAddr::new(self.host, port))
+    }
+}
+
+/// Paths are given with or without slashes around them; we keep them without
+fn normalize_path(path: &str) -> Result<String, String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() || trimmed.split('/').any(|segment| segment.is_empty()) {
+        return Err(format!("Bad path {}", path));
+    }
+
+    Ok(trimmed.to_string())
+}
+
+/// A filter matching exactly the given path, which may have several segments
+pub fn at_path(path: &str) -> BoxedFilter<()> {
+    under_path(path).and(warp::path::end()).boxed()
+}
+
+/// A filter matching the given path and anything below it
+pub fn under_path(path: &str) -> BoxedFilter<()> {
+    path.split('/')
+        .fold(warp::any().boxed(), |filter, segment| {
+            filter.and(warp::path(segment.to_string())).boxed()
+        })
+}
+
+/// Add the arguments for where the websocket server listens
+pub fn add_endpoint_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("ws_host")
+            .long("ws_host")
+            .takes_value(true)
+            .default_value("127.0.0.1")
+            .help("Address the websocket server binds to"),
+    )
+    .arg(
+        Arg::with_name("ws_port")
+            .long("ws_port")
+            .takes_value(true)
+            .default_value("3820")
+            .help("Port the websocket server listens on"),
+    )
+    .arg(
+        Arg::with_name("listen_path")
+            .long("listen_path")
+            .takes_value(true)
+            .default_value("listen")
+            .help("Path observers connect to"),
+    )
+    .arg(
+        Arg::with_name("control_path")
+            .long("control_path")
+            .takes_value(true)
+            .default_value("control")
+            .help("Path remote controllers connect to"),
+    )
+    .arg(
+        Arg::with_name("control_port")
+            .long("control_port")
+            .takes_value(true)
+            .help("Serve remote controllers on their own port"),
+    )
+    .arg(
+        Arg::with_name("health_path")
+            .long("health_path")
+            .takes_value(true)
+            .default_value("health")
+            .help("Path of the health check"),
+    )
+    .arg(
+        Arg::with_name("metrics_path")
+            .long("metrics_path")
+            .takes_value(true)
+            .default_value("metrics")
+            .help("Path of the Prometheus metrics"),
+    )
+    .arg(
+        Arg::with_name("api_path")
+            .long("api_path")
+            .takes_value(true)
+            .default_value("api")
+            .help("Path the read-only HTTP API is served under"),
+    )
+    .arg(
+        Arg::with_name("admin_path")
+            .long("admin_path")
+            .takes_value(true)
+            .default_value("admin")
+            .help("Path administrators send commands to"),
+    )
+    .arg(
+        Arg::with_name("admin_token")
+            .long("admin_token")
+            .takes_value(true)
+            .env("ARES_ADMIN_TOKEN")
+            .hide_env_values(true)
+            .help("Token administrators must send; the admin API is off without one"),
+    )
+    .arg(
+        Arg::with_name("tls_cert")
+            .long("tls_cert")
+            .takes_value(true)
+            .requires("tls_key")
+            .help("Certificate file to serve TLS with"),
+    )
+    .arg(
+        Arg::with_name("tls_key")
+            .long("tls_key")
+            .takes_value(true)
+            .requires("tls_cert")
+            .help("Private key file to serve TLS with"),
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn test_endpoint_config() {
+    let matches = |args: &[&str]| {
+        add_endpoint_args(App::new("test"))
+            .get_matches_from_safe(std::iter::once("test").chain(args.iter().cloned()))
+            .unwrap()
+    };
+
+    let config = EndpointConfig::from_matches(&matches(&[])).unwrap();
+    assert_eq!("127.0.0.1:3820", config.addr().to_string());
+    assert_eq!(
+        ("listen", "control"),
+        (&*config.listen_path, &*config.control_path)
+    );
+    assert_eq!(None, config.control_addr());
+    assert_eq!(None, config.admin_token);
+
+    let config = EndpointConfig::from_matches(&matches(&[
+        "--ws_host",
+        "0.0.0.0",
+        "--listen_path",
+        "/ares/observe/",
+        "--control_port",
+        "3821",
+    ]))
+    .unwrap();
+    assert_eq!("ares/observe", config.listen_path);
+    assert_eq!("0.0.0.0:3821", config.control_addr().unwrap().to_string());
+
+    assert!(EndpointConfig::from_matches(&matches(&["--listen_path", "control"])).is_err());
+    assert!(EndpointConfig::from_matches(&matches(&["--listen_path", "a//b"])).is_err());
+    assert!(EndpointConfig::from_matches(&matches(&["--api_path", "health"])).is_err());
+    assert!(EndpointConfig::from_matches(&matches(&["--admin_path", "listen"])).is_err());
+    assert!(EndpointConfig::from_matches(&matches(&[
+        "--admin_path",
+        "listen",
+        "--control_port",
+        "3821"
+    ]))
+    .is_ok());
+    assert!(EndpointConfig::from_matches(&matches(&["--ws_port", "99999"])).is_err());
+    assert!(EndpointConfig::from_matches(&matches(&[
+        "--tls_cert",
+        "/nonexistent/cert.pem",
+        "--tls_key",
+        "/nonexistent/key.pem"
+    ]))
+    .is_err());
+}
@@ -1,19 +1,33 @@
 use clap::{App, Arg};
 use diesel::pg::PgConnection;
 
+pub mod admin;
+pub mod api;
 pub mod broadcast;
+pub mod control;
+pub mod endpoints;
+pub mod protocol;
 pub mod server;
+pub mod subscription;
 pub mod ws;
 
 pub use server::Server;
 pub use ws::WebsocketServer;
 
 use crate::db::*;
+use crate::logging::*;
+use endpoints::*;
 
 pub struct ServerConfig {
     pub dbconfig: DbConfig,
     pub conn: Option<PgConnection>,
 
+    // where the websocket server listens
+    pub endpoints: EndpointConfig,
+
+    // what gets logged and how
+    pub log: LogConfig,
+
     // maximum number of robots to spawn
     max_bots: usize,
 
@@ -23,57 +37,89 @@ pub struct ServerConfig {
     // do killed robots drop valuables
     no_kill_drops: bool,
 
+    // how many teams to split spawned robots into; 0 means no teams
+    teams: i32,
+
+    // robots won't spawn closer than this to a hostile robot
+    spawn_distance: i32,
+
+    // how long remote controllers have to reply each tick, in milliseconds
+    control_deadline: u64,
+
+    // how many ticks between broadcasts of every robot in full
+    keyframe_interval: u64,
+
+    // how many messages a listener can fall behind before we disconnect it
+    listener_queue: usize,
+
+    // how many events a controller can fall behind before we disconnect it
+    controller_queue: usize,
+
     debug: bool,
 }
 
 pub fn get_config() -> ServerConfig {
-    let matches = App::new("Ares Grid Admin")
+    let matches = add_log_args(add_endpoint_args(add_db_args(App::new("Ares Grid Admin"))))
         .version("0.1.0")
         .about("Create/maintain grids")
         .arg(
-            Arg::with_name("dbuser")
-                .short("u")
-                .long("user")
+            Arg::with_name("max_bots")
+                .required(true)
                 .takes_value(true)
-                .help("Database username"),
+                .help("Maximum number of robots"),
+        )
+        .arg(
+            Arg::with_name("max_valuables")
+                .required(true)
+                .takes_value(true)
+                .help("How many valuables piles to keep"),
+        )
+        .arg(
+            Arg::with_name("no_kill_drops")
+                .long("no_kill_drops")
+                .help("Kill will not drop valuables"),
         )
         .arg(
-            Arg::with_name("dbpw")
-                .short("p")
-                .long("password")
+            Arg::with_name("teams")
+                .long("teams")
                 .takes_value(true)
-                .help("Database password"),
+                .default_value("0")
+                .help("Number of teams to split robots into; 0 for every robot for itself"),
         )
         .arg(
-            Arg::with_name("dbhost")
-                .short("o")
-                .long("hostname")
+            Arg::with_name("spawn_distance")
+                .long("spawn_distance")
                 .takes_value(true)
-                .help("Database hostname"),
+                .default_value("0")
+                .help("Minimum distance from hostile robots when spawning; 0 to spawn anywhere"),
         )
         .arg(
-            Arg::with_name("db")
-                .short("n")
-                .long("dbname")
+            Arg::with_name("control_deadline")
+                .long("control_deadline")
                 .takes_value(true)
-                .help("Database name"),
+                .default_value("250")
+                .help("Milliseconds remote controllers have to reply each tick"),
         )
         .arg(
-            Arg::with_name("max_bots")
-                .required(true)
+            Arg::with_name("keyframe_interval")
+                .long("keyframe_interval")
                 .takes_value(true)
-                .help("Maximum number of robots"),
+                .default_value("30")
+                .help("Ticks between full broadcasts of every robot"),
         )
         .arg(
-            Arg::with_name("max_valuables")
-                .required(true)
+            Arg::with_name("listener_queue")
+                .long("listener_queue")
                 .takes_value(true)
-                .help("How many valuables piles to keep"),
+                .default_value("1024")
+                .help("Messages a listener can fall behind before it is disconnected"),
         )
         .arg(
-            Arg::with_name("no_kill_drops")
-                .long("no_kill_drops")
-                .help("Kill will not drop valuables"),
+            Arg::with_name("controller_queue")
+                .long("controller_queue")
+                .takes_value(true)
+                .default_value("256")
+                .help("Events a remote controller can fall behind before it is disconnected"),
         )
         .arg(
             Arg::with_name("debug")
@@ -82,14 +128,6 @@ pub fn get_config() -> ServerConfig {
         )
         .get_matches();
 
-    let dbuser = matches.value_of("dbuser").unwrap_or("ares").to_string();
-    let dbpw = matches.value_of("dbpw").unwrap_or("ares").to_string();
-    let dbhost = matches
-        .value_of("dbhost")
-        .unwrap_or("localhost")
-        .to_string();
-    let dbname = matches.value_of("db").unwrap_or("ares").to_string();
-
     let max_bots = matches.value_of("max_bots").unwrap_or("10");
     let max_bots = max_bots.parse::<usize>().expect("Could not parse max bots");
 
@@ -98,20 +136,65 @@ pub fn get_config() -> ServerConfig {
         .parse::<usize>()
         .expect("Could not parse max valuables");
 
-    let dbconfig = DbConfig {
-        dbuser,
-        dbpw,
-        dbhost,
-        dbname,
-    };
+    let teams = matches
+        .value_of("teams")
+        .unwrap_or("0")
+        .parse::<i32>()
+        .expect("Could not parse teams");
+
+    let spawn_distance = matches
+        .value_of("spawn_distance")
+        .unwrap_or("0")
+        .parse::<i32>()
+        .expect("Could not parse spawn distance");
+
+    let control_deadline = matches
+        .value_of("control_deadline")
+        .unwrap_or("250")
+        .parse::<u64>()
+        .expect("Could not parse control deadline");
+
+    let keyframe_interval = matches
+        .value_of("keyframe_interval")
+        .unwrap_or("30")
+        .parse::<u64>()
+        .expect("Could not parse keyframe interval")
+        .max(1);
+
+    let listener_queue = matches
+        .value_of("listener_queue")
+        .unwrap_or("1024")
+        .parse::<usize>()
+        .expect("Could not parse listener queue")
+        .max(1);
+
+    let controller_queue = matches
+        .value_of("controller_queue")
+        .unwrap_or("256")
+        .parse::<usize>()
+        .expect("Could not parse controller queue")
+        .max(1);
+
+    let endpoints = EndpointConfig::from_matches(&matches).expect("Bad endpoint config");
+    let log = LogConfig::from_matches(&matches).expect("Bad log config");
+
+    let dbconfig = DbConfig::from_matches(&matches);
     let conn = establish_connection(&dbconfig);
 
     ServerConfig {
         dbconfig,
         conn: Some(conn),
+        endpoints,
+        log,
         max_bots,
         max_valuables,
         no_kill_drops: matches.is_present("no_kill_drops"),
+        teams,
+        spawn_distance,
+        control_deadline,
+        keyframe_interval,
+        listener_queue,
+        controller_queue,
         debug: matches.is_present("debug"),
     }
 }
@@ -0,0 +1,196 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use warp::ws::Message;
+
+use super::*;
+use crate::server::broadcast::BroadcastMessage;
+
+/// How messages to a listener are encoded; picked with the `encoding` query
+/// parameter when connecting.  JSON goes out as text, MessagePack as binary with
+/// the cells of `InitializerData` packed as `PackedCell`s
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Encoding, String> {
+        match query.get("encoding").map(|e| e.as_str()) {
+            None | Some("json") => Ok(Encoding::Json),
+            Some("msgpack") => Ok(Encoding::MessagePack),
+            Some(other) => Err(format!(
+                "Unknown encoding {}; expected json or msgpack",
+                other
+            )),
+        }
+    }
+
+    pub fn encode(self, envelope: &Envelope) -> Result<Message, String> {
+        match self {
+            Encoding::Json => serde_json::to_string(envelope)
+                .map(Message::text)
+                .map_err(|e| format!("{}", e)),
+            Encoding::MessagePack => {
+                let encoded = match &envelope.message {
+                    BroadcastMessage::InitializerData {
+                        id,
+                        cells,
+                        robots,
+                        valuables,
+                    } => rmp_serde::to_vec_named(&PackedInitializerData {
+                        version: envelope.version,
+                        tick: envelope.tick,
+                        kind: "initializer_data",
+                        payload: PackedInitializerPayload {
+                            id: *id,
+                            cells: cells.iter().map(PackedCell::from).collect(),
+                            robots,
+                            valuables,
+                        },
+                    }),
+                    _ => rmp_serde::to_vec_named(envelope),
+                };
+
+                encoded.map(Message::binary).map_err(|e| format!("{}", e))
+            }
+        }
+    }
+}
+
+/// An envelope on its way to listeners.  Each encoding of it is made at most
+/// once, by the first listener that wants it all, and shared with the rest
+#[derive(Debug)]
+pub struct Outgoing {
+    pub envelope: Envelope,
+    encoded: [OnceLock<Option<Message>>; 2],
+}
+
+impl Outgoing {
+    pub fn new(envelope: Envelope) -> Outgoing {
+        Outgoing {
+            envelope,
+            encoded: Default::default(),
+        }
+    }
+
+    /// The whole envelope in the given encoding; None if it can't be encoded
+    pub fn encoded(&self, encoding: Encoding) -> Option<Message> {
+        self.encoded[encoding as usize]
+            .get_or_init(|| encoding.encode(&self.envelope).ok())
+            .clone()
+    }
+}
+
+/// A grid cell as sent in binary encodings; bit n of `walls` is set if there's
+/// a wall on the side at n * 60 degrees
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct PackedCell {
+    pub id: i32,
+    pub q: i32,
+    pub r: i32,
+    pub walls: u8,
+}
+
+impl From<&CellState> for PackedCell {
+    fn from(cell: &CellState) -> PackedCell {
+        let edges = [
+            cell.edge0,
+            cell.edge60,
+            cell.edge120,
+            cell.edge180,
+            cell.edge240,
+            cell.edge300,
+        ];
+
+        PackedCell {
+            id: cell.id,
+            q: cell.q,
+            r: cell.r,
+            walls: edges
+                .iter()
+                .enumerate()
+                .filter(|(_, edge)| **edge != 0)
+                .fold(0, |walls, (side, _)| walls | 1 << side),
+        }
+    }
+}
+
+/// The envelope around `InitializerData` with its cells packed
+#[derive(Serialize)]
+struct PackedInitializerData<'a> {
+    version: u32,
+    tick: u64,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    payload: PackedInitializerPayload<'a>,
+}
+
+#[derive(Serialize)]
+struct PackedInitializerPayload<'a> {
+    id: usize,
+    cells: Vec<PackedCell>,
+    robots: &'a [RobotState],
+    valuables: &'a [ValuableState],
+}
+
+#[cfg(test)]
+#[test]
+fn test_encoding() {
+    use serde_json::Value;
+
+    let cells: Vec<CellState> = (0..100)
+        .map(|id| CellState {
+            id,
+            q: id,
+            r: -id,
+            edge0: 1,
+            edge60: 0,
+            edge120: 0,
+            edge180: 1,
+            edge240: 0,
+            edge300: 1,
+        })
+        .collect();
+    let envelope = Envelope::new(
+        3,
+        BroadcastMessage::InitializerData {
+            id: 1,
+            cells,
+            robots: Vec::new(),
+            valuables: Vec::new(),
+        },
+    );
+
+    let json = Encoding::Json.encode(&envelope).unwrap();
+    let packed = Encoding::MessagePack.encode(&envelope).unwrap();
+    assert!(json.is_text() && packed.is_binary());
+
+    let outgoing = Outgoing::new(envelope.clone());
+    assert_eq!(
+        Some(packed.clone()),
+        outgoing.encoded(Encoding::MessagePack)
+    );
+    assert_eq!(Some(json.clone()), outgoing.encoded(Encoding::Json));
+    assert!(packed.as_bytes().len() * 3 < json.as_bytes().len());
+
+    let decoded: Value = rmp_serde::from_slice(packed.as_bytes()).unwrap();
+    assert_eq!(Value::from("initializer_data"), decoded["type"]);
+    assert_eq!(Value::from(3), decoded["tick"]);
+    assert_eq!(
+        Value::from(0b101001),
+        decoded["payload"]["cells"][0]["walls"]
+    );
+
+    // everything else is the same as in JSON
+    let envelope = Envelope::new(3, BroadcastMessage::RobotDestroyed { robot_id: 4 });
+    let packed = Encoding::MessagePack.encode(&envelope).unwrap();
+    let decoded: Value = rmp_serde::from_slice(packed.as_bytes()).unwrap();
+    assert_eq!(serde_json::to_value(&envelope).unwrap(), decoded);
+
+    let mut query = HashMap::new();
+    query.insert("encoding".to_string(), "cbor".to_string());
+    assert!(Encoding::from_query(&query).is_err());
+}
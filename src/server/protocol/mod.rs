@@ -0,0 +1,75 @@
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+mod encoding;
+mod state;
+
+pub use encoding::*;
+pub use state::*;
+
+use super::broadcast::BroadcastMessage;
+use super::control::{ControlEvent, ControlRequest};
+use super::subscription::ListenRequest;
+
+/// Bump whenever a change to the messages could break an existing client
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Every broadcast goes out in an envelope: `type` names the message and
+/// `payload` carries it, while `version` and `tick` say which version of the
+/// protocol and which tick of the world it belongs to
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Envelope {
+    pub version: u32,
+    pub tick: u64,
+    #[serde(flatten)]
+    pub message: BroadcastMessage,
+}
+
+impl Envelope {
+    pub fn new(tick: u64, message: BroadcastMessage) -> Envelope {
+        Envelope {
+            version: PROTOCOL_VERSION,
+            tick,
+            message,
+        }
+    }
+}
+
+/// JSON schema for the protocol, for generating client code.  The root is the
+/// envelope listeners receive; what they can send back, how binary encodings
+/// pack cells and what controllers send and receive are in the definitions
+pub fn schema() -> RootSchema {
+    let mut gen = SchemaSettings::draft07().into_generator();
+    gen.subschema_for::<ListenRequest>();
+    gen.subschema_for::<ControlRequest>();
+    gen.subschema_for::<ControlEvent>();
+    gen.subschema_for::<PackedCell>();
+
+    let mut schema = gen.into_root_schema_for::<Envelope>();
+    schema.schema.metadata().title = Some(format!("ARES protocol v{}", PROTOCOL_VERSION));
+    schema
+}
+
+#[cfg(test)]
+#[test]
+fn test_envelope() {
+    let envelope = Envelope::new(12, BroadcastMessage::RobotDestroyed { robot_id: 4 });
+    assert_eq!(
+        r#"{"version":1,"tick":12,"type":"robot_destroyed","payload":{"robot_id":4}}"#,
+        serde_json::to_string(&envelope).unwrap()
+    );
+
+    let schema = serde_json::to_value(schema()).unwrap();
+    for definition in &[
+        "RobotState",
+        "RobotDelta",
+        "ListenRequest",
+        "ControlRequest",
+        "ControlEvent",
+        "ScanState",
+    ] {
+        assert!(schema["definitions"].get(definition).is_some());
+    }
+}
@@ -0,0 +1,322 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::grid::{Coords, GridCell};
+use crate::robot::process::ScanResults;
+use crate::robot::{Robot, RobotKnownCell, RobotModules};
+use crate::valuable::Valuable;
+
+/// A robot as clients see it
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct RobotState {
+    pub id: i64,
+    pub name: String,
+    pub owner: Option<i32>,
+    pub affiliation: Option<i32>,
+    pub q: i32,
+    pub r: i32,
+    /// Degrees; one of 0, 60, 120, 180, 240 or 300
+    pub orientation: i16,
+    pub power: i32,
+    pub max_power: i32,
+    pub recharge_rate: i32,
+    pub hull_strength: i32,
+    pub max_hull_strength: i32,
+    pub mined_amount: i32,
+    pub val_inventory: i32,
+    pub max_val_inventory: i32,
+    pub exfil_countdown: i32,
+    pub hibernate_countdown: i32,
+    /// The robot's last few statuses, newest first, one per line
+    pub status_text: String,
+    /// The side the robot was last attacked from, in degrees, while it is under attack
+    pub attacked_from: Option<i16>,
+    /// The robot that last attacked it, while it is under attack
+    pub attacked_by: Option<i64>,
+    pub damage_done: i32,
+    pub behavior: String,
+    pub active_process: Option<String>,
+    pub modules: ModulesState,
+}
+
+/// The module loaded in each of a robot's slots
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ModulesState {
+    pub collector: String,
+    pub comms: String,
+    pub drivesystem: String,
+    pub exfilbeacon: String,
+    pub hull: String,
+    pub memory: String,
+    pub power: String,
+    pub scanner: String,
+    pub weapons: String,
+}
+
+impl From<&Robot> for RobotState {
+    fn from(robot: &Robot) -> RobotState {
+        let data = &robot.data;
+        let under_attack = robot.is_under_attack();
+
+        RobotState {
+            id: data.id,
+            name: data.name.clone(),
+            owner: data.owner,
+            affiliation: data.affiliation,
+            q: data.q,
+            r: data.r,
+            orientation: data.orientation as i16,
+            power: data.power,
+            max_power: data.max_power,
+            recharge_rate: data.recharge_rate,
+            hull_strength: data.hull_strength,
+            max_hull_strength: data.max_hull_strength,
+            mined_amount: data.mined_amount,
+            val_inventory: data.val_inventory,
+            max_val_inventory: data.max_val_inventory,
+            exfil_countdown: data.exfil_countdown,
+            hibernate_countdown: data.hibernate_countdown,
+            status_text: data.status_text.clone(),
+            attacked_from: if under_attack {
+                Some(robot.get_attack_dir() as i16)
+            } else {
+                None
+            },
+            attacked_by: if under_attack {
+                Some(data.attacked_by)
+            } else {
+                None
+            },
+            damage_done: data.damage_done,
+            behavior: data.behavior.clone(),
+            active_process: robot
+                .active_process
+                .as_ref()
+                .map(|process| format!("{:?}", process)),
+            modules: ModulesState::from(&robot.modules),
+        }
+    }
+}
+
+impl From<&RobotModules> for ModulesState {
+    fn from(modules: &RobotModules) -> ModulesState {
+        ModulesState {
+            collector: modules.m_collector.clone(),
+            comms: modules.m_comms.clone(),
+            drivesystem: modules.m_drivesystem.clone(),
+            exfilbeacon: modules.m_exfilbeacon.clone(),
+            hull: modules.m_hull.clone(),
+            memory: modules.m_memory.clone(),
+            power: modules.m_power.clone(),
+            scanner: modules.m_scanner.clone(),
+            weapons: modules.m_weapons.clone(),
+        }
+    }
+}
+
+/// A position on the grid
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, JsonSchema)]
+pub struct CoordsState {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl From<&Coords> for CoordsState {
+    fn from(coords: &Coords) -> CoordsState {
+        CoordsState {
+            q: coords.q,
+            r: coords.r,
+        }
+    }
+}
+
+/// What a robot saw on its last scan
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ScanState {
+    pub scanned_cells: Vec<CoordsState>,
+    pub visible_robots: Vec<VisibleRobotState>,
+    pub visible_valuables: Vec<VisibleValuableState>,
+}
+
+/// A robot seen on a scan
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct VisibleRobotState {
+    pub robot_id: i64,
+    pub coords: CoordsState,
+    /// How it compares to the robot that saw it; one of Unknown, Weaker,
+    /// Stronger or Equal
+    pub threat_level: String,
+}
+
+/// A valuable seen on a scan
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct VisibleValuableState {
+    pub valuable_id: i64,
+    pub coords: CoordsState,
+}
+
+impl From<&ScanResults> for ScanState {
+    fn from(scan: &ScanResults) -> ScanState {
+        ScanState {
+            scanned_cells: scan.scanned_cells.iter().map(CoordsState::from).collect(),
+            visible_robots: scan
+                .visible_robots
+                .iter()
+                .map(|robot| VisibleRobotState {
+                    robot_id: robot.robot_id,
+                    coords: CoordsState::from(&robot.coords),
+                    threat_level: format!("{:?}", robot.threat_level),
+                })
+                .collect(),
+            visible_valuables: scan
+                .visible_valuables
+                .iter()
+                .map(|valuable| VisibleValuableState {
+                    valuable_id: valuable.valuable_id,
+                    coords: CoordsState::from(&valuable.coords),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The fields of a robot that changed since it was last sent, along with its
+/// id.  Rather than the whole `status_text`, `status_added` has the lines to add
+/// to the top of it
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RobotDelta(pub Map<String, Value>);
+
+impl RobotDelta {
+    pub fn id(&self) -> Option<i64> {
+        self.0.get("id").and_then(|id| id.as_i64())
+    }
+}
+
+/// The whole robot, for listeners that don't know it yet; all of its status
+/// text counts as added
+impl From<&RobotState> for RobotDelta {
+    fn from(robot: &RobotState) -> RobotDelta {
+        let mut fields = match serde_json::to_value(robot) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        };
+        if let Some(status_text) = fields.remove("status_text") {
+            fields.insert("status_added".to_string(), status_text);
+        }
+        RobotDelta(fields)
+    }
+}
+
+impl JsonSchema for RobotDelta {
+    fn schema_name() -> String {
+        "RobotDelta".to_string()
+    }
+
+    /// A robot state where anything but the id may be missing
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = RobotState::json_schema(gen).into_object();
+        let object = schema.object();
+        object.required.clear();
+        object.required.insert("id".to_string());
+        object.properties.remove("status_text");
+        object
+            .properties
+            .insert("status_added".to_string(), gen.subschema_for::<String>());
+        schema.metadata().description = Some(
+            "The fields of a robot that changed since it was last sent, along with its id"
+                .to_string(),
+        );
+
+        Schema::Object(schema)
+    }
+}
+
+/// A grid cell; each edge is 0 if open and 1 if there's a wall
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct CellState {
+    pub id: i32,
+    pub q: i32,
+    pub r: i32,
+    pub edge0: i16,
+    pub edge60: i16,
+    pub edge120: i16,
+    pub edge180: i16,
+    pub edge240: i16,
+    pub edge300: i16,
+}
+
+impl From<&GridCell> for CellState {
+    fn from(cell: &GridCell) -> CellState {
+        CellState {
+            id: cell.id,
+            q: cell.q,
+            r: cell.r,
+            edge0: cell.edge0 as i16,
+            edge60: cell.edge60 as i16,
+            edge120: cell.edge120 as i16,
+            edge180: cell.edge180 as i16,
+            edge240: cell.edge240 as i16,
+            edge300: cell.edge300 as i16,
+        }
+    }
+}
+
+/// A cell a robot remembers
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct KnownCellState {
+    pub id: i32,
+    pub q: i32,
+    pub r: i32,
+    /// Seconds since the Unix epoch
+    pub discovered: u64,
+    /// Seconds since the Unix epoch the robot last planned a path through it
+    pub last_used: u64,
+    pub confidence: i32,
+    pub on_route: bool,
+}
+
+impl From<&RobotKnownCell> for KnownCellState {
+    fn from(cell: &RobotKnownCell) -> KnownCellState {
+        fn seconds(time: SystemTime) -> u64 {
+            time.duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0)
+        }
+
+        KnownCellState {
+            id: cell.gridcell_id,
+            q: cell.q,
+            r: cell.r,
+            discovered: seconds(cell.discovery_time),
+            last_used: seconds(cell.last_used),
+            confidence: cell.confidence,
+            on_route: cell.on_route,
+        }
+    }
+}
+
+/// A pile of valuables
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ValuableState {
+    pub id: i64,
+    pub q: i32,
+    pub r: i32,
+    pub kind: String,
+    pub amount: i32,
+}
+
+impl From<&Valuable> for ValuableState {
+    fn from(valuable: &Valuable) -> ValuableState {
+        ValuableState {
+            id: valuable.id,
+            q: valuable.q,
+            r: valuable.r,
+            kind: valuable.kind.clone(),
+            amount: valuable.amount,
+        }
+    }
+}
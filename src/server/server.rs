@@ -1,15 +1,23 @@
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Read;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime};
-
-use super::broadcast::BroadcastMessage;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, info, info_span, warn};
+
+use super::admin::*;
+use super::api::{WorldSnapshot, WorldView};
+use super::broadcast::{BroadcastMessage, RobotTracker};
+use super::control::*;
+use super::protocol::*;
 use super::*;
-use crate::grid::{Coords, Dir, Grid, GridCell};
+use crate::grid::{Coords, Dir, Grid, SpatialIndex};
+use crate::metrics::{metrics, process_name};
+use crate::robot::behaviors::get_random_behavior;
 use crate::robot::modules::*;
-use crate::robot::Robot;
+use crate::robot::{Robot, VisibleRobot, VisibleValuable};
 use crate::utils;
 use crate::valuable::*;
 
@@ -20,11 +28,35 @@ pub struct Server {
     robots: HashMap<i64, Robot>,
     valuables: HashMap<i64, Valuable>,
 
-    /// our transmitter to the websocket server
-    out_tx: mpsc::Sender<BroadcastMessage>,
-    out_rx: Arc<Mutex<mpsc::Receiver<BroadcastMessage>>>,
-    in_tx: Arc<Mutex<mpsc::Sender<usize>>>,
-    in_rx: mpsc::Receiver<usize>,
+    /// our transmitter to every listener of the websocket server
+    out_tx: tokio::sync::broadcast::Sender<Arc<Outgoing>>,
+    /// listeners that want initializer data
+    in_tx: tokio::sync::mpsc::UnboundedSender<usize>,
+    in_rx: tokio::sync::mpsc::UnboundedReceiver<usize>,
+
+    /// our transmitter to remote controllers, by way of the websocket server
+    control_tx: tokio::sync::mpsc::UnboundedSender<ControlOutgoing>,
+    control_rx: Option<tokio::sync::mpsc::UnboundedReceiver<ControlOutgoing>>,
+    controller_tx: tokio::sync::mpsc::UnboundedSender<ControllerUpdate>,
+    controller_rx: tokio::sync::mpsc::UnboundedReceiver<ControllerUpdate>,
+
+    /// commands from administrators, applied between ticks
+    admin_tx: AdminSender,
+    admin_rx: AdminReceiver,
+
+    /// how many controllers each owner has connected
+    controllers: HashMap<i32, usize>,
+
+    /// how many ticks we have run
+    tick: u64,
+
+    /// what we last broadcast about each robot
+    robot_tracker: RobotTracker,
+
+    /// the grid as clients see it; it doesn't change while we run
+    cells: Arc<Vec<CellState>>,
+    /// read handle on the world for the HTTP API
+    world: WorldView,
 
     /// if true, we've been asked to shutdown
     shutdown: bool,
@@ -37,10 +69,7 @@ impl Server {
         let grid = Arc::new(Mutex::new(
             Grid::load(config.conn.as_ref()).expect("Failed to load grid"),
         ));
-        println!(
-            "Loaded grid with {} cells",
-            grid.lock().unwrap().cells.len()
-        );
+        info!(cells = grid.lock().unwrap().cells.len(), "Loaded grid");
 
         let mut robots: HashMap<i64, Robot> =
             match Robot::load_all(config.conn.as_ref(), grid.clone()) {
@@ -51,7 +80,7 @@ impl Server {
         if let Ok(loaded_robots) = Robot::load_all(config.conn.as_ref(), grid.clone()) {
             robots = loaded_robots;
         }
-        println!("Loaded {} active robots", robots.len());
+        info!(robots = robots.len(), "Loaded active robots");
 
         for (_, robot) in &robots {
             grid.lock().unwrap().add_robot(robot);
@@ -61,21 +90,33 @@ impl Server {
             Ok(valuables) => valuables,
             Err(_) => HashMap::new(),
         };
-        let mut valuables_locs: HashMap<Coords, i64> = HashMap::new();
+        let mut valuables_locs = SpatialIndex::new();
         for (id, valuable) in &valuables {
             valuables_locs.insert(
+                *id,
                 Coords {
                     q: valuable.q,
                     r: valuable.r,
                 },
-                *id,
             );
         }
 
         grid.lock().unwrap().valuables_locs = valuables_locs;
 
-        let (out_tx, out_rx) = mpsc::channel::<BroadcastMessage>();
-        let (in_tx, in_rx) = mpsc::channel::<usize>();
+        let cells: Vec<CellState> = grid
+            .lock()
+            .unwrap()
+            .cells
+            .values()
+            .map(CellState::from)
+            .collect();
+
+        let (out_tx, _) = tokio::sync::broadcast::channel::<Arc<Outgoing>>(config.listener_queue);
+        let (in_tx, in_rx) = tokio::sync::mpsc::unbounded_channel::<usize>();
+        let (control_tx, control_rx) = tokio::sync::mpsc::unbounded_channel::<ControlOutgoing>();
+        let (controller_tx, controller_rx) =
+            tokio::sync::mpsc::unbounded_channel::<ControllerUpdate>();
+        let (admin_tx, admin_rx) = tokio::sync::mpsc::unbounded_channel::<AdminRequest>();
 
         Server {
             config,
@@ -83,17 +124,50 @@ impl Server {
             robots,
             valuables,
             out_tx,
-            out_rx: Arc::new(Mutex::new(out_rx)),
-            in_tx: Arc::new(Mutex::new(in_tx)),
+            in_tx,
             in_rx,
+            control_tx,
+            control_rx: Some(control_rx),
+            controller_tx,
+            controller_rx,
+            admin_tx,
+            admin_rx,
+            controllers: HashMap::new(),
+            tick: 0,
+            robot_tracker: RobotTracker::new(),
+            cells: Arc::new(cells),
+            world: WorldView::new(),
             shutdown: false,
         }
     }
 
-    /// Spawn a new robot by finding an open, unoccupied cell
-    fn spawn_robot(&mut self) {
+    /// Pick the team with the fewest robots for a new robot; None if we aren't using teams
+    fn pick_affiliation(&self) -> Option<i32> {
+        (1..self.config.teams + 1).min_by_key(|team| {
+            self.robots
+                .values()
+                .filter(|r| r.data.affiliation == Some(*team))
+                .count()
+        })
+    }
+
+    /// Spawn a new robot by finding an open, unoccupied cell in its spawn zone;
+    /// errors if there is no such cell
+    fn spawn_robot(&mut self) -> Result<i64, String> {
+        self.spawn_robot_at(None)
+    }
+
+    /// Spawn a new robot at the given cell, or in its spawn zone if none is given;
+    /// returns the new robot's id
+    fn spawn_robot_at(&mut self, at: Option<Coords>) -> Result<i64, String> {
+        let affiliation = self.pick_affiliation();
+
         let mut grid = self.grid.lock().expect("Could not get lock on grid");
-        let coords = grid.get_random_open_cell();
+        let coords = match at {
+            Some(coords) if grid.is_free_for_robot(&coords) => coords,
+            Some(coords) => return Err(format!("Cell {},{} is not free", coords.q, coords.r)),
+            None => grid.get_spawn_cell(affiliation, self.config.spawn_distance)?,
+        };
         let orientation: Dir = rand::random();
 
         let mut modules: HashMap<String, String> = HashMap::new();
@@ -128,56 +202,72 @@ impl Server {
         let weapon_module = weapon::WeaponModule::get_random();
         modules.insert("m_weapon".to_string(), weapon_module.to_string());
 
-        let robot = Robot::new(
-            coords.clone(),
+        // comms only help robots that have allies to talk to
+        if affiliation.is_some() {
+            let comms_module = comms::CommsModule::get_random();
+            modules.insert("m_comms".to_string(), comms_module.to_string());
+        }
+
+        let mut robot = Robot::new(
+            coords,
             orientation,
             self.config.conn.as_ref(),
             self.grid.clone(),
             Some(modules),
         );
+        robot.set_affiliation(self.config.conn.as_ref(), affiliation);
+        robot.set_behavior(self.config.conn.as_ref(), &get_random_behavior());
 
         grid.add_robot(&robot);
 
-        let _ = self.out_tx.send(BroadcastMessage::RobotSpawned {
-            robot: robot.clone(),
+        // listeners get the new robot in full, so later ticks only need what changed
+        self.broadcast(BroadcastMessage::RobotSpawned {
+            robot: RobotState::from(&robot),
         });
+        self.robot_tracker.record_all(std::iter::once(&robot));
 
-        self.robots.insert(robot.data.id, robot);
+        let robot_id = robot.data.id;
+        self.robots.insert(robot_id, robot);
+        metrics().spawns.inc();
+        Ok(robot_id)
     }
 
     /// Spawn a new valuable at a given location for a given amount
     fn spawn_valuable(&mut self, coords: &Coords, amount: i32) {
         let mut grid = self.grid.lock().expect("Could not get lock on grid");
-        if let Some(valuable_id) = grid.valuables_locs.get(coords) {
+        if let Some(valuable_id) = grid.valuables_locs.get_id(coords) {
             let valuable = self.valuables.get_mut(valuable_id);
             if valuable.is_some() {
                 let valuable = valuable.unwrap();
                 valuable.add_to_amount(self.config.conn.as_ref(), amount);
 
-                let _ = self.out_tx.send(BroadcastMessage::ValuableUpdated {
-                    valuable: valuable.clone(),
-                });
+                let message = BroadcastMessage::ValuableUpdated {
+                    valuable: ValuableState::from(&*valuable),
+                };
+                self.broadcast(message);
             }
         } else {
             let valuable = Valuable::new(coords.clone(), amount, self.config.conn.as_ref());
-            grid.valuables_locs.insert(coords.clone(), valuable.id);
+            grid.valuables_locs.insert(valuable.id, *coords);
 
-            let _ = self.out_tx.send(BroadcastMessage::ValuableCreated {
-                valuable: valuable.clone(),
+            self.broadcast(BroadcastMessage::ValuableCreated {
+                valuable: ValuableState::from(&valuable),
             });
             self.valuables.insert(valuable.id, valuable);
         }
     }
 
-    /// Spawn a new valuable in a random open location with a random amount
-    fn spawn_random_valuable(&mut self) {
-        let mut grid = self.grid.lock().expect("Could not get lock on grid");
-        let coords = grid.get_random_open_cell();
+    /// Spawn a new valuable in a random open location with a random amount;
+    /// errors if there are no open locations left
+    fn spawn_random_valuable(&mut self) -> Result<(), String> {
+        let grid = self.grid.lock().expect("Could not get lock on grid");
+        let coords = grid.get_random_open_cell()?;
         let mut rng = rand::thread_rng();
         let amount: i32 = rng.gen_range(50, 5000);
         drop(grid);
 
         self.spawn_valuable(&coords, amount);
+        Ok(())
     }
 
     /// Check all the valuables; if they are now exhausted, tell them
@@ -208,7 +298,7 @@ impl Server {
                 .remove_valuable_by_loc(&valuable.0);
             self.valuables.remove(&valuable.1);
 
-            let _ = self.out_tx.send(BroadcastMessage::ValuableDepleted {
+            self.broadcast(BroadcastMessage::ValuableDepleted {
                 valuable_id: valuable.1,
             });
         }
@@ -232,7 +322,7 @@ impl Server {
         let valuable = self.valuables.get_mut(&valuable_id);
 
         if valuable.is_none() {
-            println!("Valuable is non-existant");
+            debug!(valuable_id, "Valuable to mine is gone");
             return Some(Response::Fail);
         }
 
@@ -250,8 +340,9 @@ impl Server {
         grid.remove_robot_by_id(robot_id);
 
         self.robots.remove(robot_id);
+        self.robot_tracker.forget(*robot_id);
 
-        let _ = self.out_tx.send(BroadcastMessage::RobotExfiltrated {
+        self.broadcast(BroadcastMessage::RobotExfiltrated {
             robot_id: *robot_id,
         });
 
@@ -266,6 +357,7 @@ impl Server {
         drop(grid);
 
         let robot = self.robots.remove(robot_id);
+        self.robot_tracker.forget(*robot_id);
 
         let coords: Coords;
         if robot.is_some() {
@@ -277,7 +369,7 @@ impl Server {
             return None;
         }
 
-        let _ = self.out_tx.send(BroadcastMessage::RobotDestroyed {
+        self.broadcast(BroadcastMessage::RobotDestroyed {
             robot_id: *robot_id,
         });
 
@@ -298,6 +390,8 @@ impl Server {
         }
 
         let attacker_coords = attacker.as_ref().unwrap().get_coords();
+        let attacker_orientation = attacker.as_ref().unwrap().data.orientation;
+        let attacker_weapon = attacker.as_ref().unwrap().modules.m_weapons.clone();
 
         let min_power =
             weapon::WeaponModule::get_min_damage(&attacker.as_ref().unwrap().modules.m_weapons);
@@ -310,17 +404,30 @@ impl Server {
         // and register that as well
         let mut target = self.robots.get_mut(target_id);
         let target_coords = target.as_ref().unwrap().get_coords();
+
+        // the attacker may have a stale view of the target, so make sure the
+        // shot isn't blocked or out of range before doing any damage
+        if !weapon::WeaponModule::has_clear_shot(
+            &attacker_weapon,
+            &self.grid.lock().unwrap(),
+            &attacker_coords,
+            &attacker_orientation,
+            &target_coords,
+        ) {
+            debug!(attacker_id, target_id, "Attack is blocked or out of range");
+            return Some(Response::AttackFailed);
+        }
         let mut attack_dir = utils::get_bearing(&Dir::Orient0, &target_coords, &attacker_coords);
         if attack_dir.is_none() {
             attack_dir = Some(Dir::get_random().into());
         }
 
-        println!(
-            "Server: received attack from {} to {} for {}; bearing from target to attacker: {}",
+        info!(
             attacker_id,
             target_id,
             damage,
-            attack_dir.unwrap()
+            bearing = %attack_dir.unwrap(),
+            "Attack hit"
         );
 
         target
@@ -334,7 +441,7 @@ impl Server {
         );
 
         // TODO error handling?
-        let _ = self.out_tx.send(BroadcastMessage::RobotAttacked {
+        self.broadcast(BroadcastMessage::RobotAttacked {
             attacker_id: *attacker_id,
             target_id: *target_id,
         });
@@ -350,14 +457,37 @@ impl Server {
     /// robots or valuables.  So it must ask the server to do things like
     /// mining or shooting at others
     fn handle_request_for_robot(&mut self, robot_id: &i64, request: Request) -> Option<Response> {
+        let metrics = metrics();
         match request {
-            Request::Attack { target_id } => self.handle_attack_request(&robot_id, &target_id),
-            Request::Exfiltrate { robot_id } => self.handle_exfiltrate_request(&robot_id),
-            Request::Explode { valuables } => self.handle_robot_explosion(&robot_id, valuables),
+            Request::Attack { target_id } => {
+                let response = self.handle_attack_request(robot_id, &target_id);
+                match response {
+                    Some(Response::AttackSuccess { damage, .. }) => {
+                        metrics.attacks.with_label_values(&["hit"]).inc();
+                        metrics.damage.inc_by(damage.max(0) as u64);
+                    }
+                    _ => metrics.attacks.with_label_values(&["miss"]).inc(),
+                }
+                response
+            }
+            Request::Exfiltrate { robot_id } => {
+                metrics.exfiltrations.inc();
+                self.handle_exfiltrate_request(&robot_id)
+            }
+            Request::Explode { valuables } => {
+                metrics.explosions.inc();
+                self.handle_robot_explosion(robot_id, valuables)
+            }
             Request::Mine {
                 valuable_id,
                 amount,
-            } => self.mine_for_robot(robot_id, valuable_id, amount),
+            } => {
+                let response = self.mine_for_robot(robot_id, valuable_id, amount);
+                if let Some(Response::Mined { amount, .. }) = response {
+                    metrics.mined.inc_by(amount.max(0) as u64);
+                }
+                response
+            }
         }
     }
 
@@ -371,6 +501,14 @@ impl Server {
 
         let _robot = robot.unwrap();
 
+        // everything the robot and the server do for it this tick is logged in its span
+        let span = info_span!(
+            "robot",
+            id = *robot_id,
+            process = %process_name(_robot)
+        );
+        let _entered = span.enter();
+
         let server_request = _robot.tick(self.config.conn.as_ref());
 
         let server_request = if server_request.is_some() {
@@ -388,21 +526,366 @@ impl Server {
         }
     }
 
-    /// Send initial data for a specified new client
-    fn send_initializer_data(&self, client_id: usize) {
-        println!("Send initial data to Listener {:?}", client_id);
-        let cells: Vec<GridCell> = self
-            .grid
-            .lock()
-            .unwrap()
-            .cells
+    /// Let allied robots with comms share what they know with allies in range.  Each
+    /// sender pays power to transmit and can only send so many entries per tick,
+    /// split between the allies listening
+    fn share_comms(&mut self) {
+        for robot in self.robots.values_mut() {
+            robot.clear_ally_sightings();
+        }
+
+        let sender_ids: Vec<i64> = self
+            .robots
             .values()
-            .map(|g| g.clone())
+            .filter(|r| {
+                r.data.affiliation.is_some()
+                    && comms::CommsModule::get_bandwidth(&r.modules.m_comms) > 0
+            })
+            .map(|r| r.data.id)
             .collect();
-        let robots: Vec<Robot> = self.robots.values().map(|r| r.clone()).collect();
-        let valuables: Vec<Valuable> = self.valuables.values().map(|v| v.clone()).collect();
 
-        let _ = self.out_tx.send(BroadcastMessage::InitializerData {
+        for sender_id in sender_ids {
+            let sender = self.robots.get(&sender_id).unwrap();
+            let affiliation = sender.data.affiliation;
+            let range = comms::CommsModule::get_range(&sender.modules.m_comms);
+            let power_need = comms::CommsModule::get_power_usage(&sender.modules.m_comms);
+            let bandwidth = comms::CommsModule::get_bandwidth(&sender.modules.m_comms);
+            if sender.data.power < power_need {
+                continue;
+            }
+
+            // only allies with their own comms can listen in
+            let sender_coords = sender.get_coords();
+            let mut receivers: Vec<(Coords, i64)> = self
+                .grid
+                .lock()
+                .unwrap()
+                .robot_locs
+                .within_radius(&sender_coords, range)
+                .into_iter()
+                .filter(|(_, id)| *id != sender_id)
+                .filter(|(_, id)| match self.robots.get(id) {
+                    Some(r) => {
+                        r.data.affiliation == affiliation
+                            && comms::CommsModule::get_bandwidth(&r.modules.m_comms) > 0
+                    }
+                    None => false,
+                })
+                .collect();
+            if receivers.is_empty() {
+                continue;
+            }
+
+            // the bandwidth is split evenly between the receivers, the closest ones
+            // getting whatever doesn't divide evenly
+            receivers.sort_by_key(|(coords, id)| (coords.distance_to(&sender_coords), *id));
+            let share = bandwidth / receivers.len();
+            let mut remainder = bandwidth % receivers.len();
+
+            let sender = self.robots.get_mut(&sender_id).unwrap();
+            sender.use_power(self.config.conn.as_ref(), power_need);
+            let known_cells = sender.known_cells.clone();
+            let visible_others = sender.visible_others.clone();
+            let visible_valuables = sender.visible_valuables.clone();
+
+            // sightings go out first since they go stale quickly; cells fill what is left
+            for (_, receiver_id) in receivers {
+                let mut bandwidth = share;
+                if remainder > 0 {
+                    bandwidth += 1;
+                    remainder -= 1;
+                }
+                if bandwidth == 0 {
+                    break;
+                }
+                let receiver = self.robots.get_mut(&receiver_id).unwrap();
+
+                let others: Vec<VisibleRobot> = visible_others
+                    .iter()
+                    .filter(|r| r.robot_id != receiver_id)
+                    .take(bandwidth)
+                    .cloned()
+                    .collect();
+                bandwidth -= others.len();
+
+                let valuables: Vec<VisibleValuable> =
+                    visible_valuables.iter().take(bandwidth).cloned().collect();
+                bandwidth -= valuables.len();
+
+                let cells = comms::CommsModule::pick_cells_to_share(
+                    &known_cells,
+                    &receiver.known_cells,
+                    bandwidth,
+                );
+
+                receiver.receive_shared(self.config.conn.as_ref(), cells, others, valuables);
+            }
+        }
+    }
+
+    /// Keep track of controllers coming and going and take the actions they send.
+    /// Actions for robots we are still waiting on are taken off `waiting`
+    fn handle_controller_update(&mut self, update: ControllerUpdate, waiting: &mut HashSet<i64>) {
+        match update {
+            ControllerUpdate::Connected { owner_id } => {
+                *self.controllers.entry(owner_id).or_insert(0) += 1;
+            }
+            ControllerUpdate::Disconnected { owner_id } => {
+                if let Some(count) = self.controllers.get_mut(&owner_id) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.controllers.remove(&owner_id);
+                    }
+                }
+            }
+            ControllerUpdate::Act {
+                owner_id,
+                tick,
+                robot_id,
+                action,
+            } => {
+                let reason = match self.robots.get_mut(&robot_id) {
+                    Some(robot) if robot.data.owner == Some(owner_id) => {
+                        if tick != self.tick {
+                            format!("Action for robot {} on tick {} is late", robot_id, tick)
+                        } else {
+                            robot.remote_action = Some(action);
+                            waiting.remove(&robot_id);
+                            return;
+                        }
+                    }
+                    _ => format!("Robot {} is not yours", robot_id),
+                };
+                let _ = self.control_tx.send(ControlOutgoing {
+                    owner_id,
+                    event: ControlEvent::Rejected { reason },
+                });
+            }
+        }
+    }
+
+    /// Send every connected controller what its robots see and wait until the
+    /// deadline for their actions.  Robots whose controller is late or quiet fall
+    /// back to their own behavior for this tick
+    fn collect_remote_actions(&mut self) {
+        while let Ok(update) = self.controller_rx.try_recv() {
+            self.handle_controller_update(update, &mut HashSet::new());
+        }
+
+        let mut waiting: HashSet<i64> = HashSet::new();
+        for robot in self.robots.values() {
+            let owner_id = match robot.data.owner {
+                Some(owner_id) if self.controllers.contains_key(&owner_id) => owner_id,
+                _ => continue,
+            };
+
+            let _ = self.control_tx.send(ControlOutgoing {
+                owner_id,
+                event: ControlEvent::Observation {
+                    tick: self.tick,
+                    deadline_ms: self.config.control_deadline,
+                    robot: Box::new(RobotObservation::new(robot)),
+                    scan: ScanState::from(&robot.last_scan),
+                },
+            });
+            waiting.insert(robot.data.id);
+        }
+
+        // the channel is async, so we check it every millisecond rather than block on it
+        let deadline = Instant::now() + Duration::from_millis(self.config.control_deadline);
+        while !waiting.is_empty() && Instant::now() < deadline {
+            match self.controller_rx.try_recv() {
+                Ok(update) => self.handle_controller_update(update, &mut waiting),
+                Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(1)),
+                Err(TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Tell listeners what changed about each robot this tick; on the first tick
+    /// and every so often after, send everything in full instead
+    fn broadcast_robot_changes(&mut self) {
+        if self.tick == 1 || self.tick.is_multiple_of(self.config.keyframe_interval) {
+            let robots: Vec<Robot> = self.robots.values().cloned().collect();
+            self.robot_tracker.record_all(robots.iter());
+
+            self.broadcast(BroadcastMessage::Keyframe {
+                robots: robots.iter().map(RobotState::from).collect(),
+                valuables: self.valuables.values().map(ValuableState::from).collect(),
+            });
+            return;
+        }
+
+        let tracker = &mut self.robot_tracker;
+        let (robots, full): (Vec<RobotDelta>, Vec<RobotState>) = self
+            .robots
+            .values()
+            .filter_map(|robot| {
+                tracker
+                    .changes(robot)
+                    .map(|changes| (changes, RobotState::from(robot)))
+            })
+            .unzip();
+        if robots.is_empty() {
+            return;
+        }
+
+        self.broadcast(BroadcastMessage::RobotsMoved { robots, full });
+    }
+
+    /// Send a message out to listeners, stamped with the current tick
+    fn broadcast(&self, message: BroadcastMessage) {
+        // this only fails if nobody is listening
+        let _ = self
+            .out_tx
+            .send(Arc::new(Outgoing::new(Envelope::new(self.tick, message))));
+    }
+
+    /// Carry out the commands administrators sent during the last tick
+    fn apply_admin_commands(&mut self) {
+        while let Ok(request) = self.admin_rx.try_recv() {
+            let outcome = self.apply_admin_command(request.command);
+            // the admin may have given up waiting
+            let _ = request.reply.send(outcome);
+        }
+    }
+
+    /// Apply a single admin command and broadcast what it changed
+    fn apply_admin_command(&mut self, command: AdminCommand) -> Result<String, String> {
+        match command {
+            AdminCommand::SpawnRobot { at } => {
+                let robot_id = self.spawn_robot_at(at)?;
+                Ok(format!("Spawned robot {}", robot_id))
+            }
+            AdminCommand::DestroyRobot { robot_id } => {
+                let mut robot = self
+                    .robots
+                    .remove(&robot_id)
+                    .ok_or_else(|| format!("No robot {}", robot_id))?;
+                self.grid.lock().unwrap().remove_robot_by_id(&robot_id);
+                self.robot_tracker.forget(robot_id);
+                robot.destroy(self.config.conn.as_ref());
+
+                self.broadcast(BroadcastMessage::RobotDestroyed { robot_id });
+                Ok(format!("Destroyed robot {}", robot_id))
+            }
+            AdminCommand::MoveRobot { robot_id, to } => {
+                let mut grid = self.grid.lock().unwrap();
+                if !grid.is_free_for_robot(&to) {
+                    return Err(format!("Cell {},{} is not free", to.q, to.r));
+                }
+                let robot = self
+                    .robots
+                    .get_mut(&robot_id)
+                    .ok_or_else(|| format!("No robot {}", robot_id))?;
+                robot.relocate(self.config.conn.as_ref(), &to);
+                grid.update_robot_loc(robot_id, to);
+                drop(grid);
+
+                if let Some(changes) = self.robot_tracker.changes(robot) {
+                    let full = vec![RobotState::from(&*robot)];
+                    self.broadcast(BroadcastMessage::RobotsMoved {
+                        robots: vec![changes],
+                        full,
+                    });
+                }
+                Ok(format!("Moved robot {} to {},{}", robot_id, to.q, to.r))
+            }
+            AdminCommand::PlaceValuable { at, amount } => {
+                if amount <= 0 {
+                    return Err("The amount must be positive".to_string());
+                }
+                let open = self
+                    .grid
+                    .lock()
+                    .unwrap()
+                    .cells
+                    .get(&at)
+                    .is_some_and(|cell| cell.is_open());
+                if !open {
+                    return Err(format!("Cell {},{} is not open", at.q, at.r));
+                }
+
+                self.spawn_valuable(&at, amount);
+                Ok(format!("Placed {} valuables at {},{}", amount, at.q, at.r))
+            }
+            AdminCommand::SetLimits {
+                max_bots,
+                max_valuables,
+            } => {
+                let max_bots = max_bots.unwrap_or(self.config.max_bots);
+                let max_valuables = max_valuables.unwrap_or(self.config.max_valuables);
+
+                // robots and valuables each take up a cell of their own
+                let open_cells = self
+                    .grid
+                    .lock()
+                    .unwrap()
+                    .cells
+                    .values()
+                    .filter(|cell| cell.is_open())
+                    .count();
+                if max_bots + max_valuables > open_cells {
+                    return Err(format!(
+                        "{} robots and {} valuables will not fit in {} open cells",
+                        max_bots, max_valuables, open_cells
+                    ));
+                }
+
+                self.config.max_bots = max_bots;
+                self.config.max_valuables = max_valuables;
+                Ok(format!(
+                    "Keeping {} robots and {} valuables",
+                    self.config.max_bots, self.config.max_valuables
+                ))
+            }
+        }
+    }
+
+    /// Record how long the tick took and what the world looks like after it
+    fn record_tick_metrics(&self, elapsed: Duration) {
+        let metrics = metrics();
+        metrics.ticks.inc();
+        metrics.tick_duration.observe(elapsed.as_secs_f64());
+        if elapsed > Duration::from_secs(1) {
+            metrics.tick_overruns.inc();
+        }
+
+        metrics.record_robots(self.robots.values());
+        metrics.valuables.set(self.valuables.len() as i64);
+    }
+
+    /// Let HTTP API readers see the world as it is at the end of this tick
+    fn publish_snapshot(&self) {
+        self.world.publish(WorldSnapshot {
+            tick: self.tick,
+            cells: self.cells.clone(),
+            robots: self
+                .robots
+                .iter()
+                .map(|(id, robot)| (*id, RobotState::from(robot)))
+                .collect(),
+            known_cells: self
+                .robots
+                .iter()
+                .map(|(id, robot)| {
+                    let cells = robot.known_cells.iter().map(KnownCellState::from);
+                    (*id, cells.collect())
+                })
+                .collect(),
+            valuables: self.valuables.values().map(ValuableState::from).collect(),
+        });
+    }
+
+    /// Send initial data for a specified new client
+    fn send_initializer_data(&self, client_id: usize) {
+        debug!(listener = client_id, "Sending initializer data");
+        let cells: Vec<CellState> = self.cells.to_vec();
+        let robots: Vec<RobotState> = self.robots.values().map(RobotState::from).collect();
+        let valuables: Vec<ValuableState> =
+            self.valuables.values().map(ValuableState::from).collect();
+
+        self.broadcast(BroadcastMessage::InitializerData {
             id: client_id,
             cells,
             robots,
@@ -414,47 +897,76 @@ impl Server {
     pub fn run(&mut self) {
         let mut last_tick = SystemTime::now();
 
-        let mut ws = WebsocketServer::new(self.out_rx.clone(), self.in_tx.clone());
+        let mut ws = WebsocketServer::new(
+            self.out_tx.clone(),
+            self.in_tx.clone(),
+            self.control_rx.take().expect("Server is already running"),
+            self.controller_tx.clone(),
+            self.config.controller_queue,
+            self.config.dbconfig.clone(),
+            self.config.endpoints.clone(),
+            self.world.clone(),
+            self.admin_tx.clone(),
+        );
 
         thread::spawn(move || {
             ws.run();
         });
 
-        println!("Server config {}", self.config.no_kill_drops);
+        info!(
+            max_bots = self.config.max_bots,
+            max_valuables = self.config.max_valuables,
+            no_kill_drops = self.config.no_kill_drops,
+            teams = self.config.teams,
+            "Server running"
+        );
         while !self.shutdown {
             if self.config.debug {
                 self._wait_for_enter().expect("Not possible");
             }
+            let started = Instant::now();
 
+            self.apply_admin_commands();
+
+            // top up the robots and valuables; if there is no room for them,
+            // try again next tick
             while self.robots.len() < self.config.max_bots {
-                self.spawn_robot();
+                if let Err(reason) = self.spawn_robot() {
+                    warn!("Could not spawn robot: {}", reason);
+                    break;
+                }
             }
 
             while self.valuables.len() < self.config.max_valuables {
-                self.spawn_random_valuable();
+                if let Err(reason) = self.spawn_random_valuable() {
+                    warn!("Could not spawn valuable: {}", reason);
+                    break;
+                }
             }
 
+            self.tick += 1;
+            let span = info_span!("tick", tick = self.tick);
+            let _entered = span.enter();
+            self.collect_remote_actions();
+
             // because we need the server `self` to be mutable, we cannot borrow
             // anything else to send along, otherwise, we get hit by the borrower
             // check.  So, let's make copies of the robot ids and use that
             let robot_ids: Vec<i64> = self.robots.keys().map(|k| k.clone()).collect();
             for id in robot_ids {
                 self.tick_robot(&id);
-                if let Some(robot) = self.robots.get(&id) {
-                    if let Err(err) = self.out_tx.send(BroadcastMessage::RobotMoved {
-                        robot: robot.clone(),
-                    }) {
-                        println!("Error: {:?}", err);
-                    }
-                }
             }
 
+            self.share_comms();
             self.destroy_depleted_valuables();
+            self.broadcast_robot_changes();
+            self.publish_snapshot();
 
             // Send initializer data to all new clients
             while let Ok(client_id) = self.in_rx.try_recv() {
                 self.send_initializer_data(client_id);
             }
+            self.record_tick_metrics(started.elapsed());
 
             // Wait for remainer of the tick time
             if let Ok(elapse) = last_tick.elapsed() {
@@ -0,0 +1,439 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::broadcast::BroadcastMessage;
+use super::protocol::{RobotDelta, RobotState};
+use crate::grid::Coords;
+
+/// A circle of cells around a center
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq)]
+pub struct Area {
+    pub q: i32,
+    pub r: i32,
+    pub radius: i32,
+}
+
+impl Area {
+    pub fn contains(&self, coords: &Coords) -> bool {
+        Coords {
+            q: self.q,
+            r: self.r,
+        }
+        .distance_to(coords)
+            <= self.radius
+    }
+}
+
+/// What a listener wants to hear about.  A robot is relevant if it is in the
+/// area, is one of the listed robots, or belongs to the owner; a listener that
+/// sets none of these hears about everything.  Cells and valuables are only
+/// limited by the area
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq)]
+pub struct Subscription {
+    #[serde(default)]
+    pub area: Option<Area>,
+    #[serde(default)]
+    pub robots: Vec<i64>,
+    #[serde(default)]
+    pub owner: Option<i32>,
+}
+
+/// Messages a listener sends to the server
+#[derive(Debug, Deserialize, JsonSchema)]
+pub enum ListenRequest {
+    /// Replace our subscription; the server answers with initializer data for it
+    Subscribe(Subscription),
+}
+
+impl Subscription {
+    /// Build a subscription from `/listen` query parameters: `q`, `r` and `radius`
+    /// for an area, `robots` as a comma separated list of ids, and `owner`
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Subscription, String> {
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .trim()
+                .parse::<T>()
+                .map_err(|_| format!("Bad value for {}: {}", name, value))
+        }
+
+        let area = match (query.get("q"), query.get("r"), query.get("radius")) {
+            (None, None, None) => None,
+            (Some(q), Some(r), Some(radius)) => Some(Area {
+                q: parse("q", q)?,
+                r: parse("r", r)?,
+                radius: parse("radius", radius)?,
+            }),
+            _ => return Err("An area needs q, r and radius".to_string()),
+        };
+
+        let robots = match query.get("robots") {
+            Some(robots) => robots
+                .split(',')
+                .map(|id| parse("robots", id))
+                .collect::<Result<Vec<i64>, String>>()?,
+            None => Vec::new(),
+        };
+
+        let owner = match query.get("owner") {
+            Some(owner) => Some(parse("owner", owner)?),
+            None => None,
+        };
+
+        Ok(Subscription {
+            area,
+            robots,
+            owner,
+        })
+    }
+
+    /// True if we are not limiting anything
+    pub fn is_everything(&self) -> bool {
+        self.area.is_none() && self.robots.is_empty() && self.owner.is_none()
+    }
+
+    pub fn wants_robot(&self, robot_id: i64, coords: Option<&Coords>, owner: Option<i32>) -> bool {
+        if self.is_everything() || self.robots.contains(&robot_id) {
+            return true;
+        }
+        if self.owner.is_some() && self.owner == owner {
+            return true;
+        }
+
+        match (self.area, coords) {
+            (Some(area), Some(coords)) => area.contains(coords),
+            _ => false,
+        }
+    }
+
+    pub fn wants_coords(&self, coords: &Coords) -> bool {
+        match self.area {
+            Some(area) => area.contains(coords),
+            None => true,
+        }
+    }
+
+    fn wants_known_robot(&self, world: &WorldIndex, robot_id: i64) -> bool {
+        match world.robots.get(&robot_id) {
+            Some((coords, owner)) => self.wants_robot(robot_id, Some(coords), *owner),
+            None => self.wants_robot(robot_id, None, None),
+        }
+    }
+
+    fn wants_known_valuable(&self, world: &WorldIndex, valuable_id: i64) -> bool {
+        match world.valuables.get(&valuable_id) {
+            Some(coords) => self.wants_coords(coords),
+            None => self.is_everything(),
+        }
+    }
+
+    pub fn wants_full_robot(&self, robot: &RobotState) -> bool {
+        self.wants_robot(
+            robot.id,
+            Some(&Coords {
+                q: robot.q,
+                r: robot.r,
+            }),
+            robot.owner,
+        )
+    }
+
+    /// Cut a broadcast down to what we subscribed to; None if none of it is
+    /// relevant.  Robot changes are sent if the robot was relevant before the
+    /// change or after it, so listeners hear about robots leaving their area
+    pub fn filter(&self, msg: &BroadcastMessage, world: &WorldIndex) -> Option<BroadcastMessage> {
+        if self.is_everything() {
+            return Some(msg.clone());
+        }
+
+        let relevant = match msg {
+            BroadcastMessage::InitializerData {
+                id,
+                cells,
+                robots,
+                valuables,
+            } => {
+                return Some(BroadcastMessage::InitializerData {
+                    id: *id,
+                    cells: cells
+                        .iter()
+                        .filter(|c| self.wants_coords(&Coords { q: c.q, r: c.r }))
+                        .cloned()
+                        .collect(),
+                    robots: robots
+                        .iter()
+                        .filter(|r| self.wants_full_robot(r))
+                        .cloned()
+                        .collect(),
+                    valuables: valuables
+                        .iter()
+                        .filter(|v| self.wants_coords(&Coords { q: v.q, r: v.r }))
+                        .cloned()
+                        .collect(),
+                })
+            }
+            BroadcastMessage::Keyframe { robots, valuables } => {
+                return Some(BroadcastMessage::Keyframe {
+                    robots: robots
+                        .iter()
+                        .filter(|r| self.wants_full_robot(r))
+                        .cloned()
+                        .collect(),
+                    valuables: valuables
+                        .iter()
+                        .filter(|v| self.wants_coords(&Coords { q: v.q, r: v.r }))
+                        .cloned()
+                        .collect(),
+                })
+            }
+            BroadcastMessage::RobotsMoved { robots, full } => {
+                let (robots, full): (Vec<RobotDelta>, Vec<RobotState>) = robots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, changes)| {
+                        let robot = full.get(i)?;
+                        let changes = self.changes_for(changes, robot, world)?;
+                        Some((changes, robot.clone()))
+                    })
+                    .unzip();
+                if robots.is_empty() {
+                    return None;
+                }
+                return Some(BroadcastMessage::RobotsMoved { robots, full });
+            }
+            BroadcastMessage::RobotAttacked {
+                attacker_id,
+                target_id,
+            } => {
+                self.wants_known_robot(world, *attacker_id)
+                    || self.wants_known_robot(world, *target_id)
+            }
+            BroadcastMessage::RobotSpawned { robot } => self.wants_full_robot(robot),
+            BroadcastMessage::RobotDestroyed { robot_id }
+            | BroadcastMessage::RobotExfiltrated { robot_id } => {
+                self.wants_known_robot(world, *robot_id)
+            }
+            BroadcastMessage::ValuableCreated { valuable }
+            | BroadcastMessage::ValuableUpdated { valuable } => self.wants_coords(&Coords {
+                q: valuable.q,
+                r: valuable.r,
+            }),
+            BroadcastMessage::ValuableDepleted { valuable_id } => {
+                self.wants_known_valuable(world, *valuable_id)
+            }
+        };
+
+        if relevant {
+            Some(msg.clone())
+        } else {
+            None
+        }
+    }
+
+    /// What to send about a robot that changed: nothing if it's not relevant
+    /// before or after the change, everything if it has only just become
+    /// relevant, since we may never have sent it in full
+    fn changes_for(
+        &self,
+        changes: &RobotDelta,
+        robot: &RobotState,
+        world: &WorldIndex,
+    ) -> Option<RobotDelta> {
+        let wants_before = match world.robots.get(&robot.id) {
+            Some((coords, owner)) => self.wants_robot(robot.id, Some(coords), *owner),
+            None => false,
+        };
+        let wants_after = self.wants_full_robot(robot);
+
+        match (wants_before, wants_after) {
+            (false, true) => Some(RobotDelta::from(robot)),
+            (true, _) => Some(changes.clone()),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Where each robot and valuable is, as far as the broadcasts have told us, so
+/// broadcasts that only carry ids can be matched to subscriptions
+#[derive(Debug, Default)]
+pub struct WorldIndex {
+    robots: HashMap<i64, (Coords, Option<i32>)>,
+    valuables: HashMap<i64, Coords>,
+}
+
+impl WorldIndex {
+    pub fn new() -> WorldIndex {
+        WorldIndex::default()
+    }
+
+    fn record_robot(&mut self, robot: &RobotState) {
+        self.robots.insert(
+            robot.id,
+            (
+                Coords {
+                    q: robot.q,
+                    r: robot.r,
+                },
+                robot.owner,
+            ),
+        );
+    }
+
+    /// Update what we know with a broadcast; call after filtering it, since
+    /// filtering needs to know where things were before it
+    pub fn update(&mut self, msg: &BroadcastMessage) {
+        match msg {
+            BroadcastMessage::InitializerData {
+                robots, valuables, ..
+            }
+            | BroadcastMessage::Keyframe {
+                robots, valuables, ..
+            } => {
+                self.robots.clear();
+                self.valuables.clear();
+                for robot in robots {
+                    self.record_robot(robot);
+                }
+                for valuable in valuables {
+                    self.valuables.insert(
+                        valuable.id,
+                        Coords {
+                            q: valuable.q,
+                            r: valuable.r,
+                        },
+                    );
+                }
+            }
+            BroadcastMessage::RobotsMoved { robots, .. } => {
+                for changes in robots {
+                    if let Some(robot_id) = changes.id() {
+                        let before = self.robots.get(&robot_id);
+                        if let Some(after) = apply_changes(before, changes) {
+                            self.robots.insert(robot_id, after);
+                        }
+                    }
+                }
+            }
+            BroadcastMessage::RobotSpawned { robot } => self.record_robot(robot),
+            BroadcastMessage::RobotDestroyed { robot_id }
+            | BroadcastMessage::RobotExfiltrated { robot_id } => {
+                self.robots.remove(robot_id);
+            }
+            BroadcastMessage::ValuableCreated { valuable }
+            | BroadcastMessage::ValuableUpdated { valuable } => {
+                self.valuables.insert(
+                    valuable.id,
+                    Coords {
+                        q: valuable.q,
+                        r: valuable.r,
+                    },
+                );
+            }
+            BroadcastMessage::ValuableDepleted { valuable_id } => {
+                self.valuables.remove(valuable_id);
+            }
+            BroadcastMessage::RobotAttacked { .. } => {}
+        }
+    }
+}
+
+/// Where a robot is and who owns it after some changes; None if we don't know
+/// where it is
+fn apply_changes(
+    before: Option<&(Coords, Option<i32>)>,
+    changes: &RobotDelta,
+) -> Option<(Coords, Option<i32>)> {
+    let get_i32 = |key: &str| {
+        changes
+            .0
+            .get(key)
+            .and_then(|v| v.as_i64())
+            .map(|v| v as i32)
+    };
+
+    let q = get_i32("q").or_else(|| before.map(|(coords, _)| coords.q))?;
+    let r = get_i32("r").or_else(|| before.map(|(coords, _)| coords.r))?;
+    let owner = match changes.0.get("owner") {
+        Some(owner) => owner.as_i64().map(|owner| owner as i32),
+        None => before.and_then(|(_, owner)| *owner),
+    };
+
+    Some((Coords { q, r }, owner))
+}
+
+#[cfg(test)]
+#[test]
+fn test_subscription() {
+    let mut query = HashMap::new();
+    query.insert("q".to_string(), "0".to_string());
+    query.insert("r".to_string(), "0".to_string());
+    query.insert("radius".to_string(), "2".to_string());
+    query.insert("robots".to_string(), "7,8".to_string());
+    let sub = Subscription::from_query(&query).unwrap();
+
+    use crate::grid::{Dir, Grid};
+    use crate::robot::Robot;
+    use serde_json::{Map, Value};
+    use std::sync::{Arc, Mutex};
+
+    let grid = Arc::new(Mutex::new(Grid::new(2, None).unwrap()));
+    let robot = Robot::new(Coords { q: 0, r: 0 }, Dir::Orient0, None, grid, None);
+
+    let mut world = WorldIndex::new();
+    let moved = |id: i64, q: i32| {
+        let mut full = RobotState::from(&robot);
+        full.id = id;
+        full.q = q;
+        full.r = 0;
+        full.owner = None;
+
+        let mut changes = Map::new();
+        changes.insert("id".to_string(), id.into());
+        changes.insert("q".to_string(), q.into());
+        changes.insert("r".to_string(), 0.into());
+        changes.insert("owner".to_string(), Value::Null);
+        BroadcastMessage::RobotsMoved {
+            robots: vec![RobotDelta(changes)],
+            full: vec![full],
+        }
+    };
+    let sent = |msg: Option<BroadcastMessage>| match msg {
+        Some(BroadcastMessage::RobotsMoved { mut robots, .. }) => robots.pop(),
+        _ => None,
+    };
+
+    // a robot far away doesn't interest us, until it walks into our area; we
+    // never sent it in full, so it comes in full
+    let far = moved(1, 5);
+    assert!(sub.filter(&far, &world).is_none());
+    world.update(&far);
+    let near = moved(1, 1);
+    let entered = sent(sub.filter(&near, &world)).unwrap();
+    assert!(entered.0.contains_key("power"));
+    assert!(entered.0.contains_key("status_added"));
+    assert!(!entered.0.contains_key("status_text"));
+    world.update(&near);
+
+    // after that only what changed
+    let closer = moved(1, 0);
+    let changes = sent(sub.filter(&closer, &world)).unwrap();
+    assert!(!changes.0.contains_key("power"));
+    world.update(&closer);
+
+    // we hear about it walking back out, but not after that
+    let gone = moved(1, 4);
+    assert!(sub.filter(&gone, &world).is_some());
+    world.update(&gone);
+    assert!(sub
+        .filter(&BroadcastMessage::RobotDestroyed { robot_id: 1 }, &world)
+        .is_none());
+
+    // robots we asked for by id are relevant anywhere
+    assert!(sent(sub.filter(&moved(7, 9), &world))
+        .unwrap()
+        .0
+        .contains_key("power"));
+
+    query.remove("radius");
+    assert!(Subscription::from_query(&query).is_err());
+}
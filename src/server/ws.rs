@@ -3,139 +3,547 @@ use futures::prelude::*;
 #[allow(unused_imports)]
 use tokio::prelude::*;
 
-use futures::channel::mpsc::UnboundedSender;
+use futures::stream::SplitSink;
+use futures::StreamExt;
+use serde::Serialize;
 use serde_json;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc, Mutex,
+    Arc,
 };
 use tokio::runtime::Runtime;
+use tokio::sync::broadcast::RecvError;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::RwLock;
-use tokio::time::{delay_for, Duration};
+use tokio::time::{timeout, Duration};
+use tracing::{error, info, warn};
 use warp::ws::{Message, WebSocket};
-use warp::{Error, Filter};
+use warp::{Filter, Reply};
 
+use super::admin::{self, AdminSender};
+use super::api::{self, WorldView};
 use super::broadcast::BroadcastMessage;
+use super::control::*;
+use super::endpoints::*;
+use super::protocol::{Encoding, Envelope, Outgoing};
+use super::subscription::*;
+use crate::db::{establish_connection, DbConfig};
+use crate::metrics::metrics;
+use crate::owner::Owner;
+use crate::robot::behaviors::Action;
 
 // global client id counter
 static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
 
-/// Active clients
-type Clients = Arc<RwLock<HashMap<usize, UnboundedSender<Result<Message, Error>>>>>;
+/// How long a listener has to take a message before we give up on it
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// MPSC receiver from the server
-type StandardReceiver = Arc<Mutex<std::sync::mpsc::Receiver<BroadcastMessage>>>;
+/// A client listening to broadcasts
+struct Listener {
+    id: usize,
+    subscription: Subscription,
+    encoding: Encoding,
+    /// where things are, for matching broadcasts to our subscription
+    world: WorldIndex,
+}
+
+impl Listener {
+    /// What to send this listener for a broadcast, if anything
+    fn message_for(&mut self, outgoing: &Outgoing) -> Option<Message> {
+        let envelope = &outgoing.envelope;
+        if let BroadcastMessage::InitializerData { id, .. } = envelope.message {
+            if id != self.id {
+                return None;
+            }
+        }
+
+        let msg = if self.subscription.is_everything() {
+            outgoing.encoded(self.encoding)
+        } else {
+            self.subscription
+                .filter(&envelope.message, &self.world)
+                .and_then(|msg| {
+                    self.encoding
+                        .encode(&Envelope::new(envelope.tick, msg))
+                        .ok()
+                })
+        };
+        self.world.update(&envelope.message);
 
-/// MPSC transmitter to the server
-type StandardSender = Arc<Mutex<std::sync::mpsc::Sender<usize>>>;
+        msg
+    }
+}
+
+/// Queue of events waiting to go out to a remote controller
+type ControllerQueue = tokio::sync::mpsc::Sender<Message>;
+
+/// Connected remote controllers, the owner each one authenticated as and its
+/// event queue
+type Controllers = Arc<RwLock<HashMap<usize, (i32, ControllerQueue)>>>;
+
+/// Receiver of events from the server for remote controllers
+type ControlReceiver = tokio::sync::mpsc::UnboundedReceiver<ControlOutgoing>;
+
+/// MPSC transmitter of remote controller updates to the server
+type ControllerSender = tokio::sync::mpsc::UnboundedSender<ControllerUpdate>;
+
+/// Broadcasts from the server; each listener subscribes to its own receiver
+type BroadcastSender = tokio::sync::broadcast::Sender<Arc<Outgoing>>;
+
+/// Transmitter of requests for initializer data to the server
+type InitializerSender = tokio::sync::mpsc::UnboundedSender<usize>;
+
+/// Reply to health checks
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    listeners: usize,
+}
 
 pub struct WebsocketServer {
-    pub server_rx: StandardReceiver,
-    pub server_tx: StandardSender,
-    pub clients: Clients,
+    pub broadcasts: BroadcastSender,
+    pub server_tx: InitializerSender,
+    pub control_rx: Option<ControlReceiver>,
+    pub controller_tx: ControllerSender,
+    pub controller_queue: usize,
+    pub controllers: Controllers,
+    pub dbconfig: Arc<DbConfig>,
+    pub endpoints: EndpointConfig,
+    pub world: WorldView,
+    pub admin_tx: AdminSender,
 }
 
 impl WebsocketServer {
-    pub fn new(server_rx: StandardReceiver, server_tx: StandardSender) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        broadcasts: BroadcastSender,
+        server_tx: InitializerSender,
+        control_rx: ControlReceiver,
+        controller_tx: ControllerSender,
+        controller_queue: usize,
+        dbconfig: DbConfig,
+        endpoints: EndpointConfig,
+        world: WorldView,
+        admin_tx: AdminSender,
+    ) -> Self {
         WebsocketServer {
-            server_rx,
+            broadcasts,
             server_tx,
-            clients: Clients::default(),
+            control_rx: Some(control_rx),
+            controller_tx,
+            controller_queue,
+            controllers: Controllers::default(),
+            dbconfig: Arc::new(dbconfig),
+            endpoints,
+            world,
+            admin_tx,
         }
     }
 
-    async fn send_broadcast(clients: Clients, msg: BroadcastMessage) {
-        let mut dead_clients: Vec<usize> = Vec::new();
-
-        let for_client: Option<usize> = match msg {
-            BroadcastMessage::InitializerData {
-                id,
-                cells: _,
-                robots: _,
-                valuables: _,
-            } => Some(id),
-            _ => None,
+    async fn listener_connected(
+        ws: WebSocket,
+        subscription: Subscription,
+        encoding: Encoding,
+        broadcasts: BroadcastSender,
+        server_tx: InitializerSender,
+    ) {
+        let client_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut listener = Listener {
+            id: client_id,
+            subscription,
+            encoding,
+            world: WorldIndex::new(),
+        };
+
+        info!(listener = client_id, "Listener connected");
+        metrics().listeners.inc();
+
+        // subscribe before asking for initializer data so we don't miss
+        // anything sent after it
+        let (mut client_ws_tx, mut client_ws_rx) = ws.split();
+        let mut rx = broadcasts.subscribe();
+        let _ = server_tx.send(client_id);
+
+        let reason = loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(outgoing) => {
+                        let msg = match listener.message_for(&outgoing) {
+                            Some(msg) => msg,
+                            None => continue,
+                        };
+                        match timeout(SEND_TIMEOUT, client_ws_tx.send(msg)).await {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => break format!("send error: {}", e),
+                            Err(_) => break "timed out sending".to_string(),
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        break format!("too slow, fell {} messages behind", missed)
+                    }
+                    Err(RecvError::Closed) => break "server shut down".to_string(),
+                },
+                // listeners can change what they subscribe to; they get fresh
+                // initializer data each time they do
+                incoming = client_ws_rx.next() => match incoming {
+                    Some(Ok(msg)) if msg.is_close() => break "closed".to_string(),
+                    Some(Ok(msg)) => {
+                        let text = match msg.to_str() {
+                            Ok(text) => text,
+                            Err(_) => continue,
+                        };
+                        match serde_json::from_str::<ListenRequest>(text) {
+                            Ok(ListenRequest::Subscribe(subscription)) => {
+                                listener.subscription = subscription;
+                                let _ = server_tx.send(client_id);
+                            }
+                            Err(e) => warn!(listener = client_id, "Bad request: {}", e),
+                        }
+                    }
+                    Some(Err(e)) => break format!("receive error: {}", e),
+                    None => break "closed".to_string(),
+                },
+            }
         };
 
-        if let Ok(msg_json) = serde_json::to_string(&msg) {
-            for (id, mut tx) in clients.read().await.iter() {
-                if for_client.is_some() && *id != for_client.unwrap() {
-                    continue;
-                }
+        info!(listener = client_id, %reason, "Listener disconnected");
+        metrics().listeners.dec();
+    }
 
-                let _msg = msg_json.clone();
-                if let Err(e) = tx.send(Ok(Message::text(_msg))).await {
-                    println!("Error sending to {}: {}", id, e);
-                    dead_clients.push(*id);
-                }
+    /// An event as controllers receive it; None if it can't be serialized
+    fn event_message(event: &ControlEvent) -> Option<Message> {
+        match serde_json::to_string(event) {
+            Ok(msg_json) => Some(Message::text(msg_json)),
+            Err(e) => {
+                error!(?event, "Could not serialize event: {}", e);
+                None
             }
-        } else {
-            println!("ERROR: failed to serialize {:#?}", msg);
-        }
-        for id in &dead_clients {
-            let _ = Self::listener_disconnected(*id, clients.clone()).await;
         }
     }
 
-    async fn listener_connected(ws: WebSocket, clients: Clients, server_tx: StandardSender) {
-        let client_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    /// Send a controller an event directly, skipping its queue
+    async fn send_event(
+        client_ws_tx: &mut SplitSink<WebSocket, Message>,
+        event: &ControlEvent,
+    ) -> Result<(), String> {
+        match Self::event_message(event) {
+            Some(msg) => Self::send_message(client_ws_tx, msg).await,
+            None => Ok(()),
+        }
+    }
 
-        println!("Listener {} connected", client_id);
+    async fn send_message(
+        client_ws_tx: &mut SplitSink<WebSocket, Message>,
+        msg: Message,
+    ) -> Result<(), String> {
+        match timeout(SEND_TIMEOUT, client_ws_tx.send(msg)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(format!("send error: {}", e)),
+            Err(_) => Err("timed out sending".to_string()),
+        }
+    }
 
-        let (client_ws_tx, _) = ws.split();
-        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Message, Error>>();
+    /// Check the credentials in a controller's first message; returns the owner id
+    async fn authenticate(msg: Message, dbconfig: Arc<DbConfig>) -> Result<i32, String> {
+        let request = msg
+            .to_str()
+            .map_err(|_| "Expected a text message".to_string())
+            .and_then(|text| serde_json::from_str(text).map_err(|e| format!("{}", e)))?;
 
-        tokio::task::spawn(rx.forward(client_ws_tx).map(|result| {
-            if let Err(e) = result {
-                println!("Websocket send error: {}", e);
-            }
-        }));
+        let (owner, token) = match request {
+            ControlRequest::Authenticate { owner, token } => (owner, token),
+            _ => return Err("Authenticate first".to_string()),
+        };
 
-        clients.write().await.insert(client_id, tx);
-        let _ = server_tx.lock().unwrap().send(client_id);
+        tokio::task::spawn_blocking(move || {
+            let conn = establish_connection(&dbconfig);
+            Owner::authenticate(&conn, &owner, &token).map(|owner| owner.id)
+        })
+        .await
+        .map_err(|e| format!("{}", e))?
     }
 
-    async fn listener_disconnected(id: usize, client_list: Clients) {
-        println!("Listener {} disconnected", id);
-        client_list.write().await.remove(&id);
+    /// Pass a controller's request on to the server; Err has why we rejected it
+    fn handle_control_request(
+        text: &str,
+        owner_id: i32,
+        controller_tx: &ControllerSender,
+    ) -> Result<(), String> {
+        match serde_json::from_str::<ControlRequest>(text) {
+            Ok(ControlRequest::Act {
+                tick,
+                robot_id,
+                action,
+            }) => {
+                let action = Action::try_from(action)?;
+                let _ = controller_tx.send(ControllerUpdate::Act {
+                    owner_id,
+                    tick,
+                    robot_id,
+                    action,
+                });
+                Ok(())
+            }
+            Ok(ControlRequest::Authenticate { .. }) => Err("Already authenticated".to_string()),
+            Err(e) => Err(format!("Bad request: {}", e)),
+        }
     }
 
-    /// async loop that listens for incoming broadcast messages from the
-    /// server and rebroadcasts to all listeners
-    async fn rebroadcast_loop(rx: StandardReceiver, clients: Clients) {
-        loop {
-            let msg_try = rx.lock().unwrap().try_recv();
-            if let Ok(msg) = msg_try {
-                Self::send_broadcast(clients.clone(), msg).await;
-            } else {
-                delay_for(Duration::from_secs(1)).await;
+    async fn controller_connected(
+        ws: WebSocket,
+        controllers: Controllers,
+        controller_tx: ControllerSender,
+        controller_queue: usize,
+        dbconfig: Arc<DbConfig>,
+    ) {
+        let (mut client_ws_tx, mut client_ws_rx) = ws.split();
+
+        // the first message must say who we are
+        let authenticated = match client_ws_rx.next().await {
+            Some(Ok(msg)) => Self::authenticate(msg, dbconfig).await,
+            _ => return,
+        };
+        let owner_id = match authenticated {
+            Ok(owner_id) => owner_id,
+            Err(reason) => {
+                let event = ControlEvent::AuthenticationFailed { reason };
+                let _ = Self::send_event(&mut client_ws_tx, &event).await;
+                return;
             }
+        };
+
+        let client_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        info!(controller = client_id, owner_id, "Controller connected");
+        metrics().controllers.inc();
+
+        // events from the server queue up for us; the queue is dropped if we
+        // fall too far behind on it
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Message>(controller_queue);
+        controllers.write().await.insert(client_id, (owner_id, tx));
+        let _ = controller_tx.send(ControllerUpdate::Connected { owner_id });
+
+        let authenticated = ControlEvent::Authenticated { owner_id };
+        let reason = match Self::send_event(&mut client_ws_tx, &authenticated).await {
+            Err(reason) => reason,
+            Ok(()) => loop {
+                tokio::select! {
+                    queued = rx.recv() => match queued {
+                        Some(msg) => {
+                            if let Err(reason) = Self::send_message(&mut client_ws_tx, msg).await {
+                                break reason;
+                            }
+                        }
+                        None => {
+                            break format!("too slow, fell {} events behind", controller_queue)
+                        }
+                    },
+                    incoming = client_ws_rx.next() => match incoming {
+                        Some(Ok(msg)) if msg.is_close() => break "closed".to_string(),
+                        Some(Ok(msg)) => {
+                            let text = match msg.to_str() {
+                                Ok(text) => text,
+                                Err(_) => continue,
+                            };
+                            if let Err(reason) =
+                                Self::handle_control_request(text, owner_id, &controller_tx)
+                            {
+                                let rejected = ControlEvent::Rejected { reason };
+                                if let Err(reason) =
+                                    Self::send_event(&mut client_ws_tx, &rejected).await
+                                {
+                                    break reason;
+                                }
+                            }
+                        }
+                        Some(Err(e)) => break format!("receive error: {}", e),
+                        None => break "closed".to_string(),
+                    },
+                }
+            },
+        };
+
+        info!(controller = client_id, %reason, "Controller disconnected");
+        metrics().controllers.dec();
+        controllers.write().await.remove(&client_id);
+        let _ = controller_tx.send(ControllerUpdate::Disconnected { owner_id });
+    }
+
+    /// async loop that passes events from the server on to the controllers of
+    /// the owner they are for.  A controller whose queue is full is dropped,
+    /// which disconnects it
+    async fn control_loop(mut rx: ControlReceiver, controllers: Controllers) {
+        while let Some(outgoing) = rx.recv().await {
+            let msg = match Self::event_message(&outgoing.event) {
+                Some(msg) => msg,
+                None => continue,
+            };
+            controllers
+                .write()
+                .await
+                .retain(|client_id, (owner_id, tx)| {
+                    if *owner_id != outgoing.owner_id {
+                        return true;
+                    }
+                    match tx.try_send(msg.clone()) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(_)) => {
+                            warn!(
+                                controller = *client_id,
+                                "Controller queue is full, dropping it"
+                            );
+                            false
+                        }
+                        Err(TrySendError::Closed(_)) => false,
+                    }
+                });
         }
     }
 
-    async fn serve(&self) {
-        let client_list = self.clients.clone();
+    async fn serve(&mut self) {
+        let _broadcasts = self.broadcasts.clone();
         let _server_tx = self.server_tx.clone();
-        let clients = warp::any().map(move || client_list.clone());
+        let broadcasts = warp::any().map(move || _broadcasts.clone());
         let server_tx = warp::any().map(move || _server_tx.clone());
 
-        let listen = warp::path("listen")
+        let listen = at_path(&self.endpoints.listen_path)
             .and(warp::ws())
-            .and(clients)
+            .and(warp::query::<HashMap<String, String>>())
+            .and(broadcasts.clone())
             .and(server_tx)
-            .map(|ws: warp::ws::Ws, clients, server_tx| {
-                ws.on_upgrade(move |socket| {
-                    WebsocketServer::listener_connected(socket, clients, server_tx)
-                })
+            .map(
+                |ws: warp::ws::Ws, query, broadcasts, server_tx| -> Box<dyn warp::Reply> {
+                    let parsed = Subscription::from_query(&query)
+                        .and_then(|sub| Ok((sub, Encoding::from_query(&query)?)));
+                    match parsed {
+                        Ok((subscription, encoding)) => Box::new(ws.on_upgrade(move |socket| {
+                            WebsocketServer::listener_connected(
+                                socket,
+                                subscription,
+                                encoding,
+                                broadcasts,
+                                server_tx,
+                            )
+                        })),
+                        Err(reason) => Box::new(warp::reply::with_status(
+                            reason,
+                            warp::http::StatusCode::BAD_REQUEST,
+                        )),
+                    }
+                },
+            );
+
+        let controller_list = self.controllers.clone();
+        let _controller_tx = self.controller_tx.clone();
+        let _dbconfig = self.dbconfig.clone();
+        let controllers = warp::any().map(move || controller_list.clone());
+        let controller_tx = warp::any().map(move || _controller_tx.clone());
+        let controller_queue = self.controller_queue;
+        let dbconfig = warp::any().map(move || _dbconfig.clone());
+
+        let control = at_path(&self.endpoints.control_path)
+            .and(warp::ws())
+            .and(controllers)
+            .and(controller_tx)
+            .and(dbconfig)
+            .map(
+                move |ws: warp::ws::Ws, controllers, controller_tx, dbconfig| -> Box<dyn Reply> {
+                    Box::new(ws.on_upgrade(move |socket| {
+                        WebsocketServer::controller_connected(
+                            socket,
+                            controllers,
+                            controller_tx,
+                            controller_queue,
+                            dbconfig,
+                        )
+                    }))
+                },
+            );
+
+        let metrics_route =
+            at_path(&self.endpoints.metrics_path)
+                .and(warp::get())
+                .map(|| -> Box<dyn Reply> {
+                    Box::new(warp::reply::with_header(
+                        metrics().render(),
+                        "content-type",
+                        "text/plain; version=0.0.4",
+                    ))
+                });
+
+        let health = at_path(&self.endpoints.health_path)
+            .and(warp::get())
+            .and(broadcasts)
+            .map(|broadcasts: BroadcastSender| -> Box<dyn Reply> {
+                Box::new(warp::reply::json(&Health {
+                    status: "ok",
+                    listeners: broadcasts.receiver_count(),
+                }))
             });
 
-        tokio::task::spawn(Self::rebroadcast_loop(
-            self.server_rx.clone(),
-            self.clients.clone(),
-        ));
+        // monitoring is served on every port
+        let health = health.or(metrics_route).unify().boxed();
+
+        let api = api::routes(&self.endpoints.api_path, self.world.clone());
 
-        warp::serve(listen).run(([127, 0, 0, 1], 3820)).await;
+        // administrators connect alongside controllers
+        let control = match admin::routes(
+            &self.endpoints.admin_path,
+            self.endpoints.admin_token.clone(),
+            self.admin_tx.clone(),
+        ) {
+            Some(admin) => control.or(admin).unify().boxed(),
+            None => control.boxed(),
+        };
+
+        if let Some(control_rx) = self.control_rx.take() {
+            tokio::task::spawn(Self::control_loop(control_rx, self.controllers.clone()));
+        }
+
+        let tls = self.endpoints.tls.clone();
+        match self.endpoints.control_addr() {
+            // controllers get their own port, so it can be kept off the proxy
+            Some(control_addr) => {
+                let observers = listen.or(api).unify().or(health.clone()).unify().boxed();
+                let controllers = control.or(health).unify().boxed();
+                future::join(
+                    Self::serve_routes(observers, self.endpoints.addr(), tls.clone()),
+                    Self::serve_routes(controllers, control_addr, tls),
+                )
+                .await;
+            }
+            None => {
+                let routes = listen
+                    .or(control)
+                    .unify()
+                    .or(api)
+                    .unify()
+                    .or(health)
+                    .unify()
+                    .boxed();
+                Self::serve_routes(routes, self.endpoints.addr(), tls).await;
+            }
+        }
+    }
+
+    async fn serve_routes(routes: Routes, addr: SocketAddr, tls: Option<TlsConfig>) {
+        match tls {
+            Some(tls) => {
+                info!("Serving on https://{}", addr);
+                warp::serve(routes)
+                    .tls()
+                    .cert_path(tls.cert)
+                    .key_path(tls.key)
+                    .run(addr)
+                    .await
+            }
+            None => {
+                info!("Serving on http://{}", addr);
+                warp::serve(routes).run(addr).await
+            }
+        }
     }
 
     pub fn run(&mut self) {
@@ -143,3 +551,56 @@ impl WebsocketServer {
         rt.block_on(self.serve());
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_control_loop() {
+    let controllers = Controllers::default();
+    let (control_tx, control_rx) = tokio::sync::mpsc::unbounded_channel::<ControlOutgoing>();
+    let (slow_tx, mut slow_rx) = tokio::sync::mpsc::channel::<Message>(1);
+    let (other_tx, mut other_rx) = tokio::sync::mpsc::channel::<Message>(1);
+
+    let mut rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut list = controllers.write().await;
+        list.insert(1, (1, slow_tx));
+        list.insert(2, (2, other_tx));
+    });
+
+    // the first controller doesn't take anything off its queue
+    for reason in &["first", "second"] {
+        let _ = control_tx.send(ControlOutgoing {
+            owner_id: 1,
+            event: ControlEvent::Rejected {
+                reason: reason.to_string(),
+            },
+        });
+    }
+    drop(control_tx);
+    rt.block_on(WebsocketServer::control_loop(
+        control_rx,
+        controllers.clone(),
+    ));
+
+    // it gets what fit in its queue and is then dropped; others are left alone
+    rt.block_on(async {
+        assert!(slow_rx
+            .recv()
+            .await
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("first"));
+        assert!(slow_rx.recv().await.is_none());
+        assert!(other_rx.try_recv().is_err());
+        assert_eq!(
+            vec![2],
+            controllers
+                .read()
+                .await
+                .keys()
+                .cloned()
+                .collect::<Vec<usize>>()
+        );
+    });
+}
@@ -2,8 +2,10 @@ use diesel::prelude::*;
 use diesel::PgConnection;
 use serde::Serialize;
 use std::collections::HashMap;
+use tracing::debug;
 
 use crate::grid::Coords;
+use crate::metrics;
 use crate::schema::*;
 
 const MAX_AMOUNT: i32 = 5000;
@@ -38,10 +40,12 @@ impl Valuable {
 
         let mut _valuable: Valuable;
         if let Some(conn) = conn {
-            _valuable = diesel::insert_into(valuables::table)
-                .values(new_valuable)
-                .get_result(conn)
-                .expect("Error saving cells");
+            _valuable = metrics::db_query("valuables", || {
+                diesel::insert_into(valuables::table)
+                    .values(new_valuable)
+                    .get_result(conn)
+            })
+            .expect("Error saving cells");
         } else {
             _valuable = Valuable {
                 id: 0,
@@ -78,9 +82,11 @@ impl Valuable {
     /// persist current values to the db
     fn persist_to_db(&mut self, conn: &PgConnection) {
         // update the db
-        let _ = diesel::update(valuables::table.filter(valuables::id.eq(self.id)))
-            .set(valuables::amount.eq(self.amount))
-            .execute(conn);
+        let _ = metrics::db_query("valuables", || {
+            diesel::update(valuables::table.filter(valuables::id.eq(self.id)))
+                .set(valuables::amount.eq(self.amount))
+                .execute(conn)
+        });
     }
 
     /// Increase in value
@@ -116,10 +122,12 @@ impl Valuable {
 
     /// Delete self
     pub fn destroy(&mut self, conn: Option<&PgConnection>) -> bool {
-        println!("Valuable {}: Destroy", self.id);
+        debug!(valuable_id = self.id, "Destroy");
         if conn.is_some() {
-            let _ = diesel::delete(valuables::table.filter(valuables::id.eq(self.id)))
-                .execute(conn.unwrap());
+            let _ = metrics::db_query("valuables", || {
+                diesel::delete(valuables::table.filter(valuables::id.eq(self.id)))
+                    .execute(conn.unwrap())
+            });
         }
 
         true